@@ -0,0 +1,157 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use csv::{ByteRecord, StringRecord};
+use payments_engine::{
+    csv::{csv_transaction::CsvTransaction, parse_options::ParseOptions},
+    domain::{
+        client_account::ClientId,
+        engine::Engine,
+        transaction::{
+            Deposit, Dispute, Transaction, TransactionAction, TransactionId, Withdrawal,
+        },
+    },
+};
+use rust_decimal_macros::dec;
+
+const NUM_TRANSACTIONS: u32 = 100_000;
+const NUM_CLIENTS: u32 = 1000;
+
+/// Spreads transactions across clients in runs of four, so each client sees several
+/// consecutive transactions.
+fn client_for(transaction_id: TransactionId) -> ClientId {
+    (transaction_id / 4 % NUM_CLIENTS) as ClientId
+}
+
+/// Three deposits to a client followed by a small withdrawal.
+fn deposit_heavy_transactions() -> Vec<Transaction> {
+    (0..NUM_TRANSACTIONS)
+        .map(|transaction_id| {
+            let action = if transaction_id % 4 == 3 {
+                TransactionAction::Withdrawal(Withdrawal {
+                    amount: dec!(1.2345),
+                })
+            } else {
+                TransactionAction::Deposit(Deposit {
+                    amount: dec!(123.45),
+                })
+            };
+            Transaction {
+                client_id: client_for(transaction_id),
+                transaction_id,
+                action,
+            }
+        })
+        .collect()
+}
+
+/// Cycles of a deposit which is then disputed and resolved.
+fn dispute_heavy_transactions() -> Vec<Transaction> {
+    (0..NUM_TRANSACTIONS / 3)
+        .flat_map(|transaction_id| {
+            let client_id = client_for(transaction_id * 4);
+            [
+                TransactionAction::Deposit(Deposit {
+                    amount: dec!(123.45),
+                }),
+                TransactionAction::Dispute(Dispute {
+                    amount: None,
+                    target: None,
+                }),
+                TransactionAction::Resolve,
+            ]
+            .map(|action| Transaction {
+                client_id,
+                transaction_id,
+                action,
+            })
+        })
+        .collect()
+}
+
+fn apply_all(transactions: Vec<Transaction>) -> Engine {
+    let mut engine = Engine::default();
+    for transaction in transactions {
+        engine
+            .apply(transaction)
+            .expect("benchmark transactions should apply");
+    }
+    engine
+}
+
+fn bench_deposit_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply");
+    group.throughput(Throughput::Elements(NUM_TRANSACTIONS as u64));
+    group.bench_function("deposit_heavy", |b| {
+        b.iter_batched(deposit_heavy_transactions, apply_all, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn bench_dispute_heavy(c: &mut Criterion) {
+    let transactions = dispute_heavy_transactions().len();
+    let mut group = c.benchmark_group("apply");
+    group.throughput(Throughput::Elements(transactions as u64));
+    group.bench_function("dispute_heavy", |b| {
+        b.iter_batched(dispute_heavy_transactions, apply_all, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+/// Deposit rows as read from a CSV, before they're parsed into transactions.
+fn deposit_records() -> Vec<StringRecord> {
+    (0..NUM_TRANSACTIONS)
+        .map(|transaction_id| {
+            StringRecord::from(vec![
+                "deposit".to_string(),
+                client_for(transaction_id).to_string(),
+                transaction_id.to_string(),
+                "123.4500".to_string(),
+            ])
+        })
+        .collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let options = ParseOptions::default();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(NUM_TRANSACTIONS as u64));
+    group.bench_function("string_record", |b| {
+        b.iter_batched(
+            deposit_records,
+            |records| {
+                for record in records {
+                    let csv_transaction = CsvTransaction::from_string_record(record, &options)
+                        .expect("benchmark records should parse");
+                    black_box(csv_transaction.to_transaction(&options).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("byte_record", |b| {
+        b.iter_batched(
+            || {
+                deposit_records()
+                    .into_iter()
+                    .map(StringRecord::into_byte_record)
+                    .collect::<Vec<ByteRecord>>()
+            },
+            |records| {
+                for mut record in records {
+                    let csv_transaction = CsvTransaction::from_byte_record(&mut record, &options)
+                        .expect("benchmark records should parse");
+                    black_box(csv_transaction.to_transaction(&options).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_deposit_heavy,
+    bench_dispute_heavy,
+    bench_parse
+);
+criterion_main!(benches);
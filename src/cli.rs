@@ -0,0 +1,639 @@
+use crate::{
+    domain::client_account::ClientAccount,
+    options::Options,
+    output::{
+        account_totals::AccountTotals,
+        client_renumbering::renumber_clients,
+        csv_output::{write_accounts, write_accounts_without_header},
+        fixed_width_output::write_accounts_fixed_width,
+        json_output::write_accounts_json,
+        output_options::OutputFormat,
+        split_output::write_split_accounts,
+    },
+    processing::{
+        csv_processor::{process_csv, process_csv_pipelined},
+        processing_stats::ProcessingStats,
+    },
+};
+use anyhow::Result;
+use std::io::{BufWriter, Write};
+
+/// Reads the command line arguments, including the program name, then processes the CSV
+/// they name and writes the accounts to `output`, unless they're written to files.
+pub fn run<W: Write>(args: Vec<String>, output: W) -> Result<()> {
+    execute(&Options::from_args(&args)?, output)
+}
+
+/// Processes the CSV with the given options and writes the accounts, like [`run`].
+pub fn execute<W: Write>(options: &Options, output: W) -> Result<()> {
+    let mut stats = ProcessingStats::default();
+    let process = if options.pipelined {
+        process_csv_pipelined
+    } else {
+        process_csv
+    };
+    let result = process(&options.csv_path, &options.processing, &mut stats);
+
+    if options.print_stats && !options.quiet {
+        eprint!("{}", stats);
+    }
+
+    let mut client_accounts = result?;
+
+    if options.print_totals && !options.quiet {
+        eprint!("{}", AccountTotals::of(&client_accounts)?);
+    }
+
+    if let Some(mapping_path) = &options.output.client_id_mapping_path {
+        renumber_clients(&mut client_accounts, mapping_path, &options.output)?;
+    }
+
+    if let Some(split) = &options.output.split {
+        return write_split_accounts(client_accounts, split, &options.output);
+    }
+    if let Some(file) = &options.output.file {
+        let (file, has_content) = file.open()?;
+        return write_formatted(BufWriter::new(file), client_accounts, options, !has_content);
+    }
+
+    write_formatted(output, client_accounts, options, true)
+}
+
+fn write_formatted<W: Write>(
+    output: W,
+    client_accounts: Vec<ClientAccount>,
+    options: &Options,
+    include_header: bool,
+) -> Result<()> {
+    match options.output.format {
+        OutputFormat::Csv if include_header => {
+            write_accounts(output, client_accounts, &options.output)
+        }
+        OutputFormat::Csv => {
+            write_accounts_without_header(output, client_accounts, &options.output)
+        }
+        OutputFormat::Json => write_accounts_json(output, client_accounts, &options.output),
+        OutputFormat::Fixed => write_accounts_fixed_width(output, client_accounts, &options.output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::{
+        options::{command, Options},
+        test_fixture::{write_fixture, write_zstd_fixture},
+    };
+    use anyhow::Result;
+    use std::{collections::HashSet, env, fs, process};
+
+    const TRANSACTIONS: &str = "type,client,tx,amount\n\
+                                deposit,1,1,10\n\
+                                deposit,2,2,5\n\
+                                withdrawal,1,3,3\n";
+
+    fn args(csv_path: &str, flags: &[&str]) -> Vec<String> {
+        ["payments-engine", csv_path]
+            .iter()
+            .chain(flags)
+            .map(|arg| arg.to_string())
+            .collect()
+    }
+
+    fn run_to_string(args: Vec<String>) -> Result<String> {
+        let mut output = Vec::new();
+        run(args, &mut output)?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    /// What a flag visibly changes about a run.
+    enum Effect<'a> {
+        /// The accounts written include this.
+        Writes(&'a str),
+        /// The accounts written leave this out, though they'd include it without the flag.
+        Omits(&'a str),
+        /// The file at the path includes this after the run.
+        WritesFile(String, &'a str),
+        /// The run fails with an error including this.
+        Fails(&'a str),
+        /// The flag only changes what's written to stderr or how the run works, not what
+        /// it writes, so the option it sets is checked instead.
+        Sets(fn(&Options) -> bool),
+    }
+
+    #[test]
+    fn runs_with_each_flag() -> Result<()> {
+        let config_path = write_fixture("cli-config", r#"{ "output": { "precision": 2 } }"#)?;
+        let errors_path = write_fixture("cli-errors", "")?;
+        let ledger_path = write_fixture("cli-ledger", "")?;
+        let mapping_path = write_fixture("cli-client-id-mapping", "")?;
+        let applied_ids_path = write_fixture("cli-applied-ids", "")?;
+        let opening_balances_path = write_fixture(
+            "cli-opening-balances",
+            "client,available,held,total,locked\n3,1,0,1,false\n",
+        )?;
+        let yes_no_opening_balances_path = write_fixture(
+            "cli-yes-no-opening-balances",
+            "client,available,held,total,locked\n3,1,0,1,yes\n",
+        )?;
+        let output_path = write_fixture("cli-output", "")?;
+        let split_prefix = env::temp_dir()
+            .join(format!("payments-engine-{}-cli-split", process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let with_rows = |rows: &str| format!("{}{}", TRANSACTIONS, rows);
+
+        use Effect::*;
+        let cases: Vec<(Vec<&str>, String, Effect)> = vec![
+            (
+                vec!["--config", &config_path],
+                with_rows(""),
+                Writes("1,7.00,0.00,7.00,false"),
+            ),
+            (
+                vec!["--stats"],
+                with_rows(""),
+                Sets(|options| options.print_stats),
+            ),
+            (
+                vec!["--totals"],
+                with_rows(""),
+                Sets(|options| options.print_totals),
+            ),
+            (
+                vec!["--quiet"],
+                with_rows(""),
+                Sets(|options| options.quiet),
+            ),
+            (
+                vec!["--pipelined"],
+                with_rows(""),
+                Sets(|options| options.pipelined),
+            ),
+            (
+                vec!["--allow-zero"],
+                with_rows("deposit,1,4,0\n"),
+                Writes("1,7.0000,0.0000,7.0000,false"),
+            ),
+            (
+                vec!["--check"],
+                with_rows(""),
+                Sets(|options| options.processing.check_held_balances),
+            ),
+            (
+                vec!["--reconcile"],
+                with_rows(""),
+                Sets(|options| options.processing.reconcile_held_balances),
+            ),
+            (
+                vec!["--sanitize-amounts"],
+                with_rows("deposit,3,4,\"$1,000.50\"\n"),
+                Writes("3,1000.5000,0.0000,1000.5000,false"),
+            ),
+            (
+                vec!["--tolerant-whitespace-type"],
+                with_rows("dep osit,3,4,2\n"),
+                Writes("3,2.0000,0.0000,2.0000,false"),
+            ),
+            (
+                vec!["--errors", &errors_path],
+                with_rows("deposit,3,4,abc\n"),
+                WritesFile(
+                    errors_path.clone(),
+                    "5,4,Invalid amount 'abc' for transaction 4",
+                ),
+            ),
+            (
+                vec!["--ledger", &ledger_path],
+                with_rows(""),
+                WritesFile(ledger_path.clone(), "1,3,withdrawal,7.0000,0.0000,7.0000"),
+            ),
+            (
+                vec!["--zstd"],
+                with_rows(""),
+                Writes("1,7.0000,0.0000,7.0000,false"),
+            ),
+            (
+                vec!["--skip-unknown"],
+                with_rows("refund,1,4,1\n"),
+                Writes("1,7.0000,0.0000,7.0000,false"),
+            ),
+            (
+                vec!["--skip-repeated-headers"],
+                with_rows("type,client,tx,amount\n"),
+                Writes("1,7.0000,0.0000,7.0000,false"),
+            ),
+            (
+                vec!["--allow-reset"],
+                with_rows("reset,1,4,\n"),
+                Writes("1,0.0000,0.0000,0.0000,false"),
+            ),
+            (
+                vec!["--allow-admin"],
+                with_rows("freeze,1,4,\n"),
+                Writes("1,7.0000,0.0000,7.0000,true"),
+            ),
+            (vec!["--normalize"], with_rows(""), Writes("1,7,0,7,false")),
+            (
+                vec!["--explicit-sign"],
+                with_rows(""),
+                Writes("1,+7.0000,0.0000,+7.0000,false"),
+            ),
+            (
+                vec!["--amount-scale", "2", "--amount-rounding", "truncate"],
+                with_rows("deposit,3,4,1.239\n"),
+                Writes("3,1.2300,0.0000,1.2300,false"),
+            ),
+            (
+                vec!["--warn-excess-precision"],
+                with_rows("deposit,3,4,1.23456\n"),
+                Writes("3,1.2346,0.0000,1.2346,false"),
+            ),
+            (
+                vec!["--withdrawal-fee", "1"],
+                with_rows(""),
+                Writes("1,6.0000,0.0000,6.0000,false"),
+            ),
+            (
+                vec!["--precision", "2"],
+                with_rows(""),
+                Writes("1,7.00,0.00,7.00,false"),
+            ),
+            (
+                vec!["--rounding", "half-even"],
+                with_rows("deposit,3,4,1.23455\n"),
+                Writes("3,1.2346,0.0000,1.2346,false"),
+            ),
+            (
+                vec!["--balanced-rounding"],
+                with_rows("deposit,3,4,1.00005\ndeposit,3,5,1.00005\ndispute,3,5,\n"),
+                Writes("3,1.0000,1.0001,2.0001,false"),
+            ),
+            (
+                vec!["--format", "json"],
+                with_rows(""),
+                Writes(r#"{"client":1,"available":"7.0000","#),
+            ),
+            (
+                vec!["--format", "json", "--json-numbers"],
+                with_rows(""),
+                Writes(r#"{"client":1,"available":7.0000,"#),
+            ),
+            (
+                vec!["--format", "fixed"],
+                with_rows(""),
+                Writes("    1              7.0000              0.0000"),
+            ),
+            (
+                vec!["--split", "2", "--output-prefix", &split_prefix],
+                with_rows(""),
+                WritesFile(
+                    format!("{}_1.csv", split_prefix),
+                    "1,7.0000,0.0000,7.0000,false",
+                ),
+            ),
+            (
+                vec!["--output", &output_path, "--output-mode", "overwrite"],
+                with_rows(""),
+                WritesFile(output_path.clone(), "1,7.0000,0.0000,7.0000,false"),
+            ),
+            (
+                vec!["--sort-by", "total", "--desc"],
+                with_rows(""),
+                Writes("1,7.0000,0.0000,7.0000,false\n2,5.0000,0.0000,5.0000,false\n"),
+            ),
+            (
+                vec!["--gross-deposits"],
+                with_rows(""),
+                Writes("1,7.0000,0.0000,7.0000,false,10.0000"),
+            ),
+            (vec!["--locked-only"], with_rows(""), Omits("1,7.0000")),
+            (
+                vec!["--held-ratio-threshold", "0.5"],
+                with_rows(""),
+                Omits("1,7.0000"),
+            ),
+            (
+                vec!["--max-clients", "1"],
+                with_rows(""),
+                Fails("Too many distinct clients: the maximum is 1"),
+            ),
+            (
+                vec!["--flush-every", "1"],
+                with_rows(""),
+                Sets(|options| options.output.flush_every == Some(1)),
+            ),
+            (
+                vec!["--normalize-client-ids", &mapping_path],
+                with_rows(""),
+                Writes("0,7.0000,0.0000,7.0000,false"),
+            ),
+            (
+                vec!["--block-clients", "2"],
+                with_rows(""),
+                Omits("2,5.0000"),
+            ),
+            (
+                vec!["--roster", "3"],
+                with_rows(""),
+                Writes("3,0.0000,0.0000,0.0000,false"),
+            ),
+            (
+                vec!["--input-scale", "2"],
+                with_rows(""),
+                Writes("1,0.0700,0.0000,0.0700,false"),
+            ),
+            (
+                vec!["--string-client-ids"],
+                "type,client,tx,amount\ndeposit,acme,1,10\n".to_string(),
+                Writes("acme,10.0000,0.0000,10.0000,false"),
+            ),
+            (
+                vec!["--strict-dispute-flow"],
+                with_rows("resolve,1,1,\n"),
+                Fails("Resolve references a non-disputed transaction"),
+            ),
+            (
+                vec!["--strict-transaction-ids"],
+                with_rows("deposit,1,1,10\n"),
+                Fails("Duplicate transaction id"),
+            ),
+            (
+                vec!["--dedupe-window", "1"],
+                with_rows("deposit,1,1,10\n"),
+                Writes("1,17.0000,0.0000,17.0000,false"),
+            ),
+            (
+                vec!["--route-disputes"],
+                with_rows("dispute,2,1,\n"),
+                Writes("1,-3.0000,10.0000,7.0000,false"),
+            ),
+            (
+                vec!["--resolve-keeps-held"],
+                with_rows("dispute,1,1,\nresolve,1,1,\n"),
+                Writes("1,-3.0000,10.0000,7.0000,false"),
+            ),
+            (
+                vec!["--hold-withdrawals"],
+                with_rows(""),
+                Writes("1,7.0000,3.0000,10.0000,false"),
+            ),
+            (
+                vec!["--dispute-withdrawals"],
+                "type,client,tx,amount,target\n\
+                 deposit,1,1,10,\n\
+                 withdrawal,1,3,3,\n\
+                 dispute,1,3,,withdrawal\n"
+                    .to_string(),
+                Writes("1,7.0000,3.0000,10.0000,false"),
+            ),
+            (
+                vec!["--ignore-held-overflow"],
+                with_rows(""),
+                Sets(|options| options.processing.account_policy.ignore_held_overflow),
+            ),
+            (
+                vec!["--applied-ids", &applied_ids_path],
+                with_rows(""),
+                WritesFile(applied_ids_path.clone(), "3"),
+            ),
+            (
+                vec!["--since-tx", "1"],
+                "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,5\n".to_string(),
+                Writes("1,5.0000,0.0000,5.0000,false"),
+            ),
+            (
+                vec!["--opening-balances", &opening_balances_path],
+                with_rows(""),
+                Writes("3,1.0000,0.0000,1.0000,false"),
+            ),
+            (
+                vec!["--start-locked", "9"],
+                with_rows(""),
+                Writes("9,0.0000,0.0000,0.0000,true"),
+            ),
+            (
+                vec!["--start-locked", "1", "--seeded-lock-policy", "skip"],
+                with_rows(""),
+                Writes("1,0.0000,0.0000,0.0000,true"),
+            ),
+            (
+                vec![
+                    "--opening-balances",
+                    &yes_no_opening_balances_path,
+                    "--tolerant-bool-locked",
+                ],
+                with_rows(""),
+                Writes("3,1.0000,0.0000,1.0000,true"),
+            ),
+            (
+                vec!["--no-negative-available"],
+                with_rows("withdrawal,1,4,5\ndispute,1,1,\n"),
+                Fails("Dispute would overdraw available balance"),
+            ),
+            (
+                vec!["--fail-on-lock"],
+                with_rows("dispute,2,2,\nchargeback,2,2,\n"),
+                Fails("Stopping because the account for client 2 was locked by transaction ID 2"),
+            ),
+        ];
+
+        let covered: HashSet<&str> = cases
+            .iter()
+            .flat_map(|(flags, _, _)| flags)
+            .filter_map(|arg| arg.strip_prefix("--"))
+            .collect();
+        for arg in command().get_arguments() {
+            if let Some(long) = arg.get_long() {
+                assert!(covered.contains(long), "--{} has no test case", long);
+            }
+        }
+
+        for (index, (flags, input, effect)) in cases.into_iter().enumerate() {
+            let csv_path = if flags.contains(&"--zstd") {
+                // Without the .zst extension, only the flag says the input is compressed
+                let zstd_path = write_zstd_fixture("cli-zstd-transactions", &input)?;
+                let csv_path = zstd_path.trim_end_matches(".zst").to_string();
+                fs::copy(&zstd_path, &csv_path)?;
+                csv_path
+            } else {
+                write_fixture(&format!("cli-transactions-{}", index), &input)?
+            };
+            let args = args(&csv_path, &flags);
+
+            let result = match effect {
+                Sets(is_set) => Options::from_args(&args).map(|options| is_set(&options)),
+                Fails(expected) => {
+                    Ok(run_to_string(args)
+                        .is_err_and(|err| format!("{:#}", err).contains(expected)))
+                }
+                Writes(expected) => run_to_string(args).map(|output| output.contains(expected)),
+                Omits(unexpected) => run_to_string(args).map(|output| !output.contains(unexpected)),
+                WritesFile(path, expected) => run_to_string(args)
+                    .and_then(|_| Ok(fs::read_to_string(path)?.contains(expected))),
+            };
+            let has_effect =
+                result.map_err(|err| err.context(format!("Failed to run with {:?}", flags)))?;
+            assert!(has_effect, "{:?} had no visible effect", flags);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writes_accounts_for_bare_and_process_invocations() -> Result<()> {
+        let csv_path = write_fixture("cli-subcommand", TRANSACTIONS)?;
+
+        let bare = run_to_string(args(&csv_path, &["--sort-by", "client"]))?;
+        let process = run_to_string(vec![
+            "payments-engine".to_string(),
+            "process".to_string(),
+            csv_path,
+            "--sort-by".to_string(),
+            "client".to_string(),
+        ])?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,7.0000,0.0000,7.0000,false\n\
+             2,5.0000,0.0000,5.0000,false\n",
+            bare
+        );
+        assert_eq!(bare, process);
+        Ok(())
+    }
+
+    #[test]
+    fn overwrites_an_existing_output_file() -> Result<()> {
+        let csv_path = write_fixture("cli-overwrite-input", TRANSACTIONS)?;
+        let output_path = write_fixture("cli-overwrite-output", "stale\n")?;
+
+        let stdout = run_to_string(args(
+            &csv_path,
+            &["--output", &output_path, "--sort-by", "client"],
+        ))?;
+
+        assert_eq!("", stdout);
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,7.0000,0.0000,7.0000,false\n\
+             2,5.0000,0.0000,5.0000,false\n",
+            fs::read_to_string(&output_path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn appends_to_an_output_file_without_repeating_the_header() -> Result<()> {
+        let csv_path = write_fixture("cli-append-input", TRANSACTIONS)?;
+        let output_path = write_fixture(
+            "cli-append-output",
+            "client,available,held,total,locked\n\
+             9,1.0000,0.0000,1.0000,false\n",
+        )?;
+
+        run_to_string(args(
+            &csv_path,
+            &[
+                "--output",
+                &output_path,
+                "--output-mode",
+                "append",
+                "--sort-by",
+                "client",
+            ],
+        ))?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             9,1.0000,0.0000,1.0000,false\n\
+             1,7.0000,0.0000,7.0000,false\n\
+             2,5.0000,0.0000,5.0000,false\n",
+            fs::read_to_string(&output_path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn appends_a_header_to_an_empty_output_file() -> Result<()> {
+        let csv_path = write_fixture("cli-append-empty-input", TRANSACTIONS)?;
+        let output_path = write_fixture("cli-append-empty-output", "")?;
+
+        run_to_string(args(
+            &csv_path,
+            &[
+                "--output",
+                &output_path,
+                "--output-mode",
+                "append",
+                "--locked-only",
+            ],
+        ))?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n",
+            fs::read_to_string(&output_path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_write_an_existing_output_file_in_fail_mode() -> Result<()> {
+        let csv_path = write_fixture("cli-fail-input", TRANSACTIONS)?;
+        let output_path = write_fixture("cli-fail-output", "earlier run\n")?;
+
+        let result = run_to_string(args(
+            &csv_path,
+            &["--output", &output_path, "--output-mode", "fail"],
+        ));
+
+        assert_eq!(
+            Some(format!(
+                "Output file at path {} already exists",
+                output_path
+            )),
+            result.err().map(|err| err.to_string())
+        );
+        assert_eq!("earlier run\n", fs::read_to_string(&output_path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn writes_string_client_ids_back_out() -> Result<()> {
+        let csv_path = write_fixture(
+            "cli-string-client-ids",
+            "type,client,tx,amount\n\
+             deposit,acme,1,10\n\
+             deposit,globex,2,5\n\
+             withdrawal,acme,3,3\n",
+        )?;
+
+        let csv = run_to_string(args(
+            &csv_path,
+            &["--string-client-ids", "--sort-by", "client"],
+        ))?;
+        let json = run_to_string(args(
+            &csv_path,
+            &["--string-client-ids", "--format", "json", "--normalize"],
+        ))?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             acme,7.0000,0.0000,7.0000,false\n\
+             globex,5.0000,0.0000,5.0000,false\n",
+            csv
+        );
+        assert!(json.contains("{\"client\":\"acme\",\"available\":\"7\","));
+        assert!(json.contains("{\"client\":\"globex\",\"available\":\"5\","));
+        Ok(())
+    }
+
+    #[test]
+    fn writes_accounts_in_the_chosen_format() -> Result<()> {
+        let csv_path = write_fixture("cli-format", TRANSACTIONS)?;
+
+        let output = run_to_string(args(&csv_path, &["--format", "json", "--locked-only"]))?;
+
+        assert_eq!("[]\n", output);
+        Ok(())
+    }
+}
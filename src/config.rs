@@ -0,0 +1,207 @@
+use crate::{
+    domain::{account_policy::AccountPolicy, client_account::ClientId},
+    output::output_options::{OutputFormat, OutputOptions, Rounding, SortKey},
+    processing::processing_options::ProcessingOptions,
+};
+use anyhow::{Error, Result};
+use serde::Deserialize;
+use std::{collections::HashSet, fs};
+
+/// Settings read from a JSON file with `--config`, as an alternative to passing each one
+/// as a flag. Anything left out keeps its default, and flags override the file.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// The policy every client account is created with, including the scale amounts are
+    /// rounded to.
+    pub policy: AccountPolicy,
+    /// Treat amounts as whole numbers of minor units, as `--input-scale`.
+    pub input_scale: Option<u32>,
+    pub check_held_balances: bool,
+    pub skip_unknown_types: bool,
+    pub blocked_clients: HashSet<ClientId>,
+    pub max_clients: Option<usize>,
+    /// How the accounts are written.
+    pub output: OutputConfig,
+}
+
+/// The output settings of an [`EngineConfig`], each matching the flag of the same name.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub format: OutputFormat,
+    /// How many decimal places balances are written with.
+    pub precision: Option<u32>,
+    pub rounding: Option<Rounding>,
+    pub normalize: bool,
+    pub explicit_sign: bool,
+    pub sort_by: Option<SortKey>,
+    pub desc: bool,
+}
+
+impl EngineConfig {
+    pub fn load(path: &str) -> Result<EngineConfig> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            Error::msg(format!(
+                "Failed to read config file at path {}: {}",
+                path, err
+            ))
+        })?;
+        serde_json::from_str(&contents)
+            .map_err(|err| Error::msg(format!("Invalid config file {}: {}", path, err)))
+    }
+    /// Builds the output options this config describes.
+    pub fn output_options(&self) -> OutputOptions {
+        let output = &self.output;
+        OutputOptions {
+            format: output.format,
+            precision: output.precision,
+            rounding: output.rounding,
+            normalize_balances: output.normalize,
+            explicit_sign: output.explicit_sign,
+            sort_by: output.sort_by,
+            descending: output.desc,
+            ..Default::default()
+        }
+    }
+    /// Builds the processing options this config describes.
+    pub fn processing_options(self) -> ProcessingOptions {
+        let mut processing = ProcessingOptions {
+            account_policy: self.policy,
+            check_held_balances: self.check_held_balances,
+            skip_unknown_types: self.skip_unknown_types,
+            blocked_clients: self.blocked_clients,
+            max_clients: self.max_clients,
+            ..Default::default()
+        };
+        processing.parse.input_scale = self.input_scale;
+        processing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EngineConfig;
+    use crate::{
+        domain::account_policy::{AmountRounding, WithdrawalFee},
+        options::Options,
+        output::csv_output::write_accounts,
+        processing::{csv_processor::process_csv, processing_stats::ProcessingStats},
+        test_fixture::write_fixture,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn applies_the_config_file_during_processing() -> Result<()> {
+        let config_path = write_fixture(
+            "config",
+            r#"{
+                "policy": {
+                    "amount_scale": 2,
+                    "amount_rounding": "half-even",
+                    "withdrawal_fee": { "flat": "0.5" }
+                }
+            }"#,
+        )?;
+        let csv_path = write_fixture(
+            "config-transactions",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.125\n\
+             withdrawal,1,2,2.0\n",
+        )?;
+
+        let options = Options::from_args(&[
+            "payments-engine".to_string(),
+            csv_path,
+            "--config".to_string(),
+            config_path,
+        ])?;
+        assert_eq!(Some(2), options.processing.account_policy.amount_scale);
+        assert_eq!(
+            AmountRounding::HalfEven,
+            options.processing.account_policy.amount_rounding
+        );
+
+        let mut stats = ProcessingStats::default();
+        let accounts = process_csv(&options.csv_path, &options.processing, &mut stats)?;
+
+        // 10.125 rounds half-even to 10.12, less the withdrawal and its fee
+        assert_eq!(dec!(7.62), accounts[0].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn flags_override_the_config_file() -> Result<()> {
+        let config_path = write_fixture(
+            "config-overridden",
+            r#"{ "policy": { "withdrawal_fee": { "flat": "0.5" } }, "input_scale": 2 }"#,
+        )?;
+
+        let options = Options::from_args(&[
+            "payments-engine".to_string(),
+            "--withdrawal-fee".to_string(),
+            "1%".to_string(),
+            "--config".to_string(),
+            config_path,
+            "tx.csv".to_string(),
+        ])?;
+
+        assert_eq!(
+            WithdrawalFee::Percentage(dec!(1)),
+            options.processing.account_policy.withdrawal_fee
+        );
+        assert_eq!(Some(2), options.processing.parse.input_scale);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_the_configured_precision_and_policy() -> Result<()> {
+        let config_path = write_fixture(
+            "config-precision",
+            r#"{
+                "policy": { "withdrawal_fee": { "flat": "0.5" } },
+                "output": { "precision": 2, "rounding": "half-up" }
+            }"#,
+        )?;
+        let csv_path = write_fixture(
+            "config-precision-transactions",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.125\n\
+             withdrawal,1,2,2.0\n",
+        )?;
+
+        let options = Options::from_args(&[
+            "payments-engine".to_string(),
+            csv_path,
+            "--config".to_string(),
+            config_path,
+        ])?;
+        assert_eq!(Some(2), options.output.precision);
+
+        let mut stats = ProcessingStats::default();
+        let accounts = process_csv(&options.csv_path, &options.processing, &mut stats)?;
+        let mut output = Vec::new();
+        write_accounts(&mut output, accounts, &options.output)?;
+
+        // 7.625 after the withdrawal and its fee, rounded half-up to 2 decimal places
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,7.63,0.00,7.63,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_load_a_config_with_unknown_settings() -> Result<()> {
+        let config_path = write_fixture("config-unknown", r#"{ "decimal_places": 2 }"#)?;
+
+        let err = EngineConfig::load(&config_path).unwrap_err().to_string();
+        assert!(err.starts_with(&format!(
+            "Invalid config file {}: unknown field `decimal_places`",
+            config_path
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use crate::domain::client_account::ClientId;
+use anyhow::{Error, Result};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Maps string client identifiers, as some partners use, to the numeric client IDs the
+/// engine works with. IDs are handed out in the order labels are first seen, and the
+/// labels are kept so the output can show them. Clones share the table, so rows parsed
+/// on another thread are interned into the same one.
+#[derive(Debug, Default, Clone)]
+pub struct ClientLabels(Arc<Mutex<LabelTable>>);
+
+#[derive(Debug, Default)]
+struct LabelTable {
+    client_ids: HashMap<String, ClientId>,
+    labels: Vec<String>,
+}
+
+impl ClientLabels {
+    /// The client ID for `label`, assigning the next unused one if it hasn't been seen.
+    pub fn intern(&self, label: &str) -> Result<ClientId> {
+        let mut table = self.lock()?;
+        if let Some(&client_id) = table.client_ids.get(label) {
+            return Ok(client_id);
+        }
+
+        let client_id = ClientId::try_from(table.labels.len()).map_err(|_| {
+            Error::msg(format!(
+                "Too many distinct client labels: at most {} are supported",
+                usize::from(ClientId::MAX) + 1
+            ))
+        })?;
+        table.client_ids.insert(label.to_string(), client_id);
+        table.labels.push(label.to_string());
+        Ok(client_id)
+    }
+    /// The label the client ID was assigned to, if any.
+    pub fn label(&self, client_id: ClientId) -> Result<Option<String>> {
+        Ok(self.lock()?.labels.get(usize::from(client_id)).cloned())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, LabelTable>> {
+        self.0
+            .lock()
+            .map_err(|_| Error::msg("Client labels are unusable after a panic while locked"))
+    }
+}
+
+/// Labels are equal when they're clones of each other, so options holding them can still
+/// be compared.
+impl PartialEq for ClientLabels {
+    fn eq(&self, other: &ClientLabels) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ClientLabels {}
@@ -0,0 +1,319 @@
+use anyhow::Result;
+use csv::Writer;
+use rust_decimal::Decimal;
+use std::io::Write;
+
+/// How many clients a generated fixture spreads its transactions across.
+const NUM_CLIENTS: u16 = 1000;
+
+/// How many of each client's most recent deposits remain candidates for disputes.
+/// Bounding this keeps memory flat however many events are generated.
+const DISPUTABLE_DEPOSITS_PER_CLIENT: usize = 16;
+
+/// The largest deposit generated, in ten-thousandths.
+const MAX_DEPOSIT: u64 = 10_000_000;
+
+/// The relative weight of each transaction type in a generated fixture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixtureMix {
+    pub deposits: u32,
+    pub withdrawals: u32,
+    pub disputes: u32,
+    pub resolves: u32,
+    pub chargebacks: u32,
+}
+
+impl Default for FixtureMix {
+    fn default() -> Self {
+        FixtureMix {
+            deposits: 50,
+            withdrawals: 25,
+            disputes: 12,
+            resolves: 12,
+            chargebacks: 1,
+        }
+    }
+}
+
+/// The balances a client should end with once a generated fixture has been processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixtureBalance {
+    pub client_id: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+}
+
+/// Writes a CSV of `num_events` transactions using the default [`FixtureMix`], returning
+/// the expected balances. The same `seed` always produces the same bytes.
+pub fn generate_fixture<W: Write>(
+    writer: W,
+    num_events: u64,
+    seed: u64,
+) -> Result<Vec<FixtureBalance>> {
+    generate_fixture_with_mix(writer, num_events, seed, FixtureMix::default())
+}
+
+/// Writes a CSV of `num_events` transactions, choosing each type according to `mix`.
+/// Every generated transaction can be applied without error: withdrawals never exceed
+/// the available balance, disputes only refer to earlier deposits and locked clients
+/// receive no further transactions. When the chosen type isn't possible for the chosen
+/// client, a deposit is written instead. Returns the expected balances of every client
+/// that was written to, in client order.
+pub fn generate_fixture_with_mix<W: Write>(
+    writer: W,
+    num_events: u64,
+    seed: u64,
+    mix: FixtureMix,
+) -> Result<Vec<FixtureBalance>> {
+    let mut writer = Writer::from_writer(writer);
+    let mut rng = FixtureRng::new(seed);
+    let mut clients: Vec<FixtureClient> = (1..=NUM_CLIENTS).map(FixtureClient::new).collect();
+    let mut locked_clients = Vec::new();
+    let total_weight =
+        (mix.deposits + mix.withdrawals + mix.disputes + mix.resolves + mix.chargebacks).max(1);
+
+    writer.write_record(["type", "client", "tx", "amount"])?;
+    for transaction_id in 1..=num_events {
+        let client_index = rng.below(clients.len() as u64) as usize;
+        let client = &mut clients[client_index];
+        client.written = true;
+        let client_id = client.client_id.to_string();
+        let mut roll = rng.below(total_weight as u64) as u32;
+
+        if roll < mix.deposits {
+            write_deposit(&mut writer, &mut rng, client, transaction_id)?;
+            continue;
+        }
+        roll -= mix.deposits;
+
+        if roll < mix.withdrawals {
+            if client.available <= 0 {
+                write_deposit(&mut writer, &mut rng, client, transaction_id)?;
+                continue;
+            }
+            let amount = 1 + rng.below(client.available as u64) as i64;
+            client.available -= amount;
+            writer.write_record([
+                "withdrawal",
+                &client_id,
+                &transaction_id.to_string(),
+                &format_amount(amount),
+            ])?;
+            continue;
+        }
+        roll -= mix.withdrawals;
+
+        if roll < mix.disputes {
+            if client.undisputed.is_empty() {
+                write_deposit(&mut writer, &mut rng, client, transaction_id)?;
+                continue;
+            }
+            let index = rng.below(client.undisputed.len() as u64) as usize;
+            let (disputed_id, amount) = client.undisputed.swap_remove(index);
+            client.available -= amount;
+            client.disputed.push((disputed_id, amount));
+            writer.write_record(["dispute", &client_id, &disputed_id.to_string(), ""])?;
+            continue;
+        }
+        roll -= mix.disputes;
+
+        // A chargeback locks the client, so always leave at least one to write to
+        let chargeback = roll >= mix.resolves && clients.len() > 1;
+        let client = &mut clients[client_index];
+        if client.disputed.is_empty() {
+            write_deposit(&mut writer, &mut rng, client, transaction_id)?;
+            continue;
+        }
+        let index = rng.below(client.disputed.len() as u64) as usize;
+        let (disputed_id, amount) = client.disputed.swap_remove(index);
+
+        if chargeback {
+            writer.write_record(["chargeback", &client_id, &disputed_id.to_string(), ""])?;
+            locked_clients.push(clients.swap_remove(client_index));
+        } else {
+            client.available += amount;
+            client.remember_deposit(disputed_id, amount);
+            writer.write_record(["resolve", &client_id, &disputed_id.to_string(), ""])?;
+        }
+    }
+
+    writer.flush()?;
+
+    let mut balances: Vec<FixtureBalance> = clients
+        .iter()
+        .filter(|client| client.written)
+        .map(|client| client.balance(false))
+        .chain(locked_clients.iter().map(|client| client.balance(true)))
+        .collect();
+    balances.sort_by_key(|balance| balance.client_id);
+
+    Ok(balances)
+}
+
+fn write_deposit<W: Write>(
+    writer: &mut Writer<W>,
+    rng: &mut FixtureRng,
+    client: &mut FixtureClient,
+    transaction_id: u64,
+) -> Result<()> {
+    let amount = 1 + rng.below(MAX_DEPOSIT) as i64;
+    client.available += amount;
+    client.remember_deposit(transaction_id, amount);
+    writer.write_record([
+        "deposit",
+        &client.client_id.to_string(),
+        &transaction_id.to_string(),
+        &format_amount(amount),
+    ])?;
+    Ok(())
+}
+
+fn format_amount(amount: i64) -> String {
+    Decimal::new(amount, 4).to_string()
+}
+
+/// What the generator needs to know about a client to keep its transactions valid.
+/// Amounts are in ten-thousandths.
+struct FixtureClient {
+    client_id: u16,
+    written: bool,
+    available: i64,
+    undisputed: Vec<(u64, i64)>,
+    disputed: Vec<(u64, i64)>,
+}
+
+impl FixtureClient {
+    fn new(client_id: u16) -> FixtureClient {
+        FixtureClient {
+            client_id,
+            written: false,
+            available: 0,
+            undisputed: Vec::new(),
+            disputed: Vec::new(),
+        }
+    }
+    fn remember_deposit(&mut self, transaction_id: u64, amount: i64) {
+        if self.undisputed.len() == DISPUTABLE_DEPOSITS_PER_CLIENT {
+            self.undisputed.remove(0);
+        }
+        self.undisputed.push((transaction_id, amount));
+    }
+    fn balance(&self, locked: bool) -> FixtureBalance {
+        let held = self.disputed.iter().map(|(_, amount)| amount).sum();
+        FixtureBalance {
+            client_id: self.client_id,
+            available: Decimal::new(self.available, 4),
+            held: Decimal::new(held, 4),
+            locked,
+        }
+    }
+}
+
+/// A SplitMix64 generator. It's kept here rather than taken from a crate so that
+/// fixtures stay byte-identical for a given seed regardless of dependency upgrades.
+struct FixtureRng {
+    state: u64,
+}
+
+impl FixtureRng {
+    fn new(seed: u64) -> FixtureRng {
+        FixtureRng { state: seed }
+    }
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Returns a value in `0..bound`. The slight modulo bias doesn't matter for fixtures.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_fixture, generate_fixture_with_mix, FixtureBalance, FixtureMix};
+    use crate::{
+        processing::{
+            csv_processor::process_csv, processing_options::ProcessingOptions,
+            processing_stats::ProcessingStats,
+        },
+        test_fixture::write_fixture,
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn generates_identical_fixtures_from_the_same_seed() -> Result<()> {
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        let mut other_seed = Vec::new();
+
+        generate_fixture(&mut first, 1000, 42)?;
+        generate_fixture(&mut second, 1000, 42)?;
+        generate_fixture(&mut other_seed, 1000, 43)?;
+
+        assert_eq!(first, second);
+        assert_ne!(first, other_seed);
+        Ok(())
+    }
+
+    #[test]
+    fn generates_a_fixture_that_processes_without_error() -> Result<()> {
+        let mut contents = Vec::new();
+        let mix = FixtureMix {
+            chargebacks: 10,
+            ..Default::default()
+        };
+        generate_fixture_with_mix(&mut contents, 10_000, 7, mix)?;
+        let csv_path = write_fixture("generated", &String::from_utf8(contents)?)?;
+
+        let mut stats = ProcessingStats::default();
+        process_csv(&csv_path, &ProcessingOptions::default(), &mut stats)?;
+
+        assert_eq!(
+            10_000,
+            stats.deposits
+                + stats.withdrawals
+                + stats.disputes
+                + stats.resolves
+                + stats.chargebacks
+        );
+        assert!(stats.chargebacks > 0);
+        assert_eq!(0, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn generates_a_fixture_with_the_expected_balances() -> Result<()> {
+        let mut contents = Vec::new();
+        let mix = FixtureMix {
+            chargebacks: 10,
+            ..Default::default()
+        };
+        let expected = generate_fixture_with_mix(&mut contents, 10_000, 11, mix)?;
+        let csv_path = write_fixture("generated-balances", &String::from_utf8(contents)?)?;
+
+        let mut client_accounts = process_csv(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+        client_accounts.sort_by_key(|account| account.client_id);
+        let balances: Vec<FixtureBalance> = client_accounts
+            .iter()
+            .map(|account| FixtureBalance {
+                client_id: account.client_id,
+                available: account.available_balance,
+                held: account.held_balance,
+                locked: account.locked,
+            })
+            .collect();
+
+        assert_eq!(expected, balances);
+        assert!(expected.iter().any(|balance| balance.locked));
+        Ok(())
+    }
+}
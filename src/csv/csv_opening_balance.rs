@@ -0,0 +1,249 @@
+use super::csv_reader::open_csv_file;
+use crate::domain::{
+    account_policy::AccountPolicy,
+    client_account::{ClientAccount, ClientId},
+};
+use anyhow::{Error, Result};
+use csv::{Reader, StringRecord};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const EXPECTED_HEADERS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+const LOCKED_COLUMN: usize = 4;
+
+/// A client's balances at the start of processing, in the same columns as the output.
+#[derive(Debug, Deserialize)]
+pub struct CsvOpeningBalance {
+    pub client_id: ClientId,
+    pub available_balance: Decimal,
+    pub held_balance: Decimal,
+    pub total_balance: Decimal,
+    pub locked: bool,
+}
+
+impl CsvOpeningBalance {
+    /// Creates an account starting from these balances. There are no disputes to
+    /// explain the held balance, so it's treated as a manual hold which can be released.
+    pub fn to_client_account(&self, policy: AccountPolicy) -> Result<ClientAccount> {
+        if self.available_balance + self.held_balance != self.total_balance {
+            return Err(Error::msg(format!(
+                "Opening balance for client {} is inconsistent: available {} plus held {} is not total {}",
+                self.client_id, self.available_balance, self.held_balance, self.total_balance
+            )));
+        }
+        if self.held_balance.is_sign_negative() {
+            return Err(Error::msg(format!(
+                "Opening balance for client {} has a negative held balance",
+                self.client_id
+            )));
+        }
+
+        let mut account = ClientAccount::with_policy(self.client_id, policy);
+        account.available_balance = self.available_balance;
+        account.held_balance = self.held_balance;
+        account.total_balance = self.total_balance;
+        account.manually_held_balance = self.held_balance;
+        if self.locked {
+            account.seed_locked();
+        }
+        Ok(account)
+    }
+}
+
+/// Reads the opening balances CSV at `path` into accounts keyed by client, each created
+/// with `policy`. Every client may appear at most once. With `tolerant_bool_locked`, the
+/// `locked` column may also be `yes`/`no` or `1`/`0`, in any case.
+pub fn read_opening_balances(
+    path: &str,
+    policy: AccountPolicy,
+    tolerant_bool_locked: bool,
+) -> Result<HashMap<ClientId, ClientAccount>> {
+    let mut reader = Reader::from_reader(open_csv_file(path, false)?);
+
+    let headers = reader
+        .headers()
+        .map_err(|err| Error::msg(format!("Failed to read opening balances header: {}", err)))?;
+    if !headers.is_empty() && !headers.iter().map(str::trim).eq(EXPECTED_HEADERS) {
+        return Err(Error::msg(format!(
+            "Invalid opening balances header: expected {} but found {}",
+            EXPECTED_HEADERS.join(","),
+            headers.iter().collect::<Vec<_>>().join(",")
+        )));
+    }
+
+    let mut client_accounts = HashMap::new();
+    for record in reader.records() {
+        let mut record = record
+            .map_err(|err| Error::msg(format!("Failed to parse opening balance: {}", err)))?;
+        record.trim();
+        if tolerant_bool_locked {
+            record = normalize_locked(record)?;
+        }
+        let opening_balance = record
+            .deserialize::<CsvOpeningBalance>(None)
+            .map_err(|err| Error::msg(format!("Failed to parse opening balance: {}", err)))?;
+
+        let account = opening_balance.to_client_account(policy)?;
+        if client_accounts.insert(account.client_id, account).is_some() {
+            return Err(Error::msg(format!(
+                "Duplicate opening balance for client {}",
+                opening_balance.client_id
+            )));
+        }
+    }
+
+    Ok(client_accounts)
+}
+
+/// Rewrites the record's `locked` field as `true` or `false` from any of the forms a
+/// tolerant read accepts.
+fn normalize_locked(record: StringRecord) -> Result<StringRecord> {
+    let Some(locked) = record.get(LOCKED_COLUMN) else {
+        // A short row is left for deserializing to reject
+        return Ok(record);
+    };
+    let locked = match locked.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => "true",
+        "false" | "no" | "0" => "false",
+        _ => {
+            return Err(Error::msg(format!(
+                "Failed to parse opening balance: Invalid locked value {}. Expected true, false, yes, no, 1 or 0",
+                locked
+            )))
+        }
+    };
+
+    Ok(record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if index == LOCKED_COLUMN {
+                locked
+            } else {
+                field
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_opening_balances;
+    use crate::{
+        assert_err::assert_err, domain::account_policy::AccountPolicy, test_fixture::write_fixture,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn reads_opening_balances() -> Result<()> {
+        let path = write_fixture(
+            "opening-balances",
+            "client,available,held,total,locked\n\
+             1,10.5,2.0,12.5,false\n\
+             2,0,0,0,true\n",
+        )?;
+
+        let client_accounts = read_opening_balances(&path, AccountPolicy::default(), false)?;
+
+        assert_eq!(dec!(10.5), client_accounts[&1].available_balance);
+        assert_eq!(dec!(2), client_accounts[&1].held_balance);
+        assert_eq!(dec!(12.5), client_accounts[&1].total_balance);
+        assert!(client_accounts[&1].held_matches_disputes());
+        assert!(client_accounts[&2].locked);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_inconsistent_opening_balance() -> Result<()> {
+        let path = write_fixture(
+            "opening-balances-inconsistent",
+            "client,available,held,total,locked\n\
+             1,10.5,2.5,14,false\n",
+        )?;
+
+        assert_err!(
+            read_opening_balances(&path, AccountPolicy::default(), false),
+            "Opening balance for client 1 is inconsistent: available 10.5 plus held 2.5 is not total 14"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_duplicate_opening_balance() -> Result<()> {
+        let path = write_fixture(
+            "opening-balances-duplicate",
+            "client,available,held,total,locked\n\
+             1,1,0,1,false\n\
+             1,2,0,2,false\n",
+        )?;
+
+        assert_err!(
+            read_opening_balances(&path, AccountPolicy::default(), false),
+            "Duplicate opening balance for client 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_each_tolerant_locked_form() -> Result<()> {
+        let path = write_fixture(
+            "opening-balances-tolerant-locked",
+            "client,available,held,total,locked\n\
+             1,0,0,0,yes\n\
+             2,0,0,0,NO\n\
+             3,0,0,0,1\n\
+             4,0,0,0,0\n\
+             5,0,0,0,True\n\
+             6,0,0,0,false\n",
+        )?;
+
+        let client_accounts = read_opening_balances(&path, AccountPolicy::default(), true)?;
+
+        let mut locked: Vec<_> = client_accounts
+            .values()
+            .map(|account| (account.client_id, account.locked))
+            .collect();
+        locked.sort_unstable();
+        assert_eq!(
+            vec![
+                (1, true),
+                (2, false),
+                (3, true),
+                (4, false),
+                (5, true),
+                (6, false)
+            ],
+            locked
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_unknown_tolerant_locked_value() -> Result<()> {
+        let path = write_fixture(
+            "opening-balances-maybe-locked",
+            "client,available,held,total,locked\n\
+             1,0,0,0,maybe\n",
+        )?;
+
+        assert_err!(
+            read_opening_balances(&path, AccountPolicy::default(), true),
+            "Failed to parse opening balance: Invalid locked value maybe. Expected true, false, yes, no, 1 or 0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_yes_locked_value_without_tolerance() -> Result<()> {
+        let path = write_fixture(
+            "opening-balances-strict-locked",
+            "client,available,held,total,locked\n\
+             1,0,0,0,yes\n",
+        )?;
+
+        assert!(read_opening_balances(&path, AccountPolicy::default(), false).is_err());
+        Ok(())
+    }
+}
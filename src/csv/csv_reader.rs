@@ -1,11 +1,355 @@
-use std::{fs::File, io::BufReader};
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::Path,
+};
 
 use anyhow::{Error, Result};
-use csv::Reader;
+use csv::{ByteRecord, Reader};
 
-pub fn open_csv_reader(path: &str) -> Result<Reader<BufReader<File>>> {
+use super::line_limited_reader::LineLimitedReader;
+
+const EXPECTED_HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+/// Optional columns, which come after the expected columns once reordered: the row's own
+/// ID, and the kind of transaction a dispute targets.
+const OPTIONAL_HEADERS: [&str; 2] = ["id", "target"];
+
+/// The longest line accepted. Valid rows are far shorter, so anything longer is
+/// malformed or hostile.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// An opened CSV file, which may be decompressed as it's read. It can be sent to another
+/// thread, so a pipelined run can read it there.
+pub type CsvFile = Box<dyn Read + Send>;
+
+/// Opens the CSV at `path` and checks its header row. An empty file is accepted
+/// and simply yields no records.
+pub fn open_csv_reader(path: &str) -> Result<Reader<LineLimitedReader<CsvFile>>> {
+    csv_reader(open_csv_file(path, false)?)
+}
+
+/// Opens the file at `path` for buffered reading, without checking its contents. It's
+/// decompressed as it's read if `zstd` is set or its name ends in `.zst`.
+pub fn open_csv_file(path: &str, zstd: bool) -> Result<CsvFile> {
     let file = File::open(path)
         .map_err(|err| Error::msg(format!("Failed to open CSV at path {}: {}", path, err)))?;
-    let buffered_reader = BufReader::new(file);
-    Ok(Reader::from_reader(buffered_reader))
+    if !zstd && !is_zstd_path(Path::new(path)) {
+        return Ok(Box::new(BufReader::new(file)));
+    }
+
+    let decoder = zstd::Decoder::new(file).map_err(|err| {
+        Error::msg(format!(
+            "Failed to start decompressing CSV at path {}: {}",
+            path, err
+        ))
+    })?;
+    Ok(Box::new(decoder))
+}
+
+fn is_zstd_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+/// Builds a CSV reader over any source and checks its header row, like [`open_csv_reader`].
+/// Reading fails on any line longer than [`MAX_LINE_BYTES`].
+pub fn csv_reader<R: Read>(source: R) -> Result<Reader<LineLimitedReader<R>>> {
+    let mut reader = Reader::from_reader(LineLimitedReader::new(source, MAX_LINE_BYTES));
+
+    let headers = reader
+        .headers()
+        .map_err(|err| Error::msg(format!("Failed to read CSV header: {}", err)))?;
+    if !headers.is_empty() && ColumnOrder::from_headers(headers.as_byte_record()).is_none() {
+        return Err(Error::msg(format!(
+            "Invalid CSV header: expected {} but found {}",
+            EXPECTED_HEADERS.join(","),
+            headers.iter().collect::<Vec<_>>().join(",")
+        )));
+    }
+
+    Ok(reader)
+}
+
+/// Yields the records of the CSV at `path` like [`transaction_records`]. If `path` is a
+/// directory, every `*.csv` and `*.csv.zst` file in it is read in order of file name as
+/// one stream, each with its own header. Other files are ignored. With `zstd`, every file
+/// is decompressed whatever its name.
+pub fn transaction_records_at(
+    path: &str,
+    zstd: bool,
+) -> Result<impl Iterator<Item = csv::Result<ByteRecord>>> {
+    let mut records = Vec::new();
+    for csv_path in csv_paths(path)? {
+        let reader = csv_reader(open_csv_file(&csv_path, zstd)?)?;
+        records.push(transaction_records(reader)?);
+    }
+    Ok(records.into_iter().flatten())
+}
+
+/// The CSV files at `path`: just `path` for a file, or the sorted `*.csv` and `*.csv.zst`
+/// files for a directory.
+fn csv_paths(path: &str) -> Result<Vec<String>> {
+    if !Path::new(path).is_dir() {
+        return Ok(vec![path.to_string()]);
+    }
+
+    let read_error = |err| Error::msg(format!("Failed to read CSV directory {}: {}", path, err));
+    let mut csv_paths = Vec::new();
+    for entry in fs::read_dir(path).map_err(read_error)? {
+        let entry_path = entry.map_err(read_error)?.path();
+        let file_name = entry_path.file_name().unwrap_or_default().to_string_lossy();
+        if entry_path.is_file() && (file_name.ends_with(".csv") || file_name.ends_with(".csv.zst"))
+        {
+            csv_paths.push(entry_path.to_string_lossy().into_owned());
+        }
+    }
+    csv_paths.sort();
+    Ok(csv_paths)
+}
+
+/// Checks whether the record is the expected header row, ignoring surrounding whitespace.
+pub fn is_header(record: &ByteRecord) -> bool {
+    let expected_len = EXPECTED_HEADERS.len();
+    record.len() >= expected_len
+        && record
+            .iter()
+            .take(expected_len)
+            .map(<[u8]>::trim_ascii)
+            .eq(EXPECTED_HEADERS.map(str::as_bytes))
+        && ColumnOrder::from_headers(record).is_some()
+}
+
+/// Yields the reader's records with their fields in the expected order, whatever order
+/// the file's header puts the columns in. Fields are left as bytes, so rows needn't be
+/// checked as UTF-8 before parsing.
+pub fn transaction_records<R: Read>(
+    mut reader: Reader<R>,
+) -> Result<impl Iterator<Item = csv::Result<ByteRecord>>> {
+    let headers = reader
+        .headers()
+        .map_err(|err| Error::msg(format!("Failed to read CSV header: {}", err)))?;
+    let column_order = ColumnOrder::from_headers(headers.as_byte_record()).unwrap_or_default();
+
+    Ok(reader
+        .into_byte_records()
+        .map(move |record| record.map(|record| column_order.reorder(record))))
+}
+
+/// The position in the file of each expected column, and of each optional column the
+/// file has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ColumnOrder([usize; 4], [Option<usize>; 2]);
+
+impl Default for ColumnOrder {
+    fn default() -> Self {
+        ColumnOrder([0, 1, 2, 3], [None; 2])
+    }
+}
+
+impl ColumnOrder {
+    /// Finds each expected column by name. Returns `None` unless the header has exactly
+    /// the expected columns, optionally with any of the optional columns, in any order.
+    fn from_headers(headers: &ByteRecord) -> Option<ColumnOrder> {
+        let find = |expected: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim_ascii() == expected.as_bytes())
+        };
+
+        let mut positions = [0; 4];
+        for (position, expected) in positions.iter_mut().zip(EXPECTED_HEADERS) {
+            *position = find(expected)?;
+        }
+        let optional = OPTIONAL_HEADERS.map(find);
+        let optional_count = optional.iter().flatten().count();
+        if headers.len() != EXPECTED_HEADERS.len() + optional_count {
+            return None;
+        }
+        Some(ColumnOrder(positions, optional))
+    }
+    fn reorder(&self, record: ByteRecord) -> ByteRecord {
+        let ColumnOrder(positions, optional) = *self;
+        let optional_count = optional.iter().flatten().count();
+        let in_place = positions == ColumnOrder::default().0
+            && optional
+                .iter()
+                .take(optional_count)
+                .zip(EXPECTED_HEADERS.len()..)
+                .all(|(&position, expected)| position == Some(expected));
+        if in_place {
+            return record;
+        }
+        // Rows with a different number of fields are left for parsing to reject
+        if record.len() != EXPECTED_HEADERS.len() + optional_count {
+            return record;
+        }
+
+        // An optional column missing before one that's present is filled with an empty
+        // field, so each keeps its place
+        let trailing = optional
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |last| last + 1);
+        let mut reordered: ByteRecord = positions
+            .iter()
+            .map(|&position| &record[position])
+            .chain(
+                optional[..trailing]
+                    .iter()
+                    .map(|position| position.map_or(&b""[..], |position| &record[position])),
+            )
+            .collect();
+        reordered.set_position(record.position().cloned());
+        reordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open_csv_reader, transaction_records, MAX_LINE_BYTES};
+    use crate::{assert_err::assert_err, test_fixture::write_fixture};
+    use anyhow::Result;
+
+    #[test]
+    fn reads_no_records_from_an_empty_file() -> Result<()> {
+        let csv_path = write_fixture("empty", "")?;
+
+        let mut reader = open_csv_reader(&csv_path)?;
+
+        assert_eq!(0, reader.records().count());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_no_records_from_a_header_only_file() -> Result<()> {
+        let csv_path = write_fixture("header-only", "type, client, tx, amount\n")?;
+
+        let mut reader = open_csv_reader(&csv_path)?;
+
+        assert_eq!(0, reader.records().count());
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_open_a_file_with_a_bad_header() -> Result<()> {
+        let csv_path = write_fixture("bad-header", "kind,client,tx,amount\ndeposit,1,1,1.0\n")?;
+
+        // The reader's boxed file can't be debug printed, so only the error is kept
+        let result = open_csv_reader(&csv_path).map(drop);
+        assert_err!(
+            result,
+            "Invalid CSV header: expected type,client,tx,amount but found kind,client,tx,amount"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_columns_in_any_order_by_header_name() -> Result<()> {
+        let csv_path = write_fixture(
+            "reordered-columns",
+            "client,type,amount,tx\n1,deposit,1.5,7\n2,dispute,,8\n",
+        )?;
+
+        let records =
+            transaction_records(open_csv_reader(&csv_path)?)?.collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            vec!["deposit", "1", "7", "1.5"],
+            records[0]
+                .iter()
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["dispute", "2", "8", ""],
+            records[1]
+                .iter()
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(2),
+            records[0].position().map(|position| position.line())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn moves_row_id_column_after_the_expected_columns() -> Result<()> {
+        let csv_path = write_fixture(
+            "row-id-column",
+            "id,type,client,tx,amount\n100,dispute,2,8,\n",
+        )?;
+
+        let records =
+            transaction_records(open_csv_reader(&csv_path)?)?.collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            vec!["dispute", "2", "8", "", "100"],
+            records[0]
+                .iter()
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fills_a_missing_row_id_when_there_is_a_target_column() -> Result<()> {
+        let csv_path = write_fixture(
+            "target-column",
+            "type,client,target,tx,amount\n\
+             dispute,2,withdrawal,8,\n",
+        )?;
+
+        let records =
+            transaction_records(open_csv_reader(&csv_path)?)?.collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            vec!["dispute", "2", "8", "", "", "withdrawal"],
+            records[0]
+                .iter()
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_a_file_with_crlf_line_endings() -> Result<()> {
+        let csv_path = write_fixture(
+            "crlf-reader",
+            "type,client,tx,amount\r\ndeposit,1,1,1.0\r\ndispute,1,1,\r\n",
+        )?;
+
+        let mut reader = open_csv_reader(&csv_path)?;
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(2, records.len());
+        assert_eq!(
+            vec!["deposit", "1", "1", "1.0"],
+            records[0].iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["dispute", "1", "1", ""],
+            records[1].iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_a_line_longer_than_the_maximum() -> Result<()> {
+        let csv_path = write_fixture(
+            "oversized-line",
+            &format!(
+                "type,client,tx,amount\ndeposit,1,1,{}\n",
+                "9".repeat(MAX_LINE_BYTES * 16)
+            ),
+        )?;
+
+        let mut reader = open_csv_reader(&csv_path)?;
+        let error = reader.records().next().expect("a record").unwrap_err();
+
+        assert_eq!("Line exceeds the maximum of 65536 bytes", error.to_string());
+        Ok(())
+    }
 }
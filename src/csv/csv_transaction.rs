@@ -1,41 +1,171 @@
 use anyhow::{Error, Result};
-use csv::StringRecord;
+use csv::{ByteRecord, StringRecord};
 use rust_decimal::Decimal;
 use serde::Deserialize;
+use std::{borrow::Cow, str::FromStr};
 
+use super::parse_options::ParseOptions;
 use crate::domain::{
-    client_account::ClientId,
-    transaction::{Deposit, Transaction, TransactionAction, TransactionId, Withdrawal},
+    client_account::{ClientId, OUTPUT_PRECISION},
+    transaction::{
+        Deposit, Dispute, DisputeTarget, Hold, Release, Transaction, TransactionAction,
+        TransactionId, Withdrawal,
+    },
 };
 
-#[derive(Debug, Deserialize)]
-pub struct CsvTransaction {
-    pub transaction_type: String,
+const CLIENT_COLUMN: usize = 1;
+pub(crate) const TRANSACTION_ID_COLUMN: usize = 2;
+const AMOUNT_COLUMN: usize = 3;
+const CURRENCY_SYMBOLS: [char; 4] = ['$', '€', '£', '¥'];
+/// How many single character edits a type can be from a known one to be suggested.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+const TRANSACTION_TYPES: [&str; 11] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "reset",
+    "hold",
+    "release",
+    "settle",
+    "freeze",
+    "unfreeze",
+];
+
+/// A CSV row before it's converted into a transaction. The type is either owned, or
+/// borrowed from the record it was read from to avoid allocating a `String` per row.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+pub struct CsvTransaction<T = String> {
+    pub transaction_type: T,
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Option<Decimal>,
+    /// An ID for the row itself, which disputes, resolves and chargebacks may have so that
+    /// a replayed row can be told apart from a new one. Their `tx` is the deposit's ID.
+    #[serde(default)]
+    pub row_id: Option<TransactionId>,
+    /// Whether a dispute refers to a deposit or a withdrawal, for files where the two
+    /// don't share an ID space.
+    #[serde(default)]
+    pub target: Option<DisputeTarget>,
 }
 
 impl CsvTransaction {
-    pub fn from_string_record(mut record: StringRecord) -> Result<CsvTransaction> {
+    pub fn from_string_record(
+        mut record: StringRecord,
+        options: &ParseOptions,
+    ) -> Result<CsvTransaction> {
+        record.trim();
+        let record = intern_client_label(record, options)?;
+        let record = expand_scientific_amount(map_amount_options(record, options))?;
+        record.deserialize::<CsvTransaction>(None).map_err(|err| {
+            deserialize_error(
+                record.get(AMOUNT_COLUMN),
+                record.get(TRANSACTION_ID_COLUMN),
+                err,
+            )
+        })
+    }
+}
+
+impl<'a> CsvTransaction<&'a str> {
+    /// Reads the row like [`CsvTransaction::from_string_record`], borrowing the type from
+    /// `record`. The record is trimmed in place, and only reallocated when the options
+    /// rewrite the amount.
+    pub fn from_byte_record(
+        record: &'a mut ByteRecord,
+        options: &ParseOptions,
+    ) -> Result<CsvTransaction<&'a str>> {
         record.trim();
+        let has_exponent = record
+            .get(AMOUNT_COLUMN)
+            .is_some_and(|amount| amount.iter().any(|&byte| byte == b'e' || byte == b'E'));
+        if options.sanitize_amounts || has_exponent || options.client_labels.is_some() {
+            let string_record =
+                StringRecord::from_byte_record(std::mem::take(record)).map_err(|err| {
+                    Error::msg(format!("Failed to deserialize CSV transaction: {}", err))
+                })?;
+            let string_record = intern_client_label(string_record, options)?;
+            *record = expand_scientific_amount(map_amount_options(string_record, options))?
+                .into_byte_record();
+        }
+
+        let record = &*record;
         record
-            .deserialize::<CsvTransaction>(None)
-            .map_err(|err| Error::msg(format!("Failed to deserialize CSV transaction: {}", err)))
+            .deserialize::<CsvTransaction<&str>>(None)
+            .map_err(|err| {
+                let field = |index| {
+                    record
+                        .get(index)
+                        .and_then(|field| std::str::from_utf8(field).ok())
+                };
+                deserialize_error(field(AMOUNT_COLUMN), field(TRANSACTION_ID_COLUMN), err)
+            })
     }
-    pub fn to_transaction(self) -> Result<Transaction> {
+}
+
+impl<T: AsRef<str>> CsvTransaction<T> {
+    pub fn has_known_type(&self, options: &ParseOptions) -> bool {
+        TRANSACTION_TYPES.contains(&self.transaction_type(options).as_ref())
+    }
+    /// The type, without any internal whitespace if the options tolerate it.
+    pub fn transaction_type(&self, options: &ParseOptions) -> Cow<'_, str> {
+        let transaction_type = self.transaction_type.as_ref();
+        if options.tolerant_whitespace_type && transaction_type.contains(char::is_whitespace) {
+            return Cow::Owned(transaction_type.split_whitespace().collect());
+        }
+        Cow::Borrowed(transaction_type)
+    }
+    /// Converts the row into a transaction. Returns `None` for rows the options
+    /// say should be skipped rather than applied.
+    pub fn to_transaction(&self, options: &ParseOptions) -> Result<Option<Transaction>> {
         let transaction_id = self.transaction_id;
 
-        match self.transaction_type.as_str() {
-            "deposit" => self.to_deposit(),
-            "withdrawal" => self.to_withdrawal(),
-            "dispute" => self.to_dispute(),
-            "resolve" => self.to_resolve(),
-            "chargeback" => self.to_chargeback(),
-            _ => Err(Error::msg(format!(
-                "Unknown type {}",
-                self.transaction_type
-            ))),
+        match self.transaction_type(options).as_ref() {
+            transaction_type
+                if self.row_id.is_some()
+                    && !matches!(transaction_type, "dispute" | "resolve" | "chargeback") =>
+            {
+                Err(Error::msg(
+                    "Only disputes, resolves and chargebacks can have a row ID",
+                ))
+            }
+            transaction_type
+                if self.target.is_some()
+                    && !matches!(transaction_type, "dispute" | "resolve" | "chargeback") =>
+            {
+                Err(Error::msg(
+                    "Only disputes, resolves and chargebacks can have a target",
+                ))
+            }
+            "deposit" | "withdrawal"
+                if options.allow_zero_amounts && self.amount == Some(Decimal::ZERO) =>
+            {
+                Ok(None)
+            }
+            "deposit" => self.to_deposit(options).map(Some),
+            "withdrawal" => self.to_withdrawal(options).map(Some),
+            "dispute" => self.to_dispute(options).map(Some),
+            "resolve" => self.to_resolve().map(Some),
+            "chargeback" => self.to_chargeback().map(Some),
+            "reset" if options.allow_reset => self.to_reset().map(Some),
+            "reset" => Err(Error::msg("Reset transactions are not allowed")),
+            "hold" => self.to_hold(options).map(Some),
+            "release" => self.to_release(options).map(Some),
+            "settle" => self.to_settle().map(Some),
+            "freeze" if options.allow_admin => self.to_freeze().map(Some),
+            "unfreeze" if options.allow_admin => self.to_unfreeze().map(Some),
+            "freeze" | "unfreeze" => Err(Error::msg(
+                "Freeze and unfreeze transactions are not allowed",
+            )),
+            transaction_type => Err(match closest_transaction_type(transaction_type) {
+                Some(suggestion) => Error::msg(format!(
+                    "Unknown type {} (did you mean {}?)",
+                    transaction_type, suggestion
+                )),
+                None => Error::msg(format!("Unknown type {}", transaction_type)),
+            }),
         }
         .map_err(|err| {
             Error::msg(format!(
@@ -44,8 +174,8 @@ impl CsvTransaction {
             ))
         })
     }
-    fn to_deposit(self) -> Result<Transaction> {
-        let amount = self.assert_positive_amount()?;
+    fn to_deposit(&self, options: &ParseOptions) -> Result<Transaction> {
+        let amount = self.assert_positive_amount(options)?;
 
         Ok(Transaction {
             client_id: self.client_id,
@@ -53,8 +183,8 @@ impl CsvTransaction {
             action: TransactionAction::Deposit(Deposit { amount }),
         })
     }
-    fn to_withdrawal(self) -> Result<Transaction> {
-        let amount = self.assert_positive_amount()?;
+    fn to_withdrawal(&self, options: &ParseOptions) -> Result<Transaction> {
+        let amount = self.assert_positive_amount(options)?;
 
         Ok(Transaction {
             client_id: self.client_id,
@@ -62,30 +192,86 @@ impl CsvTransaction {
             action: TransactionAction::Withdrawal(Withdrawal { amount }),
         })
     }
-    fn to_dispute(self) -> Result<Transaction> {
+    fn to_dispute(&self, options: &ParseOptions) -> Result<Transaction> {
+        // An amount is optional and disputes only that part of the deposit
+        let amount = match self.amount {
+            Some(_) => Some(self.assert_positive_amount(options)?),
+            None => None,
+        };
+
         Ok(Transaction {
             client_id: self.client_id,
             transaction_id: self.transaction_id,
-            action: TransactionAction::Dispute,
+            action: TransactionAction::Dispute(Dispute {
+                amount,
+                target: self.target,
+            }),
         })
     }
-    fn to_resolve(self) -> Result<Transaction> {
+    fn to_resolve(&self) -> Result<Transaction> {
         Ok(Transaction {
             client_id: self.client_id,
             transaction_id: self.transaction_id,
             action: TransactionAction::Resolve,
         })
     }
-    fn to_chargeback(self) -> Result<Transaction> {
+    fn to_chargeback(&self) -> Result<Transaction> {
         Ok(Transaction {
             client_id: self.client_id,
             transaction_id: self.transaction_id,
             action: TransactionAction::Chargeback,
         })
     }
-    fn assert_positive_amount(&self) -> Result<Decimal> {
+    fn to_settle(&self) -> Result<Transaction> {
+        Ok(Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            action: TransactionAction::Settle,
+        })
+    }
+    fn to_freeze(&self) -> Result<Transaction> {
+        Ok(Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            action: TransactionAction::Freeze,
+        })
+    }
+    fn to_unfreeze(&self) -> Result<Transaction> {
+        Ok(Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            action: TransactionAction::Unfreeze,
+        })
+    }
+    fn to_reset(&self) -> Result<Transaction> {
+        Ok(Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            action: TransactionAction::Reset,
+        })
+    }
+    fn to_hold(&self, options: &ParseOptions) -> Result<Transaction> {
+        let amount = self.assert_positive_amount(options)?;
+
+        Ok(Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            action: TransactionAction::Hold(Hold { amount }),
+        })
+    }
+    fn to_release(&self, options: &ParseOptions) -> Result<Transaction> {
+        let amount = self.assert_positive_amount(options)?;
+
+        Ok(Transaction {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            action: TransactionAction::Release(Release { amount }),
+        })
+    }
+    fn assert_positive_amount(&self, options: &ParseOptions) -> Result<Decimal> {
         self.amount
             .ok_or(Error::msg("Amount is missing"))
+            .and_then(|amount| scale_amount(amount, options))
             .and_then(|amount| {
                 if amount > Decimal::ZERO {
                     Ok(amount)
@@ -96,22 +282,242 @@ impl CsvTransaction {
     }
 }
 
+fn deserialize_error(amount: Option<&str>, transaction_id: Option<&str>, err: csv::Error) -> Error {
+    match amount {
+        // The decimal deserializer's error doesn't say which field failed, so
+        // check the amount separately to give a clearer message
+        Some(amount) if !amount.is_empty() && parse_amount(amount).is_none() => {
+            Error::msg(format!(
+                "Invalid amount '{}' for transaction {}",
+                amount,
+                transaction_id.unwrap_or_default()
+            ))
+        }
+        _ => Error::msg(format!("Failed to deserialize CSV transaction: {}", err)),
+    }
+}
+
+/// Parses an amount the way the decimal deserializer does, accepting scientific notation.
+fn parse_amount(amount: &str) -> Option<Decimal> {
+    Decimal::from_str(amount)
+        .or_else(|_| Decimal::from_scientific(amount))
+        .ok()
+}
+
+/// Converts an amount given in minor units, e.g. cents, into its decimal value.
+fn scale_amount(amount: Decimal, options: &ParseOptions) -> Result<Decimal> {
+    let Some(input_scale) = options.input_scale else {
+        return Ok(amount);
+    };
+
+    if !amount.fract().is_zero() {
+        return Err(Error::msg(format!(
+            "Amount {} is not a whole number of minor units",
+            amount
+        )));
+    }
+
+    // Setting the scale of a whole number divides it by 10^scale exactly
+
+    let mut scaled = amount.trunc();
+    scaled.set_scale(input_scale).map_err(|err| {
+        Error::msg(format!(
+            "Failed to apply input scale {}: {}",
+            input_scale, err
+        ))
+    })?;
+
+    Ok(scaled)
+}
+
+/// Rewrites an amount in scientific notation, e.g. `1.25e1`, as a plain decimal. It fails
+/// rather than rounding when the amount can't be represented exactly, such as when it
+/// needs more than 28 decimal places, or when it has more than 4 decimal places. Malformed
+/// amounts are left for the deserializer to report.
+fn expand_scientific_amount(record: StringRecord) -> Result<StringRecord> {
+    let Some((mantissa, exponent)) = record
+        .get(AMOUNT_COLUMN)
+        .and_then(|amount| amount.split_once(['e', 'E']))
+    else {
+        return Ok(record);
+    };
+    let (Ok(mantissa_value), Ok(exponent)) = (Decimal::from_str(mantissa), exponent.parse::<i64>())
+    else {
+        return Ok(record);
+    };
+
+    let inexact = || {
+        Error::msg(format!(
+            "Amount '{}' for transaction {} can't be represented exactly as a decimal",
+            record.get(AMOUNT_COLUMN).unwrap_or_default(),
+            record.get(TRANSACTION_ID_COLUMN).unwrap_or_default()
+        ))
+    };
+
+    // Parsing rounds a mantissa with more digits than a decimal can hold
+    if significant_digits(mantissa) != significant_digits(&mantissa_value.to_string()) {
+        return Err(inexact());
+    }
+
+    let mantissa_value = mantissa_value.normalize();
+    let scale = i64::from(mantissa_value.scale()) - exponent;
+    let expanded = if scale >= 0 {
+        let mut expanded = Decimal::from_i128_with_scale(mantissa_value.mantissa(), 0);
+        u32::try_from(scale)
+            .ok()
+            .and_then(|scale| expanded.set_scale(scale).ok())
+            .ok_or_else(inexact)?;
+        expanded
+    } else {
+        (0..-scale).try_fold(
+            Decimal::from_i128_with_scale(mantissa_value.mantissa(), 0),
+            |expanded, _| expanded.checked_mul(Decimal::TEN).ok_or_else(inexact),
+        )?
+    };
+
+    // The exponent can hide how precise an amount is, so it's held to the engine's scale
+    if expanded.normalize().scale() > OUTPUT_PRECISION {
+        return Err(Error::msg(format!(
+            "Amount '{}' for transaction {} has more than {} decimal places",
+            record.get(AMOUNT_COLUMN).unwrap_or_default(),
+            record.get(TRANSACTION_ID_COLUMN).unwrap_or_default(),
+            OUTPUT_PRECISION
+        )));
+    }
+
+    let expanded = expanded.to_string();
+    Ok(map_amount(&record, |_| expanded.clone()))
+}
+
+/// The digits of a decimal written without exponent, less any leading and trailing zeros.
+fn significant_digits(amount: &str) -> String {
+    let digits: String = amount.chars().filter(char::is_ascii_digit).collect();
+    digits.trim_matches('0').to_string()
+}
+
+/// Replaces the client label with the client ID it's interned as, when the options use
+/// string client identifiers.
+fn intern_client_label(record: StringRecord, options: &ParseOptions) -> Result<StringRecord> {
+    let (Some(client_labels), Some(label)) = (&options.client_labels, record.get(CLIENT_COLUMN))
+    else {
+        return Ok(record);
+    };
+    let client_id = client_labels.intern(label)?.to_string();
+
+    let mut interned: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if index == CLIENT_COLUMN {
+                &client_id
+            } else {
+                field
+            }
+        })
+        .collect();
+    interned.set_position(record.position().cloned());
+    Ok(interned)
+}
+
+/// Rewrites the amount as the options ask, before it's parsed.
+fn map_amount_options(record: StringRecord, options: &ParseOptions) -> StringRecord {
+    if !options.sanitize_amounts {
+        return record;
+    }
+    map_amount(&record, |amount| {
+        strip_thousands_separators(amount.trim_start_matches(CURRENCY_SYMBOLS))
+    })
+}
+
+/// Removes the commas from an amount only when they group its whole number into thousands,
+/// as in `1,234,567.89`. Any other comma, such as a decimal comma in `1,50`, is left in
+/// place so the amount fails to parse rather than being read as a different number.
+fn strip_thousands_separators(amount: &str) -> String {
+    let unsigned = amount.strip_prefix('+').unwrap_or(amount);
+    let whole = unsigned
+        .split_once('.')
+        .map_or(unsigned, |(whole, _)| whole);
+    let is_digits = |group: &str| group.bytes().all(|byte| byte.is_ascii_digit());
+    let mut groups = whole.split(',');
+    let first_is_valid = groups
+        .next()
+        .is_some_and(|group| (1..=3).contains(&group.len()) && is_digits(group));
+    let rest_are_valid = groups.all(|group| group.len() == 3 && is_digits(group));
+
+    if whole.contains(',') && first_is_valid && rest_are_valid {
+        amount.replace(',', "")
+    } else {
+        amount.to_string()
+    }
+}
+
+fn map_amount(record: &StringRecord, map: impl Fn(&str) -> String) -> StringRecord {
+    record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if index == AMOUNT_COLUMN {
+                map(field)
+            } else {
+                field.to_string()
+            }
+        })
+        .collect()
+}
+
+/// The known type fewest edits away from `transaction_type`, if any is close enough to be
+/// a likely typo.
+fn closest_transaction_type(transaction_type: &str) -> Option<&'static str> {
+    TRANSACTION_TYPES
+        .iter()
+        .map(|&known| (edit_distance(transaction_type, known), known))
+        .filter(|&(distance, _)| distance <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance between `a` and `b`: the fewest single character insertions,
+/// deletions and substitutions turning one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 #[cfg(test)]
 mod tests {
     use super::CsvTransaction;
-    use crate::assert_err::assert_err;
+    use crate::{
+        assert_err::assert_err,
+        csv::parse_options::ParseOptions,
+        domain::transaction::{Deposit, DisputeTarget, TransactionAction},
+    };
     use anyhow::Result;
+    use csv::{ByteRecord, StringRecord};
     use rust_decimal_macros::dec;
 
     #[test]
     fn fails_to_read_deposit_with_missing_amount() -> Result<()> {
         assert_err!(
-            CsvTransaction::to_transaction(CsvTransaction {
-                transaction_type: "deposit".to_string(),
-                client_id: 1,
-                transaction_id: 1,
-                amount: None,
-            }),
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "deposit".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: None,
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
             "Failed to read transaction with ID 1: Amount is missing"
         );
         Ok(())
@@ -120,70 +526,659 @@ mod tests {
     #[test]
     fn fails_to_read_deposit_with_zero_amount() -> Result<()> {
         assert_err!(
-            CsvTransaction::to_transaction(CsvTransaction {
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "deposit".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(0)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
+            "Failed to read transaction with ID 1: Amount is negative or zero"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_deposit_with_negative_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "deposit".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(-1)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
+            "Failed to read transaction with ID 1: Amount is negative or zero"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_withdrawal_with_missing_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "withdrawal".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: None,
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
+            "Failed to read transaction with ID 1: Amount is missing"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_withdrawal_with_zero_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "withdrawal".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(0)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
+            "Failed to read transaction with ID 1: Amount is negative or zero"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_withdrawal_with_negative_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "withdrawal".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(-1)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
+            "Failed to read transaction with ID 1: Amount is negative or zero"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skips_zero_amount_deposit_when_allowed() -> Result<()> {
+        let transaction = CsvTransaction::to_transaction(
+            &CsvTransaction {
                 transaction_type: "deposit".to_string(),
                 client_id: 1,
                 transaction_id: 1,
                 amount: Some(dec!(0)),
-            }),
+                row_id: None,
+                target: None,
+            },
+            &ParseOptions {
+                allow_zero_amounts: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert!(transaction.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn skips_zero_amount_withdrawal_when_allowed() -> Result<()> {
+        let transaction = CsvTransaction::to_transaction(
+            &CsvTransaction {
+                transaction_type: "withdrawal".to_string(),
+                client_id: 1,
+                transaction_id: 1,
+                amount: Some(dec!(0.0000)),
+                row_id: None,
+                target: None,
+            },
+            &ParseOptions {
+                allow_zero_amounts: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert!(transaction.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_negative_amount_when_zero_allowed() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "deposit".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(-1)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions {
+                    allow_zero_amounts: true,
+                    ..Default::default()
+                },
+            ),
             "Failed to read transaction with ID 1: Amount is negative or zero"
         );
         Ok(())
     }
 
     #[test]
-    fn fails_to_read_deposit_with_negative_amount() -> Result<()> {
-        assert_err!(
-            CsvTransaction::to_transaction(CsvTransaction {
+    fn reads_positive_deposit_when_zero_allowed() -> Result<()> {
+        let transaction = CsvTransaction::to_transaction(
+            &CsvTransaction {
                 transaction_type: "deposit".to_string(),
                 client_id: 1,
                 transaction_id: 1,
-                amount: Some(dec!(-1)),
-            }),
+                amount: Some(dec!(1.5)),
+                row_id: None,
+                target: None,
+            },
+            &ParseOptions {
+                allow_zero_amounts: true,
+                ..Default::default()
+            },
+        )?
+        .expect("Deposit should not be skipped");
+
+        assert!(matches!(
+            transaction.action,
+            TransactionAction::Deposit(Deposit { amount }) if amount == dec!(1.5)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_amount_with_currency_symbol_and_thousands_separators() -> Result<()> {
+        let options = ParseOptions {
+            sanitize_amounts: true,
+            ..Default::default()
+        };
+        let formatted = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "1", "$1,234.56"]),
+            &options,
+        )?
+        .to_transaction(&options)?;
+        let plain = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "1", "1234.56"]),
+            &options,
+        )?
+        .to_transaction(&options)?;
+
+        for transaction in [formatted, plain] {
+            assert!(matches!(
+                transaction.map(|transaction| transaction.action),
+                Some(TransactionAction::Deposit(Deposit { amount })) if amount == dec!(1234.56)
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn suggests_the_closest_type_for_an_unknown_type() -> Result<()> {
+        let options = ParseOptions::default();
+        let read = |transaction_type| {
+            CsvTransaction::from_string_record(
+                StringRecord::from(vec![transaction_type, "1", "1", "1.5"]),
+                &options,
+            )?
+            .to_transaction(&options)
+        };
+
+        let result = read("dep osit");
+        assert_err!(
+            result,
+            "Failed to read transaction with ID 1: Unknown type dep osit (did you mean deposit?)"
+        );
+        let result = read("widthdrawal");
+        assert_err!(
+            result,
+            "Failed to read transaction with ID 1: Unknown type widthdrawal (did you mean withdrawal?)"
+        );
+        // Nothing is suggested when no known type is close
+        let result = read("refund");
+        assert_err!(
+            result,
+            "Failed to read transaction with ID 1: Unknown type refund"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_type_with_internal_whitespace_when_tolerant() -> Result<()> {
+        let options = ParseOptions {
+            tolerant_whitespace_type: true,
+            ..Default::default()
+        };
+        let csv_transaction = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["dep osit", "1", "1", "1.5"]),
+            &options,
+        )?;
+
+        assert!(csv_transaction.has_known_type(&options));
+        assert!(matches!(
+            csv_transaction
+                .to_transaction(&options)?
+                .map(|transaction| transaction.action),
+            Some(TransactionAction::Deposit(Deposit { amount })) if amount == dec!(1.5)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_amount_with_trailing_carriage_return() -> Result<()> {
+        let options = ParseOptions::default();
+        let transaction = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "1", "1.5\r"]),
+            &options,
+        )?
+        .to_transaction(&options)?;
+
+        assert!(matches!(
+            transaction.map(|transaction| transaction.action),
+            Some(TransactionAction::Deposit(Deposit { amount })) if amount == dec!(1.5)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_amounts_in_scientific_notation_exactly() -> Result<()> {
+        let options = ParseOptions::default();
+        for (amount, expected) in [
+            ("1.25e1", dec!(12.5)),
+            ("125E-2", dec!(1.25)),
+            ("1.0000e-4", dec!(0.0001)),
+        ] {
+            let transaction = CsvTransaction::from_string_record(
+                StringRecord::from(vec!["deposit", "1", "1", amount]),
+                &options,
+            )?
+            .to_transaction(&options)?;
+
+            assert!(matches!(
+                transaction.map(|transaction| transaction.action),
+                Some(TransactionAction::Deposit(Deposit { amount })) if amount == expected
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_scientific_amount_with_more_than_four_decimal_places() -> Result<()> {
+        let result = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "9", "1.23456789e1"]),
+            &ParseOptions::default(),
+        );
+        assert_err!(
+            result,
+            "Amount '1.23456789e1' for transaction 9 has more than 4 decimal places"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_scientific_amount_beyond_decimal_precision() -> Result<()> {
+        let result = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "7", "1e-30"]),
+            &ParseOptions::default(),
+        );
+        assert_err!(
+            result,
+            "Amount '1e-30' for transaction 7 can't be represented exactly as a decimal"
+        );
+
+        let result = CsvTransaction::from_string_record(
+            StringRecord::from(vec![
+                "deposit",
+                "1",
+                "8",
+                "1.23456789012345678901234567891e1",
+            ]),
+            &ParseOptions::default(),
+        );
+        assert_err!(
+            result,
+            "Amount '1.23456789012345678901234567891e1' for transaction 8 can't be represented exactly as a decimal"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_non_numeric_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::from_string_record(
+                StringRecord::from(vec!["deposit", "1", "7", "abc"]),
+                &ParseOptions::default(),
+            ),
+            "Invalid amount 'abc' for transaction 7"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_amount_with_commas_that_are_not_thousands_separators() -> Result<()> {
+        let options = ParseOptions {
+            sanitize_amounts: true,
+            ..Default::default()
+        };
+
+        for amount in [
+            "1,50",
+            "$1,50",
+            "12,34,567",
+            ",100",
+            "1,000,00",
+            "1,0000",
+            "1.000,5",
+        ] {
+            let result = CsvTransaction::from_string_record(
+                StringRecord::from(vec!["deposit", "1", "1", amount]),
+                &options,
+            );
+            assert!(result.is_err(), "{} should not parse", amount);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_amount_with_currency_symbol_without_sanitizing() -> Result<()> {
+        let result = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "1", "$1,234.56"]),
+            &ParseOptions::default(),
+        );
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_amount_with_leading_plus_sign() -> Result<()> {
+        let options = ParseOptions::default();
+        let transaction = CsvTransaction::from_string_record(
+            StringRecord::from(vec!["deposit", "1", "1", "+12.5555"]),
+            &options,
+        )?
+        .to_transaction(&options)?;
+
+        assert!(matches!(
+            transaction.map(|transaction| transaction.action),
+            Some(TransactionAction::Deposit(Deposit { amount })) if amount == dec!(12.5555)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_negative_amount() -> Result<()> {
+        let options = ParseOptions::default();
+
+        assert_err!(
+            CsvTransaction::from_string_record(
+                StringRecord::from(vec!["deposit", "1", "1", "-12.5555"]),
+                &options,
+            )?
+            .to_transaction(&options),
             "Failed to read transaction with ID 1: Amount is negative or zero"
         );
         Ok(())
     }
 
     #[test]
-    fn fails_to_read_withdrawal_with_missing_amount() -> Result<()> {
+    fn reads_reset_when_allowed() -> Result<()> {
+        let transaction = CsvTransaction::to_transaction(
+            &CsvTransaction {
+                transaction_type: "reset".to_string(),
+                client_id: 1,
+                transaction_id: 1,
+                amount: None,
+                row_id: None,
+                target: None,
+            },
+            &ParseOptions {
+                allow_reset: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert!(matches!(
+            transaction.map(|transaction| transaction.action),
+            Some(TransactionAction::Reset)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_reset_when_not_allowed() -> Result<()> {
         assert_err!(
-            CsvTransaction::to_transaction(CsvTransaction {
-                transaction_type: "withdrawal".to_string(),
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "reset".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: None,
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default(),
+            ),
+            "Failed to read transaction with ID 1: Reset transactions are not allowed"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_freeze_when_admin_not_allowed() -> Result<()> {
+        let result = CsvTransaction::to_transaction(
+            &CsvTransaction {
+                transaction_type: "freeze".to_string(),
                 client_id: 1,
                 transaction_id: 1,
                 amount: None,
-            }),
-            "Failed to read transaction with ID 1: Amount is missing"
+                row_id: None,
+                target: None,
+            },
+            &ParseOptions::default(),
+        );
+        assert_err!(
+            result,
+            "Failed to read transaction with ID 1: Freeze and unfreeze transactions are not allowed"
         );
         Ok(())
     }
 
     #[test]
-    fn fails_to_read_withdrawal_with_zero_amount() -> Result<()> {
+    fn fails_to_read_deposit_with_target() -> Result<()> {
+        let result = CsvTransaction::to_transaction(
+            &CsvTransaction {
+                transaction_type: "deposit".to_string(),
+                client_id: 1,
+                transaction_id: 1,
+                amount: Some(dec!(1)),
+                row_id: None,
+                target: Some(DisputeTarget::Withdrawal),
+            },
+            &ParseOptions::default(),
+        );
         assert_err!(
-            CsvTransaction::to_transaction(CsvTransaction {
-                transaction_type: "withdrawal".to_string(),
+            result,
+            "Failed to read transaction with ID 1: Only disputes, resolves and chargebacks can have a target"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_amount_in_minor_units_with_input_scale() -> Result<()> {
+        let transaction = CsvTransaction::to_transaction(
+            &CsvTransaction {
+                transaction_type: "deposit".to_string(),
                 client_id: 1,
                 transaction_id: 1,
-                amount: Some(dec!(0)),
-            }),
+                amount: Some(dec!(125555)),
+                row_id: None,
+                target: None,
+            },
+            &ParseOptions {
+                input_scale: Some(4),
+                ..Default::default()
+            },
+        )?;
+
+        assert!(matches!(
+            transaction.map(|transaction| transaction.action),
+            Some(TransactionAction::Deposit(Deposit { amount })) if amount == dec!(12.5555)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_fractional_amount_with_input_scale() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "withdrawal".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(12.55)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions {
+                    input_scale: Some(2),
+                    ..Default::default()
+                },
+            ),
+            "Failed to read transaction with ID 1: Amount 12.55 is not a whole number of minor units"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_hold_with_missing_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "hold".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: None,
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
+            "Failed to read transaction with ID 1: Amount is missing"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_release_with_negative_amount() -> Result<()> {
+        assert_err!(
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "release".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(-1)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
             "Failed to read transaction with ID 1: Amount is negative or zero"
         );
         Ok(())
     }
 
     #[test]
-    fn fails_to_read_withdrawal_with_negative_amount() -> Result<()> {
+    fn fails_to_read_dispute_with_negative_amount() -> Result<()> {
         assert_err!(
-            CsvTransaction::to_transaction(CsvTransaction {
-                transaction_type: "withdrawal".to_string(),
-                client_id: 1,
-                transaction_id: 1,
-                amount: Some(dec!(-1)),
-            }),
+            CsvTransaction::to_transaction(
+                &CsvTransaction {
+                    transaction_type: "dispute".to_string(),
+                    client_id: 1,
+                    transaction_id: 1,
+                    amount: Some(dec!(-1)),
+                    row_id: None,
+                    target: None,
+                },
+                &ParseOptions::default()
+            ),
             "Failed to read transaction with ID 1: Amount is negative or zero"
         );
         Ok(())
     }
+
+    #[test]
+    fn reads_the_same_transactions_from_byte_records() -> Result<()> {
+        let rows = [
+            vec!["deposit", "1", "1", "1.5"],
+            vec![" withdrawal ", " 2 ", " 2 ", " 0.25\r"],
+            vec!["dispute", "1", "1", ""],
+            vec!["dispute", "1", "1", "0.5"],
+            vec!["resolve", "1", "1", ""],
+            vec!["chargeback", "1", "1", ""],
+            vec!["hold", "3", "4", "$1,000"],
+            vec!["release", "3", "5", "2"],
+            vec!["settle", "2", "2", ""],
+            vec!["refund", "1", "6", "1"],
+            vec!["deposit", "1", "7", "abc"],
+            vec!["deposit", "x", "8", "1"],
+            vec!["deposit", "1", "9", "1.25e1"],
+            vec!["deposit", "1", "10", "1e-30"],
+        ];
+        let options = ParseOptions {
+            sanitize_amounts: true,
+            ..Default::default()
+        };
+
+        for row in rows {
+            let string_record = StringRecord::from(row.clone());
+            let mut byte_record = ByteRecord::from(row);
+
+            let owned = CsvTransaction::from_string_record(string_record, &options);
+            let borrowed = CsvTransaction::from_byte_record(&mut byte_record, &options);
+
+            match (owned, borrowed) {
+                (Ok(owned), Ok(borrowed)) => {
+                    assert_eq!(owned.transaction_type, borrowed.transaction_type);
+                    assert_eq!(owned.client_id, borrowed.client_id);
+                    assert_eq!(owned.transaction_id, borrowed.transaction_id);
+                    assert_eq!(owned.amount, borrowed.amount);
+                    let to_transaction = |transaction: Result<_>| {
+                        transaction
+                            .map(|transaction| format!("{:?}", transaction))
+                            .map_err(|err| err.to_string())
+                    };
+                    assert_eq!(
+                        to_transaction(owned.to_transaction(&options)),
+                        to_transaction(borrowed.to_transaction(&options))
+                    );
+                }
+                (owned, borrowed) => assert_eq!(
+                    owned.map(|_| ()).map_err(|err| err.to_string()),
+                    borrowed.map(|_| ()).map_err(|err| err.to_string())
+                ),
+            }
+        }
+        Ok(())
+    }
 }
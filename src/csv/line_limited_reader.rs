@@ -0,0 +1,42 @@
+use std::io::{Error, ErrorKind, Read, Result};
+
+/// Fails a read once any line grows beyond `max_line_bytes`, so a single huge line in
+/// hostile input is rejected before the CSV parser buffers all of it.
+#[derive(Debug)]
+pub struct LineLimitedReader<R> {
+    inner: R,
+    max_line_bytes: usize,
+    line_bytes: usize,
+}
+
+impl<R: Read> LineLimitedReader<R> {
+    pub fn new(inner: R, max_line_bytes: usize) -> LineLimitedReader<R> {
+        LineLimitedReader {
+            inner,
+            max_line_bytes,
+            line_bytes: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for LineLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        for &byte in &buf[..read] {
+            if byte == b'\n' {
+                self.line_bytes = 0;
+            } else {
+                self.line_bytes += 1;
+                if self.line_bytes > self.max_line_bytes {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Line exceeds the maximum of {} bytes", self.max_line_bytes),
+                    ));
+                }
+            }
+        }
+
+        Ok(read)
+    }
+}
@@ -1,2 +1,7 @@
+pub mod client_labels;
+pub mod csv_fixture;
+pub mod csv_opening_balance;
 pub mod csv_reader;
 pub mod csv_transaction;
+pub mod line_limited_reader;
+pub mod parse_options;
@@ -0,0 +1,21 @@
+use super::client_labels::ClientLabels;
+
+/// Controls how leniently CSV rows are converted into transactions.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Skip zero-amount deposits and withdrawals instead of rejecting them.
+    pub allow_zero_amounts: bool,
+    /// Strip a leading currency symbol and thousands separators from amounts, e.g. `$1,234.56`.
+    pub sanitize_amounts: bool,
+    /// Ignore whitespace inside the type, e.g. `dep osit`, as written by some broken exports.
+    pub tolerant_whitespace_type: bool,
+    /// Accept `reset` transactions, which should never appear in production input.
+    pub allow_reset: bool,
+    /// Accept the administrative `freeze` and `unfreeze` transactions.
+    pub allow_admin: bool,
+    /// Treat amounts as whole numbers of minor units, dividing them by 10^scale.
+    pub input_scale: Option<u32>,
+    /// Read the client column as a string identifier, interned into a numeric client ID,
+    /// rather than as a number.
+    pub client_labels: Option<ClientLabels>,
+}
@@ -0,0 +1,175 @@
+use anyhow::{Error, Result};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Rules that change how transactions are applied to an account, to suit different
+/// risk policies. The default matches the behaviour described in the README.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountPolicy {
+    /// What to do with a dispute whose funds have already been withdrawn.
+    pub dispute_policy: DisputePolicy,
+    /// Reject resolves and chargebacks that refer to a transaction which isn't disputed,
+    /// instead of ignoring them.
+    pub strict_dispute_flow: bool,
+    /// Reject a deposit, withdrawal, hold or release whose transaction ID the account has
+    /// already seen, instead of ignoring it as a duplicate. This catches IDs reused across
+    /// types, such as a deposit with the ID of an earlier withdrawal.
+    pub strict_transaction_ids: bool,
+    /// Remember only this many of the most recent deposit, withdrawal, hold and release IDs
+    /// for deduplication, bounding the memory used. A duplicate of an older transaction is
+    /// not detected and is applied again. Without this, every ID is remembered.
+    pub dedupe_window: Option<usize>,
+    /// Log and ignore a dispute that would overflow the held balance, rather than failing.
+    pub ignore_held_overflow: bool,
+    /// Leave a resolved deposit's funds held, as a manual hold to be freed by a `release`,
+    /// rather than returning them to the available balance.
+    pub resolve_keeps_held: bool,
+    /// Hold withdrawn funds as pending until a `settle` finalises the withdrawal, rather
+    /// than deducting them immediately. A pending withdrawal can be disputed to cancel it.
+    pub hold_withdrawals: bool,
+    /// Remember settled withdrawals so that a dispute targeting a withdrawal can claim one
+    /// back. Without this, settled withdrawals are forgotten and such disputes are ignored.
+    pub dispute_withdrawals: bool,
+    /// Round deposit and withdrawal amounts to this many decimal places before applying
+    /// them, so balances stay at a consistent scale. Without this, full precision is kept.
+    pub amount_scale: Option<u32>,
+    /// How amounts are rounded to `amount_scale`.
+    pub amount_rounding: AmountRounding,
+    /// Log a warning with the transaction ID whenever an amount has more decimal places
+    /// than `amount_scale` and is rounded.
+    pub warn_on_rounding: bool,
+    /// Charged on each withdrawal on top of the amount withdrawn.
+    pub withdrawal_fee: WithdrawalFee,
+    /// What to do with transactions for an account that was locked before processing,
+    /// which unlike one locked by a chargeback has nothing in this input explaining it.
+    pub seeded_lock_policy: SeededLockPolicy,
+}
+
+/// How to handle a dispute for more than the available balance, which happens when the
+/// disputed deposit has since been withdrawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisputePolicy {
+    /// Hold the disputed amount anyway, leaving the available balance negative.
+    /// The total balance is unchanged, so the client owes the shortfall.
+    #[default]
+    AllowNegative,
+    /// Reject the dispute, leaving the account unchanged.
+    RejectNegative,
+}
+
+/// How to handle a transaction for an account seeded as locked, e.g. by `--start-locked`
+/// or the opening balances.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeededLockPolicy {
+    /// Reject the transaction as for any locked account.
+    #[default]
+    Reject,
+    /// Ignore the transaction, so the account is written exactly as it was seeded.
+    Skip,
+}
+
+impl FromStr for SeededLockPolicy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<SeededLockPolicy> {
+        match value {
+            "reject" => Ok(SeededLockPolicy::Reject),
+            "skip" => Ok(SeededLockPolicy::Skip),
+            _ => Err(Error::msg(format!(
+                "Unknown seeded lock policy {}. Expected reject or skip",
+                value
+            ))),
+        }
+    }
+}
+
+/// How an amount with more decimal places than the account scale is rounded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AmountRounding {
+    #[default]
+    HalfUp,
+    HalfEven,
+    /// Drop the excess decimal places.
+    Truncate,
+}
+
+impl AmountRounding {
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            AmountRounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            AmountRounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            AmountRounding::Truncate => RoundingStrategy::ToZero,
+        }
+    }
+}
+
+impl FromStr for AmountRounding {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<AmountRounding> {
+        match value {
+            "half-up" => Ok(AmountRounding::HalfUp),
+            "half-even" => Ok(AmountRounding::HalfEven),
+            "truncate" => Ok(AmountRounding::Truncate),
+            _ => Err(Error::msg(format!(
+                "Unknown amount rounding {}. Expected half-up, half-even or truncate",
+                value
+            ))),
+        }
+    }
+}
+
+/// A fee charged per withdrawal, deducted from the account along with the amount.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WithdrawalFee {
+    #[default]
+    None,
+    /// The same fee for every withdrawal.
+    Flat(Decimal),
+    /// A percentage of the amount withdrawn, e.g. 1.5 for 1.5%.
+    Percentage(Decimal),
+}
+
+impl WithdrawalFee {
+    /// The fee for withdrawing `amount`.
+    pub fn fee_for(self, amount: Decimal) -> Option<Decimal> {
+        match self {
+            WithdrawalFee::None => Some(Decimal::ZERO),
+            WithdrawalFee::Flat(fee) => Some(fee),
+            WithdrawalFee::Percentage(percentage) => amount
+                .checked_mul(percentage)
+                .and_then(|fee| fee.checked_div(Decimal::ONE_HUNDRED)),
+        }
+    }
+}
+
+impl FromStr for WithdrawalFee {
+    type Err = Error;
+
+    /// Parses a flat fee such as `1.50`, or a percentage such as `2%`.
+    fn from_str(value: &str) -> Result<WithdrawalFee> {
+        let (fee, is_percentage) = match value.strip_suffix('%') {
+            Some(percentage) => (percentage, true),
+            None => (value, false),
+        };
+        let fee = Decimal::from_str(fee.trim())
+            .map_err(|err| Error::msg(format!("Invalid withdrawal fee {}: {}", value, err)))?;
+        if fee.is_sign_negative() {
+            return Err(Error::msg(format!(
+                "Invalid withdrawal fee {}: must not be negative",
+                value
+            )));
+        }
+
+        Ok(match is_percentage {
+            true => WithdrawalFee::Percentage(fee),
+            false => WithdrawalFee::Flat(fee),
+        })
+    }
+}
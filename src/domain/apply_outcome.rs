@@ -0,0 +1,28 @@
+/// What happened when a transaction was applied to an account without error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyOutcome {
+    Applied,
+    /// The transaction was deliberately ignored and left the account unchanged.
+    Ignored(IgnoredReason),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoredReason {
+    /// A transaction with the same ID has already been applied.
+    AlreadyApplied,
+    /// A dispute referred to a transaction that isn't an undisputed deposit.
+    NotDisputable,
+    /// A resolve or chargeback referred to a transaction that isn't disputed.
+    NotDisputed,
+    /// A dispute, resolve or chargeback was the first transaction for its client, so there
+    /// was no deposit it could refer to. This usually means the input is out of order.
+    BeforeAnyDeposit,
+    /// A settle referred to a transaction that isn't a pending withdrawal.
+    NotPending,
+    /// An unfreeze referred to an account that isn't frozen.
+    NotFrozen,
+    /// A dispute would have overflowed the held balance and the policy is to ignore it.
+    HeldBalanceOverflow,
+    /// The account was locked before processing and the policy is to skip its transactions.
+    SeededLocked,
+}
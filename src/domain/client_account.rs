@@ -1,72 +1,331 @@
-use super::transaction::{Transaction, TransactionId};
-use crate::domain::transaction::{Deposit, TransactionAction, Withdrawal};
+use super::{
+    account_policy::{AccountPolicy, DisputePolicy, SeededLockPolicy},
+    apply_outcome::{ApplyOutcome, IgnoredReason},
+    deposit_status::DepositStatus,
+    lock_reason::LockReason,
+    transaction::{Transaction, TransactionId},
+};
+use crate::domain::transaction::{
+    Deposit, Dispute, DisputeTarget, Hold, Release, TransactionAction, Withdrawal,
+};
 use anyhow::{Error, Result};
+use csv::StringRecord;
 use rust_decimal::Decimal;
-use std::collections::{hash_map::Entry, HashMap};
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
 
 pub type ClientId = u16;
 
-#[derive(Debug)]
+/// The number of decimal places balances are written with by default.
+pub const OUTPUT_PRECISION: u32 = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientAccount {
     pub client_id: ClientId,
+    /// The string identifier the client was given in the input, when clients are
+    /// identified by strings rather than numbers. It's written in place of the client ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
     pub available_balance: Decimal,
     pub held_balance: Decimal,
     pub total_balance: Decimal,
     pub locked: bool,
+    /// What locked the account, if it's locked.
+    pub lock_reason: Option<LockReason>,
+    /// The part of the held balance reserved by manual holds rather than disputes.
+    pub manually_held_balance: Decimal,
+    /// The sum of every deposit applied, regardless of later withdrawals or disputes.
+    pub gross_deposits: Decimal,
+    /// The sum of the withdrawal fees charged, which have left the total balance.
+    pub collected_fees: Decimal,
+    /// Withdrawals whose funds are held until settled, when the policy holds withdrawals.
+    pub pending_withdrawals: HashMap<TransactionId, Withdrawal>,
     pub good_deposits: HashMap<TransactionId, Deposit>,
     pub disputed_deposits: HashMap<TransactionId, Deposit>,
     pub chargedback_deposits: HashMap<TransactionId, Deposit>,
+    /// Withdrawals whose funds have left the account, which a dispute targeting
+    /// withdrawals can claim back.
+    pub settled_withdrawals: HashMap<TransactionId, Withdrawal>,
+    pub disputed_withdrawals: HashMap<TransactionId, Withdrawal>,
     pub applied_transaction_ids: HashMap<TransactionId, ()>,
+    /// The remembered transaction IDs from oldest to newest, kept only with a dedupe window.
+    pub applied_order: VecDeque<TransactionId>,
+    /// The IDs of the dispute, resolve and chargeback rows applied, for rows that have one.
+    pub applied_row_ids: HashMap<TransactionId, ()>,
+    pub policy: AccountPolicy,
 }
 
 impl ClientAccount {
     pub fn new(client_id: ClientId) -> ClientAccount {
+        ClientAccount::with_policy(client_id, AccountPolicy::default())
+    }
+    pub fn with_policy(client_id: ClientId, policy: AccountPolicy) -> ClientAccount {
         ClientAccount {
             client_id,
+            label: None,
             available_balance: Decimal::ZERO,
             held_balance: Decimal::ZERO,
             total_balance: Decimal::ZERO,
             locked: false,
+            lock_reason: None,
+            manually_held_balance: Decimal::ZERO,
+            gross_deposits: Decimal::ZERO,
+            collected_fees: Decimal::ZERO,
+            pending_withdrawals: HashMap::new(),
             good_deposits: HashMap::new(),
             disputed_deposits: HashMap::new(),
             chargedback_deposits: HashMap::new(),
+            settled_withdrawals: HashMap::new(),
+            disputed_withdrawals: HashMap::new(),
             applied_transaction_ids: HashMap::new(),
+            applied_order: VecDeque::new(),
+            applied_row_ids: HashMap::new(),
+            policy,
+        }
+    }
+    /// Renders the client, available, held, total and locked fields as written in the
+    /// output, with balances at a fixed number of decimal places.
+    pub fn summary_row(&self, precision: u32) -> [String; 5] {
+        self.summary_row_with(|balance| format!("{:.*}", precision as usize, balance))
+    }
+    /// Renders the account as the CSV record the default output writes for it.
+    pub fn to_csv_record(&self) -> StringRecord {
+        self.summary_row(OUTPUT_PRECISION).into_iter().collect()
+    }
+    /// Renders the same fields as [`ClientAccount::summary_row`] using a custom balance format.
+    pub fn summary_row_with(&self, format_balance: impl Fn(Decimal) -> String) -> [String; 5] {
+        [
+            self.client_label(),
+            format_balance(self.available_balance),
+            format_balance(self.held_balance),
+            format_balance(self.total_balance),
+            self.locked.to_string(),
+        ]
+    }
+    /// The client as written in the output: its label if it has one, or else its ID.
+    pub fn client_label(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.client_id.to_string(),
+        }
+    }
+    /// Checks that the held balance is exactly the sum of the currently disputed deposits,
+    /// plus any manual holds and pending withdrawals. A mismatch means the dispute arithmetic
+    /// has gone wrong somewhere.
+    pub fn held_matches_disputes(&self) -> bool {
+        self.expected_held_balance() == self.held_balance
+    }
+    /// The held balance implied by the currently disputed deposits, manual holds and
+    /// pending withdrawals.
+    pub fn expected_held_balance(&self) -> Decimal {
+        let disputed_total: Decimal = self
+            .disputed_deposits
+            .values()
+            .map(|deposit| deposit.amount)
+            .sum();
+        let pending_total: Decimal = self
+            .pending_withdrawals
+            .values()
+            .chain(self.disputed_withdrawals.values())
+            .map(|withdrawal| withdrawal.amount)
+            .sum();
+
+        disputed_total + pending_total + self.manually_held_balance
+    }
+    /// Looks up the deposit with the given transaction ID. A partially disputed deposit
+    /// is reported as disputed.
+    pub fn deposit_status(&self, transaction_id: TransactionId) -> DepositStatus {
+        if self.chargedback_deposits.contains_key(&transaction_id) {
+            DepositStatus::ChargedBack
+        } else if self.disputed_deposits.contains_key(&transaction_id) {
+            DepositStatus::Disputed
+        } else if self.good_deposits.contains_key(&transaction_id) {
+            DepositStatus::Applied
+        } else {
+            DepositStatus::Unknown
+        }
+    }
+    /// Whether the transaction repeats a row or transaction ID the account has already
+    /// applied, so it will be ignored, or rejected under strict transaction IDs.
+    pub fn is_repeated(&self, transaction: &Transaction, row_id: Option<TransactionId>) -> bool {
+        let has_deduped_id = matches!(
+            transaction.action,
+            TransactionAction::Deposit(_)
+                | TransactionAction::Withdrawal(_)
+                | TransactionAction::Hold(_)
+                | TransactionAction::Release(_)
+        );
+        row_id.is_some_and(|row_id| self.applied_row_ids.contains_key(&row_id))
+            || (has_deduped_id
+                && self
+                    .applied_transaction_ids
+                    .contains_key(&transaction.transaction_id))
+    }
+    /// Applies a transaction from a row with its own ID, ignoring it if a row with the same
+    /// ID has already been applied. Under strict transaction IDs, that's an error.
+    pub fn apply_transaction_row(
+        &mut self,
+        transaction: Transaction,
+        row_id: Option<TransactionId>,
+    ) -> Result<ApplyOutcome> {
+        let Some(row_id) = row_id else {
+            return self.apply_transaction(transaction);
+        };
+
+        if self.applied_row_ids.contains_key(&row_id) {
+            if self.policy.strict_transaction_ids {
+                return Err(Error::msg(format!(
+                    "Failed to apply {}: Duplicate row id {}",
+                    transaction, row_id
+                )));
+            }
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied));
         }
+
+        let outcome = self.apply_transaction(transaction)?;
+        self.applied_row_ids.insert(row_id, ());
+        Ok(outcome)
     }
-    pub fn apply_transaction(&mut self, transaction: Transaction) -> Result<()> {
+    pub fn apply_transaction(&mut self, transaction: Transaction) -> Result<ApplyOutcome> {
         let transaction_id = transaction.transaction_id;
         let transaction_description = transaction.to_string();
 
-        if self.locked {
+        // Even resets and unfreezes are skipped, so the account stays exactly as seeded
+        if self.locked
+            && self.lock_reason == Some(LockReason::Seeded)
+            && self.policy.seeded_lock_policy == SeededLockPolicy::Skip
+        {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::SeededLocked));
+        }
+        if self.locked
+            && !matches!(
+                transaction.action,
+                TransactionAction::Reset | TransactionAction::Unfreeze
+            )
+        {
             return Err(Error::msg(format!(
                 "Failed to apply {}: Account is locked",
                 transaction_description
             )));
         }
 
-        match transaction.action {
+        let outcome = match transaction.action {
             TransactionAction::Deposit(deposit) => self.apply_deposit(transaction_id, deposit),
             TransactionAction::Withdrawal(withdrawal) => {
                 self.apply_withdrawal(transaction_id, withdrawal)
             }
-            TransactionAction::Dispute => self.apply_dispute(transaction_id),
+            TransactionAction::Dispute(dispute) => self.apply_dispute(transaction_id, dispute),
             TransactionAction::Resolve => self.apply_resolve(transaction_id),
             TransactionAction::Chargeback => self.apply_chargeback(transaction_id),
+            TransactionAction::Reset => self.apply_reset(),
+            TransactionAction::Hold(hold) => self.apply_hold(transaction_id, hold),
+            TransactionAction::Release(release) => self.apply_release(transaction_id, release),
+            TransactionAction::Settle => self.apply_settle(transaction_id),
+            TransactionAction::Freeze => self.apply_freeze(transaction_id),
+            TransactionAction::Unfreeze => self.apply_unfreeze(),
         }
         .map_err(|err| {
             Error::msg(format!(
                 "Failed to apply {}: {}",
-                transaction_description,
-                err.to_string()
+                transaction_description, err
             ))
+        })?;
+
+        // Catches arithmetic regressions during development without costing release builds
+        debug_assert!(
+            self.balances_add_up(),
+            "Balances don't add up after applying {}: {} available + {} held != {} total",
+            transaction_description,
+            self.available_balance,
+            self.held_balance,
+            self.total_balance
+        );
+        Ok(outcome)
+    }
+    /// Checks that the available and held balances add up to the total balance.
+    pub fn balances_add_up(&self) -> bool {
+        self.available_balance.checked_add(self.held_balance) == Some(self.total_balance)
+    }
+
+    /// Rounds an incoming amount to the policy's scale, if it has one.
+    fn round_amount(&self, transaction_id: TransactionId, amount: Decimal) -> Result<Decimal> {
+        let Some(scale) = self.policy.amount_scale else {
+            return Ok(amount);
+        };
+
+        let rounded = amount.round_dp_with_strategy(scale, self.policy.amount_rounding.strategy());
+        if rounded.is_zero() {
+            return Err(Error::msg(format!(
+                "Amount {} rounds to zero at scale {}",
+                amount, scale
+            )));
+        }
+        if self.policy.warn_on_rounding && rounded != amount {
+            log::warn!(
+                "Rounding amount {} of transaction ID {} for client {} to {}",
+                amount,
+                transaction_id,
+                self.client_id,
+                rounded
+            );
+        }
+
+        Ok(rounded)
+    }
+
+    /// Whether a deposit, withdrawal, hold or release with this ID has already been
+    /// applied, so this one should be ignored. Under strict transaction IDs, it's an error.
+    fn is_already_applied(&self, transaction_id: TransactionId) -> Result<bool> {
+        if !self.applied_transaction_ids.contains_key(&transaction_id) {
+            return Ok(false);
+        }
+        if self.policy.strict_transaction_ids {
+            return Err(Error::msg("Duplicate transaction id"));
+        }
+        Ok(true)
+    }
+
+    /// Remembers the transaction ID as applied. With a dedupe window, the oldest ID is
+    /// forgotten once the window is full.
+    fn record_applied(&mut self, transaction_id: TransactionId) {
+        self.applied_transaction_ids.insert(transaction_id, ());
+
+        let Some(window) = self.policy.dedupe_window else {
+            return;
+        };
+        self.applied_order.push_back(transaction_id);
+        while self.applied_order.len() > window {
+            if let Some(oldest) = self.applied_order.pop_front() {
+                self.applied_transaction_ids.remove(&oldest);
+            }
+        }
+    }
+
+    fn withdrawal_fee(&self, amount: Decimal) -> Result<Decimal> {
+        let fee = self
+            .policy
+            .withdrawal_fee
+            .fee_for(amount)
+            .ok_or(Error::msg("Withdrawal fee overflow"))?;
+        Ok(match self.policy.amount_scale {
+            Some(scale) => {
+                fee.round_dp_with_strategy(scale, self.policy.amount_rounding.strategy())
+            }
+            None => fee,
         })
     }
 
-    fn apply_deposit(&mut self, transaction_id: TransactionId, deposit: Deposit) -> Result<()> {
-        if self.applied_transaction_ids.contains_key(&transaction_id) {
-            return Ok(());
+    fn apply_deposit(
+        &mut self,
+        transaction_id: TransactionId,
+        mut deposit: Deposit,
+    ) -> Result<ApplyOutcome> {
+        if self.is_already_applied(transaction_id)? {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied));
         }
 
+        deposit.amount = self.round_amount(transaction_id, deposit.amount)?;
+
         // The total balance will always be at least as high as the
         // available balance so let's check the total balance won't overflow.
         // If it won't, we can be sure the available balance won't overflow
@@ -76,62 +335,239 @@ impl ClientAccount {
             .checked_add(deposit.amount)
             .ok_or(Error::msg("Deposit would cause balance overflow"))?;
 
+        // Gross deposits are only reported, so reaching the limit shouldn't reject a deposit
+        self.gross_deposits = self.gross_deposits.saturating_add(deposit.amount);
         self.available_balance += deposit.amount;
         self.good_deposits.insert(transaction_id, deposit);
-        self.applied_transaction_ids.insert(transaction_id, ());
+        self.record_applied(transaction_id);
 
-        Ok(())
+        Ok(ApplyOutcome::Applied)
     }
 
     fn apply_withdrawal(
         &mut self,
         transaction_id: TransactionId,
-        withdrawal: Withdrawal,
-    ) -> Result<()> {
-        if self.applied_transaction_ids.contains_key(&transaction_id) {
-            return Ok(());
+        mut withdrawal: Withdrawal,
+    ) -> Result<ApplyOutcome> {
+        if self.is_already_applied(transaction_id)? {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied));
         }
 
-        if withdrawal.amount.gt(&self.available_balance) {
+        withdrawal.amount = self.round_amount(transaction_id, withdrawal.amount)?;
+        let fee = self.withdrawal_fee(withdrawal.amount)?;
+        let deducted = withdrawal
+            .amount
+            .checked_add(fee)
+            .ok_or(Error::msg("Withdrawal and fee overflow"))?;
+
+        if deducted.gt(&self.available_balance) {
             return Err(Error::msg("Insufficient available balance for withdrawal"));
         }
 
-        // The available balance can never underflow due to a withdrawal because
-        // a withdrawal cannot leave a negative balance. The total balance can
-        // never underflow because it will always be at least as high as the available balance
+        // Every checked value is worked out before the account changes, so a rejected
+        // withdrawal leaves it, and the fees collected, as they were
+        let available_balance = self.available_balance - fee;
+        let total_balance = self.total_balance - fee;
+
+        // The fee is charged as soon as the withdrawal is accepted, even if the withdrawal
+        // itself is held pending settlement
+        if self.policy.hold_withdrawals {
+            let held_balance = self
+                .held_balance
+                .checked_add(withdrawal.amount)
+                .ok_or(Error::msg("Withdrawal would cause held balance overflow"))?;
+            self.held_balance = held_balance;
+            self.available_balance = available_balance - withdrawal.amount;
+            self.total_balance = total_balance;
+            self.collected_fees = self.collected_fees.saturating_add(fee);
+            self.pending_withdrawals.insert(transaction_id, withdrawal);
+            self.record_applied(transaction_id);
+            return Ok(ApplyOutcome::Applied);
+        }
+
+        // Neither balance should underflow while the total is at least the available
+        // balance, but features that allow negative balances can break that, so check
+
+        let available_balance = available_balance
+            .checked_sub(withdrawal.amount)
+            .ok_or(Error::msg("Withdrawal would underflow balance"))?;
+        let total_balance = total_balance
+            .checked_sub(withdrawal.amount)
+            .ok_or(Error::msg("Withdrawal would underflow balance"))?;
+
+        self.available_balance = available_balance;
+        self.total_balance = total_balance;
+        self.collected_fees = self.collected_fees.saturating_add(fee);
+        self.record_settled(transaction_id, withdrawal);
+        self.record_applied(transaction_id);
+
+        Ok(ApplyOutcome::Applied)
+    }
+
+    fn apply_settle(&mut self, transaction_id: TransactionId) -> Result<ApplyOutcome> {
+        let Some(withdrawal) = self.pending_withdrawals.remove(&transaction_id) else {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::NotPending));
+        };
 
-        self.available_balance -= withdrawal.amount;
+        // Neither balance can underflow because the withdrawal's amount is part of both
+        self.held_balance -= withdrawal.amount;
         self.total_balance -= withdrawal.amount;
-        self.applied_transaction_ids.insert(transaction_id, ());
+        self.record_settled(transaction_id, withdrawal);
 
-        Ok(())
+        Ok(ApplyOutcome::Applied)
     }
 
-    fn apply_dispute(&mut self, transaction_id: TransactionId) -> Result<()> {
-        match self.good_deposits.entry(transaction_id) {
-            Entry::Occupied(entry) => {
-                let deposit = entry.get();
+    /// Remembers a settled withdrawal for later disputes, if the policy allows disputing
+    /// withdrawals. Otherwise it's not kept, since every withdrawal would stay in memory.
+    fn record_settled(&mut self, transaction_id: TransactionId, withdrawal: Withdrawal) {
+        if self.policy.dispute_withdrawals {
+            self.settled_withdrawals.insert(transaction_id, withdrawal);
+        }
+    }
+
+    fn apply_dispute(
+        &mut self,
+        transaction_id: TransactionId,
+        dispute: Dispute,
+    ) -> Result<ApplyOutcome> {
+        // Disputing a withdrawal that hasn't settled cancels it, returning its funds. There's
+        // no partial cancel, so an amount must cover the whole withdrawal
+        if dispute.target != Some(DisputeTarget::Deposit) {
+            let pending_amount = self
+                .pending_withdrawals
+                .get(&transaction_id)
+                .map(|withdrawal| withdrawal.amount);
+            if let Some(pending_amount) = pending_amount {
+                if dispute
+                    .amount
+                    .is_some_and(|amount| amount != pending_amount)
+                {
+                    return Err(Error::msg(
+                        "Dispute amount must match the pending withdrawal amount",
+                    ));
+                }
+                self.pending_withdrawals.remove(&transaction_id);
+                self.held_balance -= pending_amount;
+                self.available_balance += pending_amount;
+                return Ok(ApplyOutcome::Applied);
+            }
+        }
+        if dispute.target == Some(DisputeTarget::Withdrawal) {
+            return self.apply_withdrawal_dispute(transaction_id, dispute.amount);
+        }
+
+        // A deposit can only have one active dispute, even if it only covers part of it
+        if self.disputed_deposits.contains_key(&transaction_id) {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::NotDisputable));
+        }
+        let Some(deposit) = self.good_deposits.get(&transaction_id) else {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::NotDisputable));
+        };
+
+        let deposit_amount = deposit.amount;
+        let amount = match dispute.amount {
+            Some(amount) if amount.gt(&deposit_amount) => {
+                return Err(Error::msg("Dispute amount exceeds the deposit amount"));
+            }
+            Some(amount) => amount,
+            None => deposit_amount,
+        };
+
+        if self.policy.dispute_policy == DisputePolicy::RejectNegative
+            && amount.gt(&self.available_balance)
+        {
+            return Err(Error::msg("Dispute would overdraw available balance"));
+        }
 
-                // The held balance could overflow if there are already active disputes.
-                // The available balance cannot underflow because either the held balance
-                // would overflow and get caught here or a chargeback would lock the account.
+        // The held balance could overflow if there are already active disputes.
+        // The available balance cannot underflow because either the held balance
+        // would overflow and get caught here or a chargeback would lock the account.
+
+        let Some(held_balance) = self.held_balance.checked_add(amount) else {
+            if self.policy.ignore_held_overflow {
+                log::warn!(
+                    "Ignoring dispute for transaction ID {} of client {}: Held balance would overflow",
+                    transaction_id,
+                    self.client_id
+                );
+                return Ok(ApplyOutcome::Ignored(IgnoredReason::HeldBalanceOverflow));
+            }
+            return Err(Error::msg("Dispute would cause held balance overflow"));
+        };
 
-                self.held_balance = self
-                    .held_balance
-                    .checked_add(deposit.amount)
-                    .ok_or(Error::msg("Dispute would cause held balance overflow"))?;
+        self.held_balance = held_balance;
+        self.available_balance -= amount;
 
-                self.available_balance -= deposit.amount;
-                self.disputed_deposits
-                    .insert(transaction_id, entry.remove());
+        // Any undisputed remainder of the deposit stays in the good deposits
+        if amount == deposit_amount {
+            self.good_deposits.remove(&transaction_id);
+        } else if let Some(deposit) = self.good_deposits.get_mut(&transaction_id) {
+            deposit.amount -= amount;
+        }
+        self.disputed_deposits
+            .insert(transaction_id, Deposit { amount });
 
-                Ok(())
+        Ok(ApplyOutcome::Applied)
+    }
+
+    /// Disputes a settled withdrawal. The disputed funds come back into the account's total
+    /// but are held, so the client can't spend them until the dispute ends.
+    fn apply_withdrawal_dispute(
+        &mut self,
+        transaction_id: TransactionId,
+        amount: Option<Decimal>,
+    ) -> Result<ApplyOutcome> {
+        if self.disputed_withdrawals.contains_key(&transaction_id) {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::NotDisputable));
+        }
+        let Some(withdrawal) = self.settled_withdrawals.get(&transaction_id) else {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::NotDisputable));
+        };
+
+        let withdrawal_amount = withdrawal.amount;
+        let amount = match amount {
+            Some(amount) if amount.gt(&withdrawal_amount) => {
+                return Err(Error::msg("Dispute amount exceeds the withdrawal amount"));
             }
-            Entry::Vacant(_) => Ok(()),
+            Some(amount) => amount,
+            None => withdrawal_amount,
+        };
+
+        let held_balance = self
+            .held_balance
+            .checked_add(amount)
+            .ok_or_else(|| Error::msg("Dispute would cause held balance overflow"))?;
+        let total_balance = self
+            .total_balance
+            .checked_add(amount)
+            .ok_or_else(|| Error::msg("Dispute would cause balance overflow"))?;
+        self.held_balance = held_balance;
+        self.total_balance = total_balance;
+
+        if amount == withdrawal_amount {
+            self.settled_withdrawals.remove(&transaction_id);
+        } else if let Some(withdrawal) = self.settled_withdrawals.get_mut(&transaction_id) {
+            withdrawal.amount -= amount;
         }
+        self.disputed_withdrawals
+            .insert(transaction_id, Withdrawal { amount });
+
+        Ok(ApplyOutcome::Applied)
     }
 
-    fn apply_resolve(&mut self, transaction_id: TransactionId) -> Result<()> {
+    fn apply_resolve(&mut self, transaction_id: TransactionId) -> Result<ApplyOutcome> {
+        // Resolving a withdrawal dispute lets the withdrawal stand, so its held funds leave
+        // the account again
+        if let Some(withdrawal) = self.disputed_withdrawals.remove(&transaction_id) {
+            self.held_balance -= withdrawal.amount;
+            self.total_balance -= withdrawal.amount;
+            self.settled_withdrawals
+                .entry(transaction_id)
+                .and_modify(|remainder| remainder.amount += withdrawal.amount)
+                .or_insert(withdrawal);
+            return Ok(ApplyOutcome::Applied);
+        }
+
         match self.disputed_deposits.entry(transaction_id) {
             Entry::Occupied(entry) => {
                 let deposit = entry.get();
@@ -140,17 +576,42 @@ impl ClientAccount {
                 // balance would have overflowed beforehand. The held balance cannot
                 // underflow because it's not possible to have a negative held balance.
 
+                if self.policy.resolve_keeps_held {
+                    // The funds become a manual hold, no longer tied to the deposit, so the
+                    // resolved part can't be disputed again and taken from available twice
+                    self.manually_held_balance += deposit.amount;
+                    entry.remove();
+                    return Ok(ApplyOutcome::Applied);
+                }
                 self.available_balance += deposit.amount;
                 self.held_balance -= deposit.amount;
-                self.good_deposits.insert(transaction_id, entry.remove());
 
-                Ok(())
+                // Rejoin the disputed part with any undisputed remainder
+                let deposit = entry.remove();
+                self.good_deposits
+                    .entry(transaction_id)
+                    .and_modify(|remainder| remainder.amount += deposit.amount)
+                    .or_insert(deposit);
+
+                Ok(ApplyOutcome::Applied)
             }
-            Entry::Vacant(_) => Ok(()),
+            Entry::Vacant(_) if self.policy.strict_dispute_flow => {
+                Err(Error::msg("Resolve references a non-disputed transaction"))
+            }
+            Entry::Vacant(_) => Ok(ApplyOutcome::Ignored(IgnoredReason::NotDisputed)),
         }
     }
 
-    fn apply_chargeback(&mut self, transaction_id: TransactionId) -> Result<()> {
+    fn apply_chargeback(&mut self, transaction_id: TransactionId) -> Result<ApplyOutcome> {
+        // Charging back a withdrawal reverses it, releasing its held funds to the client
+        if let Some(withdrawal) = self.disputed_withdrawals.remove(&transaction_id) {
+            self.held_balance -= withdrawal.amount;
+            self.available_balance += withdrawal.amount;
+            self.locked = true;
+            self.lock_reason = Some(LockReason::Chargeback(transaction_id));
+            return Ok(ApplyOutcome::Applied);
+        }
+
         match self.disputed_deposits.entry(transaction_id) {
             Entry::Occupied(entry) => {
                 let deposit = entry.get();
@@ -164,22 +625,139 @@ impl ClientAccount {
                 self.chargedback_deposits
                     .insert(transaction_id, entry.remove());
                 self.locked = true;
+                self.lock_reason = Some(LockReason::Chargeback(transaction_id));
+
+                Ok(ApplyOutcome::Applied)
+            }
+            Entry::Vacant(_) if self.policy.strict_dispute_flow => Err(Error::msg(
+                "Chargeback references a non-disputed transaction",
+            )),
+            Entry::Vacant(_) => Ok(ApplyOutcome::Ignored(IgnoredReason::NotDisputed)),
+        }
+    }
+
+    /// Locks the account before any transactions are applied, as when continuing from
+    /// a prior state in which it was already locked.
+    pub fn seed_locked(&mut self) {
+        self.locked = true;
+        self.lock_reason = Some(LockReason::Seeded);
+    }
+
+    fn apply_freeze(&mut self, transaction_id: TransactionId) -> Result<ApplyOutcome> {
+        // Only reached when the account isn't already locked
+        self.locked = true;
+        self.lock_reason = Some(LockReason::Frozen(transaction_id));
 
-                Ok(())
+        Ok(ApplyOutcome::Applied)
+    }
+
+    fn apply_unfreeze(&mut self) -> Result<ApplyOutcome> {
+        match self.lock_reason {
+            Some(LockReason::Frozen(_)) => {
+                self.locked = false;
+                self.lock_reason = None;
+                Ok(ApplyOutcome::Applied)
             }
-            Entry::Vacant(_) => Ok(()),
+            Some(LockReason::Seeded) => Err(Error::msg("Account was locked before processing")),
+            _ if self.locked => Err(Error::msg("Account is locked by a chargeback")),
+            _ => Ok(ApplyOutcome::Ignored(IgnoredReason::NotFrozen)),
+        }
+    }
+
+    fn apply_reset(&mut self) -> Result<ApplyOutcome> {
+        // Transaction IDs that have already been applied are kept so that
+        // duplicates arriving after the reset are still ignored.
+
+        self.available_balance = Decimal::ZERO;
+        self.held_balance = Decimal::ZERO;
+        self.total_balance = Decimal::ZERO;
+        self.locked = false;
+        self.lock_reason = None;
+        self.manually_held_balance = Decimal::ZERO;
+        self.gross_deposits = Decimal::ZERO;
+        self.collected_fees = Decimal::ZERO;
+        self.pending_withdrawals.clear();
+        self.good_deposits.clear();
+        self.disputed_deposits.clear();
+        self.chargedback_deposits.clear();
+        self.settled_withdrawals.clear();
+        self.disputed_withdrawals.clear();
+
+        Ok(ApplyOutcome::Applied)
+    }
+
+    fn apply_hold(&mut self, transaction_id: TransactionId, hold: Hold) -> Result<ApplyOutcome> {
+        if self.is_already_applied(transaction_id)? {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied));
+        }
+
+        if hold.amount.gt(&self.available_balance) {
+            return Err(Error::msg("Insufficient available balance for hold"));
+        }
+
+        // The held balance could overflow if there are already active disputes or holds.
+        // The manually held balance is never more than the held balance so it cannot
+        // overflow if the held balance doesn't.
+
+        self.held_balance = self
+            .held_balance
+            .checked_add(hold.amount)
+            .ok_or(Error::msg("Hold would cause held balance overflow"))?;
+
+        self.available_balance -= hold.amount;
+        self.manually_held_balance += hold.amount;
+        self.record_applied(transaction_id);
+
+        Ok(ApplyOutcome::Applied)
+    }
+
+    fn apply_release(
+        &mut self,
+        transaction_id: TransactionId,
+        release: Release,
+    ) -> Result<ApplyOutcome> {
+        if self.is_already_applied(transaction_id)? {
+            return Ok(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied));
+        }
+
+        if release.amount.gt(&self.manually_held_balance) {
+            return Err(Error::msg("Insufficient manually held balance for release"));
         }
+
+        // The available balance cannot overflow due to a release because the total
+        // balance would have overflowed beforehand. The held balances cannot underflow
+        // because the release is no more than the manually held balance.
+
+        self.available_balance += release.amount;
+        self.held_balance -= release.amount;
+        self.manually_held_balance -= release.amount;
+        self.record_applied(transaction_id);
+
+        Ok(ApplyOutcome::Applied)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ClientAccount;
+    use super::{ClientAccount, ClientId};
     use crate::{
         assert_err::assert_err,
-        domain::transaction::{Deposit, Transaction, TransactionAction, Withdrawal},
+        domain::{
+            account_policy::{
+                AccountPolicy, AmountRounding, DisputePolicy, SeededLockPolicy, WithdrawalFee,
+            },
+            apply_outcome::{ApplyOutcome, IgnoredReason},
+            deposit_status::DepositStatus,
+            lock_reason::LockReason,
+            transaction::{
+                Deposit, Dispute, DisputeTarget, Hold, Release, Transaction, TransactionAction,
+                Withdrawal,
+            },
+        },
+        test_fixture::{capture_logs, logged_messages},
     };
     use anyhow::Result;
+    use csv::StringRecord;
     use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
 
@@ -212,290 +790,417 @@ mod tests {
     }
 
     #[test]
-    fn applies_withdrawals_with_sufficient_available_balance() -> Result<()> {
+    fn ignores_replayed_dispute_row() -> Result<()> {
         let client_id = 1;
         let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
+        let dispute = || Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
-            }),
-        })?;
-
-        client_account.apply_transaction(Transaction {
-            client_id,
-            transaction_id: 2,
-            action: TransactionAction::Withdrawal(Withdrawal {
-                amount: dec!(11.5555),
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
             }),
-        })?;
-
-        assert_eq!(dec!(1), client_account.available_balance);
-        assert_eq!(dec!(1), client_account.total_balance);
+        };
 
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 3,
-            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(1) }),
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
         })?;
+        client_account.apply_transaction_row(dispute(), Some(100))?;
+        client_account.apply_transaction_row(
+            Transaction {
+                client_id,
+                transaction_id: 1,
+                action: TransactionAction::Resolve,
+            },
+            Some(101),
+        )?;
+
+        // Without its row ID, the replayed dispute would dispute the deposit again
+        let outcome = client_account.apply_transaction_row(dispute(), Some(100))?;
+
+        assert_eq!(
+            ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied),
+            outcome
+        );
+        assert_eq!(dec!(10), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
 
-        assert_eq!(dec!(0), client_account.available_balance);
-        assert_eq!(dec!(0), client_account.total_balance);
         Ok(())
     }
 
     #[test]
-    fn applies_dispute() -> Result<()> {
+    fn fails_to_apply_replayed_dispute_row_with_strict_transaction_ids() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                strict_transaction_ids: true,
+                ..Default::default()
+            },
+        );
+        let dispute = || Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
             }),
-        })?;
-
-        assert_eq!(dec!(12.5555), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
+        };
 
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Dispute,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
         })?;
+        client_account.apply_transaction_row(dispute(), Some(100))?;
+        let result = client_account.apply_transaction_row(dispute(), Some(100));
 
-        assert_eq!(dec!(0), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.held_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert_err!(
+            result,
+            "Failed to apply dispute for transaction ID 1 for client 1: Duplicate row id 100"
+        );
+        assert_eq!(dec!(10), client_account.held_balance);
 
         Ok(())
     }
 
     #[test]
-    fn applies_dispute_after_withdrawal() -> Result<()> {
+    fn ignores_duplicate_inside_dedupe_window() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
+        let mut client_account = ClientAccount::with_policy(
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
-            }),
-        })?;
+            AccountPolicy {
+                dedupe_window: Some(2),
+                ..Default::default()
+            },
+        );
 
-        client_account.apply_transaction(Transaction {
-            client_id,
-            transaction_id: 2,
-            action: TransactionAction::Withdrawal(Withdrawal {
-                amount: dec!(12.5555),
-            }),
-        })?;
+        for transaction_id in [1, 2, 2] {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id,
+                action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+            })?;
+        }
 
-        client_account.apply_transaction(Transaction {
+        assert_eq!(dec!(10), client_account.total_balance);
+        assert_eq!(2, client_account.applied_transaction_ids.len());
+        Ok(())
+    }
+
+    #[test]
+    fn applies_duplicate_outside_dedupe_window() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Dispute,
-        })?;
+            AccountPolicy {
+                dedupe_window: Some(2),
+                ..Default::default()
+            },
+        );
 
-        assert_eq!(dec!(-12.5555), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.held_balance);
-        assert_eq!(dec!(0), client_account.total_balance);
+        // By the time 1 is repeated, 2 and 3 have pushed it out of the window
+        for transaction_id in [1, 2, 3, 1] {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id,
+                action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+            })?;
+        }
 
+        assert_eq!(dec!(20), client_account.total_balance);
+        assert_eq!(2, client_account.applied_transaction_ids.len());
         Ok(())
     }
 
     #[test]
-    fn applies_resolve() -> Result<()> {
+    fn fails_to_apply_deposit_reusing_a_withdrawal_id_with_strict_transaction_ids() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
+        let mut client_account = ClientAccount::with_policy(
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
-            }),
-        })?;
+            AccountPolicy {
+                strict_transaction_ids: true,
+                ..Default::default()
+            },
+        );
 
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Dispute,
+            transaction_id: 4,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
         })?;
-
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Resolve,
+            transaction_id: 5,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(3) }),
         })?;
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 5,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        });
 
-        assert_eq!(dec!(12.5555), client_account.available_balance);
-        assert_eq!(dec!(0), client_account.held_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 5 for client 1: Duplicate transaction id"
+        );
+        assert_eq!(dec!(7), client_account.total_balance);
 
         Ok(())
     }
 
     #[test]
-    fn applies_chargeback() -> Result<()> {
+    fn deducts_a_flat_withdrawal_fee() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
+        let mut client_account = ClientAccount::with_policy(
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
-            }),
-        })?;
+            AccountPolicy {
+                withdrawal_fee: WithdrawalFee::Flat(dec!(1.5)),
+                ..Default::default()
+            },
+        );
 
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Dispute,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
         })?;
-
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Chargeback,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
         })?;
 
-        assert_eq!(dec!(0), client_account.available_balance);
-        assert_eq!(dec!(0), client_account.held_balance);
-        assert_eq!(dec!(0), client_account.total_balance);
-        assert_eq!(true, client_account.locked);
+        assert_eq!(dec!(4.5), client_account.available_balance);
+        assert_eq!(dec!(4.5), client_account.total_balance);
+        assert_eq!(dec!(1.5), client_account.collected_fees);
 
         Ok(())
     }
 
     #[test]
-    fn applies_dispute_after_previous_dispute_is_resolved() -> Result<()> {
+    fn deducts_a_percentage_withdrawal_fee() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
-            client_id,
-            transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
-            }),
-        })?;
-
-        client_account.apply_transaction(Transaction {
+        let mut client_account = ClientAccount::with_policy(
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Dispute,
-        })?;
+            AccountPolicy {
+                withdrawal_fee: WithdrawalFee::Percentage(dec!(2.5)),
+                ..Default::default()
+            },
+        );
 
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Resolve,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(100) }),
         })?;
-
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 1,
-            action: TransactionAction::Dispute,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(40) }),
         })?;
 
-        assert_eq!(dec!(0), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.held_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert_eq!(dec!(59), client_account.available_balance);
+        assert_eq!(dec!(59), client_account.total_balance);
+        assert_eq!(dec!(1), client_account.collected_fees);
 
         Ok(())
     }
 
     #[test]
-    fn applies_transactions_out_of_order() -> Result<()> {
+    fn leaves_the_fee_uncharged_when_a_held_withdrawal_fails() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-
-        client_account.apply_transaction(Transaction {
+        let mut client_account = ClientAccount::with_policy(
             client_id,
-            transaction_id: 2,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
-            }),
-        })?;
-
-        assert_eq!(dec!(12.5555), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
+            AccountPolicy {
+                withdrawal_fee: WithdrawalFee::Flat(dec!(1)),
+                hold_withdrawals: true,
+                ..Default::default()
+            },
+        );
+        client_account.available_balance = dec!(10);
+        client_account.held_balance = Decimal::MAX;
+        client_account.total_balance = Decimal::MAX;
 
-        client_account.apply_transaction(Transaction {
+        let result = client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
-        })?;
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(5) }),
+        });
 
-        assert_eq!(dec!(13.5555), client_account.available_balance);
-        assert_eq!(dec!(13.5555), client_account.total_balance);
+        assert_err!(
+            result,
+            "Failed to apply withdrawal with transaction ID 1 for client 1: Withdrawal would cause held balance overflow"
+        );
+        assert_eq!(dec!(10), client_account.available_balance);
+        assert_eq!(Decimal::MAX, client_account.held_balance);
+        assert_eq!(Decimal::MAX, client_account.total_balance);
+        assert_eq!(Decimal::ZERO, client_account.collected_fees);
 
         Ok(())
     }
 
     #[test]
-    fn fails_to_apply_deposit_due_to_overflow() -> Result<()> {
+    fn fails_withdrawal_when_the_fee_exceeds_the_available_balance() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                withdrawal_fee: WithdrawalFee::Flat(dec!(1)),
+                ..Default::default()
+            },
+        );
 
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: Decimal::MAX,
-            }),
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
         })?;
-
+        // Enough for the amount alone but not with the fee on top
         let result = client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 2,
-            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(9.5) }),
         });
 
         assert_err!(
             result,
-            "Failed to apply deposit with transaction ID 2: Deposit would cause balance overflow"
+            "Failed to apply withdrawal with transaction ID 2 for client 1: Insufficient available balance for withdrawal"
         );
-        assert_eq!(Decimal::MAX, client_account.available_balance);
-        assert_eq!(Decimal::MAX, client_account.total_balance);
+        assert_eq!(dec!(10), client_account.available_balance);
+        assert_eq!(Decimal::ZERO, client_account.collected_fees);
+
         Ok(())
     }
 
     #[test]
-    fn fails_to_apply_withdrawal_with_insufficient_available_balance() -> Result<()> {
+    fn accumulates_gross_deposits_regardless_of_withdrawals() -> Result<()> {
         let client_id = 1;
         let mut client_account = ClientAccount::new(client_id);
+
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(2.5) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        })?;
+
+        assert_eq!(dec!(12.5), client_account.gross_deposits);
+        assert_eq!(dec!(8.5), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rounds_deposit_to_the_account_scale() -> Result<()> {
+        let client_id = 1;
+        for (amount_rounding, expected) in [
+            (AmountRounding::HalfUp, dec!(12.5556)),
+            (AmountRounding::Truncate, dec!(12.5555)),
+        ] {
+            let mut client_account = ClientAccount::with_policy(
+                client_id,
+                AccountPolicy {
+                    amount_scale: Some(4),
+                    amount_rounding,
+                    ..Default::default()
+                },
+            );
+
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: 1,
+                action: TransactionAction::Deposit(Deposit {
+                    amount: dec!(12.55555),
+                }),
+            })?;
+
+            assert_eq!(expected, client_account.available_balance);
+            assert_eq!(expected, client_account.total_balance);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn warns_and_rounds_excess_precision_amount() -> Result<()> {
+        capture_logs();
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                amount_scale: Some(4),
+                warn_on_rounding: true,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 31,
             action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
+                amount: dec!(2.123456),
             }),
         })?;
+
+        assert_eq!(dec!(2.1235), client_account.available_balance);
+        assert_eq!(dec!(2.1235), client_account.total_balance);
+        assert!(logged_messages().contains(
+            &"Rounding amount 2.123456 of transaction ID 31 for client 1 to 2.1235".to_string()
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_withdrawal_that_rounds_to_zero() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                amount_scale: Some(4),
+                amount_rounding: AmountRounding::Truncate,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        })?;
+
         let result = client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 2,
-            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(13) }),
+            action: TransactionAction::Withdrawal(Withdrawal {
+                amount: dec!(0.00009),
+            }),
         });
 
         assert_err!(
             result,
-            "Failed to apply withdrawal with transaction ID 2: Insufficient available balance for withdrawal"
+            "Failed to apply withdrawal with transaction ID 2 for client 1: Amount 0.00009 rounds to zero at scale 4"
         );
-        assert_eq!(dec!(12.5555), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert_eq!(dec!(1), client_account.available_balance);
+
         Ok(())
     }
 
     #[test]
-    fn fails_to_apply_dispute_due_to_overflow() -> Result<()> {
+    fn applies_withdrawals_with_sufficient_available_balance() -> Result<()> {
         let client_id = 1;
         let mut client_account = ClientAccount::new(client_id);
 
@@ -503,7 +1208,7 @@ mod tests {
             client_id,
             transaction_id: 1,
             action: TransactionAction::Deposit(Deposit {
-                amount: Decimal::MAX,
+                amount: dec!(12.5555),
             }),
         })?;
 
@@ -511,109 +1216,154 @@ mod tests {
             client_id,
             transaction_id: 2,
             action: TransactionAction::Withdrawal(Withdrawal {
-                amount: Decimal::MAX,
+                amount: dec!(11.5555),
             }),
         })?;
 
+        assert_eq!(dec!(1), client_account.available_balance);
+        assert_eq!(dec!(1), client_account.total_balance);
+
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 3,
-            action: TransactionAction::Deposit(Deposit {
-                amount: Decimal::MAX,
-            }),
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(1) }),
         })?;
 
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_dispute() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Dispute,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
         })?;
 
-        let result = client_account.apply_transaction(Transaction {
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 3,
-            action: TransactionAction::Dispute,
-        });
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
 
-        assert_err!(
-            result,
-            "Failed to apply dispute for transaction ID 3: Dispute would cause held balance overflow"
-        );
         assert_eq!(dec!(0), client_account.available_balance);
-        assert_eq!(Decimal::MAX, client_account.held_balance);
-        assert_eq!(Decimal::MAX, client_account.total_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
 
         Ok(())
     }
 
     #[test]
-    fn fails_to_act_on_a_locked_account() -> Result<()> {
+    fn applies_dispute_after_withdrawal_when_allowing_negative_available() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
-        client_account.locked = true;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                dispute_policy: DisputePolicy::AllowNegative,
+                ..Default::default()
+            },
+        );
 
-        let deposit_result = client_account.apply_transaction(Transaction {
+        client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
             action: TransactionAction::Deposit(Deposit {
                 amount: dec!(12.5555),
             }),
-        });
+        })?;
 
-        let withdrawal_result = client_account.apply_transaction(Transaction {
+        client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 2,
             action: TransactionAction::Withdrawal(Withdrawal {
                 amount: dec!(12.5555),
             }),
-        });
+        })?;
 
-        let dispute_result = client_account.apply_transaction(Transaction {
+        client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Dispute,
-        });
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
 
-        let resolve_result = client_account.apply_transaction(Transaction {
+        assert_eq!(dec!(-12.5555), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
+        assert_eq!(dec!(0), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_dispute_after_withdrawal_when_rejecting_negative_available() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                dispute_policy: DisputePolicy::RejectNegative,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Resolve,
-        });
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
 
-        let chargeback_result = client_account.apply_transaction(Transaction {
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(1) }),
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Chargeback,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
         });
 
         assert_err!(
-            deposit_result,
-            "Failed to apply deposit with transaction ID 1: Account is locked"
-        );
-        assert_err!(
-            withdrawal_result,
-            "Failed to apply withdrawal with transaction ID 2: Account is locked"
-        );
-        assert_err!(
-            dispute_result,
-            "Failed to apply dispute for transaction ID 1: Account is locked"
-        );
-        assert_err!(
-            resolve_result,
-            "Failed to apply resolve for transaction ID 1: Account is locked"
-        );
-        assert_err!(
-            chargeback_result,
-            "Failed to apply chargeback for transaction ID 1: Account is locked"
+            result,
+            "Failed to apply dispute for transaction ID 1 for client 1: Dispute would overdraw available balance"
         );
+        assert_eq!(dec!(11.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(11.5555), client_account.total_balance);
 
         Ok(())
     }
 
     #[test]
-    fn skips_applying_deposit_twice() -> Result<()> {
+    fn applies_dispute_within_available_when_rejecting_negative_available() -> Result<()> {
         let client_id = 1;
-        let mut client_account = ClientAccount::new(client_id);
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                dispute_policy: DisputePolicy::RejectNegative,
+                ..Default::default()
+            },
+        );
 
         client_account.apply_transaction(Transaction {
             client_id,
@@ -626,20 +1376,21 @@ mod tests {
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Deposit(Deposit {
-                amount: dec!(12.5555),
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
             }),
         })?;
 
-        assert_eq!(dec!(12.5555), client_account.available_balance);
-        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
         assert_eq!(dec!(12.5555), client_account.total_balance);
 
         Ok(())
     }
 
     #[test]
-    fn skips_applying_withdrawal_twice() -> Result<()> {
+    fn applies_partial_dispute() -> Result<()> {
         let client_id = 1;
         let mut client_account = ClientAccount::new(client_id);
 
@@ -653,29 +1404,34 @@ mod tests {
 
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 2,
-            action: TransactionAction::Withdrawal(Withdrawal {
-                amount: dec!(12.5555),
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: Some(dec!(2.5)),
+                target: None,
             }),
         })?;
 
+        assert_eq!(dec!(10.0555), client_account.available_balance);
+        assert_eq!(dec!(2.5), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert_eq!(dec!(10.0555), client_account.good_deposits[&1].amount);
+        assert!(client_account.held_matches_disputes());
+
         client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 2,
-            action: TransactionAction::Withdrawal(Withdrawal {
-                amount: dec!(12.5555),
-            }),
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
         })?;
 
-        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.available_balance);
         assert_eq!(dec!(0), client_account.held_balance);
-        assert_eq!(dec!(0), client_account.total_balance);
+        assert_eq!(dec!(12.5555), client_account.good_deposits[&1].amount);
 
         Ok(())
     }
 
     #[test]
-    fn skips_applying_dispute_to_unknown_transaction() -> Result<()> {
+    fn fails_to_apply_dispute_for_more_than_the_deposit() -> Result<()> {
         let client_id = 1;
         let mut client_account = ClientAccount::new(client_id);
 
@@ -687,21 +1443,27 @@ mod tests {
             }),
         })?;
 
-        client_account.apply_transaction(Transaction {
+        let result = client_account.apply_transaction(Transaction {
             client_id,
-            transaction_id: 100,
-            action: TransactionAction::Dispute,
-        })?;
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: Some(dec!(12.5556)),
+                target: None,
+            }),
+        });
 
+        assert_err!(
+            result,
+            "Failed to apply dispute for transaction ID 1 for client 1: Dispute amount exceeds the deposit amount"
+        );
         assert_eq!(dec!(12.5555), client_account.available_balance);
         assert_eq!(dec!(0), client_account.held_balance);
-        assert_eq!(dec!(12.5555), client_account.total_balance);
 
         Ok(())
     }
 
     #[test]
-    fn skips_applying_dispute_to_already_disputed_transaction() -> Result<()> {
+    fn applies_resolve() -> Result<()> {
         let client_id = 1;
         let mut client_account = ClientAccount::new(client_id);
 
@@ -716,19 +1478,1706 @@ mod tests {
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Dispute,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
         })?;
 
         client_account.apply_transaction(Transaction {
             client_id,
             transaction_id: 1,
-            action: TransactionAction::Dispute,
+            action: TransactionAction::Resolve,
         })?;
 
-        assert_eq!(dec!(0), client_account.available_balance);
-        assert_eq!(dec!(12.5555), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
         assert_eq!(dec!(12.5555), client_account.total_balance);
 
         Ok(())
     }
+
+    #[test]
+    fn ignores_a_second_dispute_of_a_deposit_resolved_keeping_funds_held() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                resolve_keeps_held: true,
+                ..Default::default()
+            },
+        );
+        let dispute = || Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        };
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        client_account.apply_transaction(dispute())?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
+        })?;
+        let outcome = client_account.apply_transaction(dispute())?;
+
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::NotDisputable), outcome);
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(10), client_account.held_balance);
+        assert_eq!(dec!(10), client_account.total_balance);
+        assert!(client_account.held_matches_disputes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_resolve_keeping_funds_held() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                resolve_keeps_held: true,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert!(client_account.disputed_deposits.is_empty());
+        assert!(client_account.held_matches_disputes());
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Release(Release {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_chargeback() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Chargeback,
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(0), client_account.total_balance);
+        assert_eq!(true, client_account.locked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn records_the_chargeback_that_locked_the_account() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        for transaction_id in [1, 2] {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id,
+                action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+            })?;
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id,
+                action: TransactionAction::Dispute(Dispute {
+                    amount: None,
+                    target: None,
+                }),
+            })?;
+        }
+        assert_eq!(None, client_account.lock_reason);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Chargeback,
+        })?;
+
+        assert!(client_account.locked);
+        assert_eq!(Some(LockReason::Chargeback(2)), client_account.lock_reason);
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_dispute_after_previous_dispute_is_resolved() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_balances_after_each_resolve_of_repeated_disputes() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        let balances = |account: &ClientAccount| {
+            (
+                account.available_balance,
+                account.held_balance,
+                account.total_balance,
+            )
+        };
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(3) }),
+        })?;
+        // Leaves less available than the deposit, so each dispute overdraws the account
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(10) }),
+        })?;
+        let original = balances(&client_account);
+
+        for _ in 0..3 {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: 1,
+                action: TransactionAction::Dispute(Dispute {
+                    amount: None,
+                    target: None,
+                }),
+            })?;
+            assert_eq!(
+                (dec!(-7), dec!(12.5555), dec!(5.5555)),
+                balances(&client_account)
+            );
+
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: 1,
+                action: TransactionAction::Resolve,
+            })?;
+            assert_eq!(original, balances(&client_account));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn disputes_whole_deposit_after_partial_dispute_is_resolved() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        let balances = |account: &ClientAccount| {
+            (
+                account.available_balance,
+                account.held_balance,
+                account.total_balance,
+            )
+        };
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        let original = balances(&client_account);
+
+        // The resolved part rejoins the undisputed remainder, so the whole deposit can be
+        // disputed next
+        for amount in [Some(dec!(4)), None] {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: 1,
+                action: TransactionAction::Dispute(Dispute {
+                    amount,
+                    target: None,
+                }),
+            })?;
+            let held = amount.unwrap_or(dec!(10));
+            assert_eq!((dec!(10) - held, held, dec!(10)), balances(&client_account));
+
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: 1,
+                action: TransactionAction::Resolve,
+            })?;
+            assert_eq!(original, balances(&client_account));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_transactions_out_of_order() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        })?;
+
+        assert_eq!(dec!(13.5555), client_account.available_balance);
+        assert_eq!(dec!(13.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_deposit_due_to_overflow() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 2 for client 1: Deposit would cause balance overflow"
+        );
+        assert_eq!(Decimal::MAX, client_account.available_balance);
+        assert_eq!(Decimal::MAX, client_account.total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_withdrawal_with_insufficient_available_balance() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(13) }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply withdrawal with transaction ID 2 for client 1: Insufficient available balance for withdrawal"
+        );
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_withdrawal_that_would_underflow_total_balance() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        // Only reachable if something has let the total fall below the available balance
+        client_account.available_balance = dec!(10);
+        client_account.total_balance = Decimal::MIN;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(10) }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply withdrawal with transaction ID 1 for client 1: Withdrawal would underflow balance"
+        );
+        assert_eq!(dec!(10), client_account.available_balance);
+        assert_eq!(Decimal::MIN, client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_dispute_due_to_overflow() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Deposit(Deposit {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply dispute for transaction ID 3 for client 1: Dispute would cause held balance overflow"
+        );
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(Decimal::MAX, client_account.held_balance);
+        assert_eq!(Decimal::MAX, client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_dispute_that_would_overflow_when_ignoring_held_overflow() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                ignore_held_overflow: true,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Deposit(Deposit {
+                amount: Decimal::MAX,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        let outcome = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(
+            ApplyOutcome::Ignored(IgnoredReason::HeldBalanceOverflow),
+            outcome
+        );
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(Decimal::MAX, client_account.held_balance);
+        assert_eq!(Decimal::MAX, client_account.total_balance);
+        assert!(client_account.good_deposits.contains_key(&3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_transactions_only_for_seeded_locks_with_the_skip_policy() -> Result<()> {
+        let client_id = 1;
+        let policy = AccountPolicy {
+            seeded_lock_policy: SeededLockPolicy::Skip,
+            ..Default::default()
+        };
+        let deposit = || Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        };
+
+        let mut seeded_account = ClientAccount::with_policy(client_id, policy);
+        seeded_account.seed_locked();
+        assert_eq!(
+            ApplyOutcome::Ignored(IgnoredReason::SeededLocked),
+            seeded_account.apply_transaction(deposit())?
+        );
+        assert_eq!(dec!(0), seeded_account.total_balance);
+
+        // An account locked by a chargeback is still rejected
+        let mut charged_back_account = ClientAccount::with_policy(client_id, policy);
+        charged_back_account.locked = true;
+        charged_back_account.lock_reason = Some(LockReason::Chargeback(9));
+        let result = charged_back_account.apply_transaction(deposit());
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 1 for client 1: Account is locked"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_act_on_a_locked_account() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        client_account.locked = true;
+
+        let deposit_result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        });
+
+        let withdrawal_result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal {
+                amount: dec!(12.5555),
+            }),
+        });
+
+        let dispute_result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        });
+
+        let resolve_result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
+        });
+
+        let chargeback_result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Chargeback,
+        });
+
+        assert_err!(
+            deposit_result,
+            "Failed to apply deposit with transaction ID 1 for client 1: Account is locked"
+        );
+        assert_err!(
+            withdrawal_result,
+            "Failed to apply withdrawal with transaction ID 2 for client 1: Account is locked"
+        );
+        assert_err!(
+            dispute_result,
+            "Failed to apply dispute for transaction ID 1 for client 1: Account is locked"
+        );
+        assert_err!(
+            resolve_result,
+            "Failed to apply resolve for transaction ID 1 for client 1: Account is locked"
+        );
+        assert_err!(
+            chargeback_result,
+            "Failed to apply chargeback for transaction ID 1 for client 1: Account is locked"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_applying_deposit_twice() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_a_repeated_deposit_as_already_applied() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        let deposit = || Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        };
+
+        let first = client_account.apply_transaction(deposit())?;
+        let second = client_account.apply_transaction(deposit())?;
+
+        assert_eq!(ApplyOutcome::Applied, first);
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied), second);
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_lower_transaction_id_after_the_maximum_transaction_id() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: u32::MAX,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+
+        let outcome = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+        })?;
+
+        assert_eq!(ApplyOutcome::Applied, outcome);
+        assert_eq!(dec!(15), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_applying_the_maximum_transaction_id_twice() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        for _ in 0..2 {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: u32::MAX,
+                action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+            })?;
+        }
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: u32::MAX,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(10), client_account.held_balance);
+        assert_eq!(dec!(10), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_applying_withdrawal_twice() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(0), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_a_repeated_withdrawal_as_already_applied() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        let withdrawal = || Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        };
+
+        let first = client_account.apply_transaction(withdrawal())?;
+        let second = client_account.apply_transaction(withdrawal())?;
+
+        assert_eq!(ApplyOutcome::Applied, first);
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied), second);
+        assert_eq!(dec!(6), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_applying_dispute_to_unknown_transaction() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 100,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_applying_dispute_to_already_disputed_transaction() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_a_dispute_of_an_unknown_or_disputed_transaction_as_not_disputable() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        let dispute = |transaction_id| Transaction {
+            client_id,
+            transaction_id,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        };
+
+        let unknown = client_account.apply_transaction(dispute(100))?;
+        let first = client_account.apply_transaction(dispute(1))?;
+        let second = client_account.apply_transaction(dispute(1))?;
+
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::NotDisputable), unknown);
+        assert_eq!(ApplyOutcome::Applied, first);
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::NotDisputable), second);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_applying_resolve_to_undisputed_transaction() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        let outcome = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
+        })?;
+
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::NotDisputed), outcome);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_resolve_to_undisputed_transaction_with_strict_dispute_flow() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                strict_dispute_flow: true,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Resolve,
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply resolve for transaction ID 1 for client 1: Resolve references a non-disputed transaction"
+        );
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_chargeback_to_undisputed_transaction_with_strict_dispute_flow() -> Result<()>
+    {
+        let client_id = 1;
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                strict_dispute_flow: true,
+                ..Default::default()
+            },
+        );
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Chargeback,
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply chargeback for transaction ID 1 for client 1: Chargeback references a non-disputed transaction"
+        );
+        assert!(!client_account.locked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn held_balance_matches_disputed_deposits() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert!(client_account.held_matches_disputes());
+        Ok(())
+    }
+
+    #[test]
+    fn detects_held_balance_not_matching_disputed_deposits() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        client_account.held_balance += dec!(0.0001);
+
+        assert!(!client_account.held_matches_disputes());
+        Ok(())
+    }
+
+    #[test]
+    fn applies_reset() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Chargeback,
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Reset,
+        })?;
+
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(0), client_account.total_balance);
+        assert!(!client_account.locked);
+        assert!(client_account.good_deposits.is_empty());
+        assert!(client_account.disputed_deposits.is_empty());
+        assert!(client_account.chargedback_deposits.is_empty());
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 4,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+        })?;
+
+        assert_eq!(dec!(5), client_account.available_balance);
+        assert_eq!(dec!(5), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn freeze_blocks_deposits_until_unfrozen() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Freeze,
+        })?;
+
+        assert!(client_account.locked);
+        assert_eq!(Some(LockReason::Frozen(2)), client_account.lock_reason);
+        assert_eq!(dec!(10), client_account.available_balance);
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+        });
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 3 for client 1: Account is locked"
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 4,
+            action: TransactionAction::Unfreeze,
+        })?;
+
+        assert!(!client_account.locked);
+        assert_eq!(None, client_account.lock_reason);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+        })?;
+
+        assert_eq!(dec!(15), client_account.available_balance);
+        assert_eq!(dec!(15), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unfreeze_does_not_lift_chargeback_lock() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Chargeback,
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Unfreeze,
+        });
+        assert_err!(
+            result,
+            "Failed to apply unfreeze with transaction ID 2 for client 1: Account is locked by a chargeback"
+        );
+        assert!(client_account.locked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn renders_summary_row() -> Result<()> {
+        let client_id = 7;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1.5) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(
+            ["7", "12.5555", "1.5000", "14.0555", "false"],
+            client_account.summary_row(4)
+        );
+        assert_eq!(
+            ["7", "12.55", "1.50", "14.05", "false"],
+            client_account.summary_row(2)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn renders_csv_record_with_held_funds() -> Result<()> {
+        let client_id = 3;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(2.5) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(
+            StringRecord::from(vec!["3", "10.0000", "2.5000", "12.5000", "false"]),
+            client_account.to_csv_record()
+        );
+
+        Ok(())
+    }
+
+    fn hold_withdrawals_account(client_id: ClientId) -> Result<ClientAccount> {
+        let mut client_account = ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                hold_withdrawals: true,
+                ..Default::default()
+            },
+        );
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        })?;
+
+        Ok(client_account)
+    }
+
+    #[test]
+    fn holds_withdrawal_until_settled() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = hold_withdrawals_account(client_id)?;
+
+        assert_eq!(dec!(6), client_account.available_balance);
+        assert_eq!(dec!(4), client_account.held_balance);
+        assert_eq!(dec!(10), client_account.total_balance);
+        assert!(client_account.held_matches_disputes());
+
+        let outcome = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Settle,
+        })?;
+
+        assert_eq!(ApplyOutcome::Applied, outcome);
+        assert_eq!(dec!(6), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(6), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cancels_held_withdrawal_when_disputed() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = hold_withdrawals_account(client_id)?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        let outcome = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Settle,
+        })?;
+
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::NotPending), outcome);
+        assert_eq!(dec!(10), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(10), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_cancel_part_of_a_held_withdrawal() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = hold_withdrawals_account(client_id)?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: Some(dec!(3)),
+                target: None,
+            }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply dispute for transaction ID 2 for client 1: Dispute amount must match the pending withdrawal amount"
+        );
+        assert_eq!(dec!(6), client_account.available_balance);
+        assert_eq!(dec!(4), client_account.held_balance);
+        assert!(client_account.pending_withdrawals.contains_key(&2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_deposit_status_through_the_dispute_lifecycle() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        let mut apply = |action| {
+            client_account.apply_transaction(Transaction {
+                client_id,
+                transaction_id: 1,
+                action,
+            })?;
+            Ok::<_, anyhow::Error>(client_account.deposit_status(1))
+        };
+
+        assert_eq!(
+            DepositStatus::Applied,
+            apply(TransactionAction::Deposit(Deposit { amount: dec!(10) }))?
+        );
+        assert_eq!(
+            DepositStatus::Disputed,
+            apply(TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None
+            }))?
+        );
+        assert_eq!(DepositStatus::Applied, apply(TransactionAction::Resolve)?);
+        apply(TransactionAction::Dispute(Dispute {
+            amount: None,
+            target: None,
+        }))?;
+        assert_eq!(
+            DepositStatus::ChargedBack,
+            apply(TransactionAction::Chargeback)?
+        );
+        assert_eq!(DepositStatus::Unknown, client_account.deposit_status(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_hold() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Hold(Hold { amount: dec!(10) }),
+        })?;
+
+        assert_eq!(dec!(2.5555), client_account.available_balance);
+        assert_eq!(dec!(10), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+        assert!(client_account.held_matches_disputes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_release() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Hold(Hold { amount: dec!(10) }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 3,
+            action: TransactionAction::Release(Release { amount: dec!(4) }),
+        })?;
+
+        assert_eq!(dec!(6.5555), client_account.available_balance);
+        assert_eq!(dec!(6), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_apply_hold_with_insufficient_available_balance() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Hold(Hold { amount: dec!(13) }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply hold with transaction ID 2 for client 1: Insufficient available balance for hold"
+        );
+        assert_eq!(dec!(12.5555), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(12.5555), client_account.total_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_release_funds_held_by_a_dispute() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        let result = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Release(Release { amount: dec!(1) }),
+        });
+
+        assert_err!(
+            result,
+            "Failed to apply release with transaction ID 2 for client 1: Insufficient manually held balance for release"
+        );
+        assert_eq!(dec!(0), client_account.available_balance);
+        assert_eq!(dec!(12.5555), client_account.held_balance);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Balances don't add up after applying deposit with transaction ID 1")]
+    fn panics_in_debug_builds_when_balances_stop_adding_up() {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+        // Stands in for an apply path that changes the held balance without the total
+        client_account.held_balance = dec!(2);
+
+        let _ = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(1) }),
+        });
+    }
+
+    fn dispute_withdrawals_account(client_id: ClientId) -> ClientAccount {
+        ClientAccount::with_policy(
+            client_id,
+            AccountPolicy {
+                dispute_withdrawals: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn charges_back_a_disputed_withdrawal() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = dispute_withdrawals_account(client_id);
+        let withdrawal_dispute = |amount| Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount,
+                target: Some(DisputeTarget::Withdrawal),
+            }),
+        };
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        })?;
+
+        let result = client_account.apply_transaction(withdrawal_dispute(Some(dec!(5))));
+        assert_err!(
+            result,
+            "Failed to apply dispute for transaction ID 2 for client 1: Dispute amount exceeds the withdrawal amount"
+        );
+
+        client_account.apply_transaction(withdrawal_dispute(None))?;
+
+        assert_eq!(dec!(6), client_account.available_balance);
+        assert_eq!(dec!(4), client_account.held_balance);
+        assert_eq!(dec!(10), client_account.total_balance);
+        assert!(client_account.held_matches_disputes());
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Chargeback,
+        })?;
+
+        assert_eq!(dec!(10), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(10), client_account.total_balance);
+        assert_eq!(Some(LockReason::Chargeback(2)), client_account.lock_reason);
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_a_disputed_withdrawal() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = dispute_withdrawals_account(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: Some(dec!(1)),
+                target: Some(DisputeTarget::Withdrawal),
+            }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Resolve,
+        })?;
+
+        assert_eq!(dec!(6), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        assert_eq!(dec!(6), client_account.total_balance);
+        assert_eq!(
+            Some(dec!(4)),
+            client_account
+                .settled_withdrawals
+                .get(&2)
+                .map(|withdrawal| withdrawal.amount)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_a_withdrawal_dispute_without_the_policy() -> Result<()> {
+        let client_id = 1;
+        let mut client_account = ClientAccount::new(client_id);
+
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(10) }),
+        })?;
+        client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        })?;
+        let outcome = client_account.apply_transaction(Transaction {
+            client_id,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: Some(DisputeTarget::Withdrawal),
+            }),
+        })?;
+
+        assert_eq!(ApplyOutcome::Ignored(IgnoredReason::NotDisputable), outcome);
+        assert!(client_account.settled_withdrawals.is_empty());
+        assert_eq!(dec!(6), client_account.available_balance);
+        assert_eq!(dec!(0), client_account.held_balance);
+        Ok(())
+    }
 }
@@ -0,0 +1,12 @@
+/// Where a deposit is in the dispute lifecycle, as seen by dispute tooling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositStatus {
+    /// No deposit with the transaction ID has been applied to the account.
+    Unknown,
+    /// The deposit has been applied and isn't disputed, so it can be.
+    Applied,
+    /// The deposit, or part of it, is currently disputed.
+    Disputed,
+    /// The deposit was charged back.
+    ChargedBack,
+}
@@ -0,0 +1,689 @@
+use super::{
+    account_policy::AccountPolicy,
+    apply_outcome::{ApplyOutcome, IgnoredReason},
+    client_account::{ClientAccount, ClientId},
+    lock_reason::LockReason,
+    transaction::{Transaction, TransactionAction, TransactionId},
+    transaction_validator::TransactionValidator,
+};
+use anyhow::{Error, Result};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt};
+
+/// Holds every client account and applies transactions to them. Accounts are kept
+/// between calls, so a long-running service can apply several batches to one engine.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Engine {
+    accounts: HashMap<ClientId, ClientAccount>,
+    policy: AccountPolicy,
+    /// The client each deposit was made to, kept only when routing disputes by transaction.
+    deposit_owners: Option<HashMap<TransactionId, ClientId>>,
+    /// Checked before each transaction is applied. It isn't part of a snapshot, so must be
+    /// set again after restoring.
+    #[serde(skip)]
+    validator: Option<Box<dyn TransactionValidator>>,
+    /// Called when an account locks. Like the validator, it isn't part of a snapshot.
+    #[serde(skip)]
+    on_lock: Option<LockCallback>,
+    /// The client and transaction ID of the most recent lock, until it's taken.
+    #[serde(skip)]
+    last_lock: Option<(ClientId, TransactionId)>,
+    /// The client the most recent transaction was applied to.
+    #[serde(skip)]
+    last_client_id: Option<ClientId>,
+}
+
+/// A callback given the client and transaction ID whenever a transaction locks an account.
+struct LockCallback(Box<dyn FnMut(ClientId, TransactionId) + Send>);
+
+impl fmt::Debug for LockCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LockCallback")
+    }
+}
+
+impl Engine {
+    /// Creates an engine whose accounts are created with `policy`.
+    pub fn new(policy: AccountPolicy) -> Engine {
+        Engine::with_accounts(HashMap::new(), policy)
+    }
+    /// Creates an engine starting from existing accounts, e.g. opening balances.
+    pub fn with_accounts(
+        accounts: HashMap<ClientId, ClientAccount>,
+        policy: AccountPolicy,
+    ) -> Engine {
+        Engine {
+            accounts,
+            policy,
+            deposit_owners: None,
+            validator: None,
+            on_lock: None,
+            last_lock: None,
+            last_client_id: None,
+        }
+    }
+    /// Checks every transaction with `validator` before applying it.
+    pub fn set_validator(&mut self, validator: Box<dyn TransactionValidator>) {
+        self.validator = Some(validator);
+    }
+    /// Calls `on_lock` with the client and transaction ID as soon as a chargeback locks an
+    /// account, e.g. to raise a fraud alert. An operator's freeze doesn't call it.
+    pub fn set_on_lock(&mut self, on_lock: impl FnMut(ClientId, TransactionId) + Send + 'static) {
+        self.on_lock = Some(LockCallback(Box::new(on_lock)));
+    }
+    /// Sends disputes, resolves and chargebacks to the client that made the deposit they
+    /// refer to, rather than the client named on their row. Without this, a dispute naming
+    /// the wrong client is ignored because the deposit isn't found in that client's account.
+    pub fn route_disputes_by_transaction(&mut self) {
+        self.deposit_owners.get_or_insert_with(HashMap::new);
+    }
+    /// Applies the transaction to its client's account, creating the account if needed.
+    pub fn apply(&mut self, transaction: Transaction) -> Result<ApplyOutcome> {
+        self.apply_row(transaction, None)
+    }
+    /// Applies the transaction like [`Engine::apply`], skipping it if it comes from a row
+    /// with an ID the account has already applied, so replayed rows aren't applied twice.
+    pub fn apply_row(
+        &mut self,
+        mut transaction: Transaction,
+        row_id: Option<TransactionId>,
+    ) -> Result<ApplyOutcome> {
+        if let Some(deposit_owners) = &mut self.deposit_owners {
+            route_transaction(&mut transaction, deposit_owners);
+        }
+        let client_id = transaction.client_id;
+        let transaction_id = transaction.transaction_id;
+        let is_deposit = matches!(transaction.action, TransactionAction::Deposit(_));
+        let dispute_kind = match transaction.action {
+            TransactionAction::Dispute(_) => Some("Dispute"),
+            TransactionAction::Resolve => Some("Resolve"),
+            TransactionAction::Chargeback => Some("Chargeback"),
+            _ => None,
+        };
+        let is_new_account = !self.accounts.contains_key(&client_id);
+
+        if let Some(validator) = &self.validator {
+            // A new client's account is only created once its transaction passes, so a
+            // rejected transaction doesn't leave an empty account behind
+            let new_account;
+            let account = match self.accounts.get(&client_id) {
+                Some(account) => account,
+                None => {
+                    new_account = ClientAccount::with_policy(client_id, self.policy);
+                    &new_account
+                }
+            };
+            // A repeat doesn't change the account, so it's left for the account to ignore
+            if !account.is_repeated(&transaction, row_id) {
+                validator.validate(account, &transaction).map_err(|err| {
+                    Error::msg(format!("Failed to apply {}: {}", transaction, err))
+                })?;
+            }
+        }
+
+        let policy = self.policy;
+        let account = self
+            .accounts
+            .entry(client_id)
+            .or_insert_with(|| ClientAccount::with_policy(client_id, policy));
+        let was_locked = account.locked;
+        let mut outcome = account.apply_transaction_row(transaction, row_id)?;
+        self.last_client_id = Some(client_id);
+
+        if let (true, Some(dispute_kind), ApplyOutcome::Ignored(_)) =
+            (is_new_account, dispute_kind, outcome)
+        {
+            log::warn!(
+                "{} before any deposit for client {}",
+                dispute_kind,
+                client_id
+            );
+            outcome = ApplyOutcome::Ignored(IgnoredReason::BeforeAnyDeposit);
+        }
+
+        // Only a chargeback counts, not an operator's freeze
+        if !was_locked && matches!(account.lock_reason, Some(LockReason::Chargeback(_))) {
+            self.last_lock = Some((client_id, transaction_id));
+            if let Some(LockCallback(on_lock)) = &mut self.on_lock {
+                on_lock(client_id, transaction_id);
+            }
+        }
+
+        if let Some(deposit_owners) = &mut self.deposit_owners {
+            if is_deposit && outcome == ApplyOutcome::Applied {
+                deposit_owners.entry(transaction_id).or_insert(client_id);
+            }
+        }
+
+        Ok(outcome)
+    }
+    /// Returns the client and transaction ID of the most recent chargeback to lock an
+    /// account, if one has since the last call, e.g. to stop processing at the first lock.
+    pub fn take_last_lock(&mut self) -> Option<(ClientId, TransactionId)> {
+        self.last_lock.take()
+    }
+    /// The account the most recent transaction was applied to, as it was left. With disputes
+    /// routed by transaction, this is the account a dispute was routed to.
+    pub fn last_account(&self) -> Option<&ClientAccount> {
+        self.accounts.get(&self.last_client_id?)
+    }
+    /// Creates an empty account for the client if it doesn't already have one.
+    pub fn open_account(&mut self, client_id: ClientId) {
+        let policy = self.policy;
+        self.accounts
+            .entry(client_id)
+            .or_insert_with(|| ClientAccount::with_policy(client_id, policy));
+    }
+    pub fn has_account(&self, client_id: ClientId) -> bool {
+        self.accounts.contains_key(&client_id)
+    }
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+    pub fn accounts(&self) -> impl Iterator<Item = &ClientAccount> {
+        self.accounts.values()
+    }
+    pub fn accounts_mut(&mut self) -> impl Iterator<Item = &mut ClientAccount> {
+        self.accounts.values_mut()
+    }
+    pub fn into_accounts(self) -> Vec<ClientAccount> {
+        self.accounts.into_values().collect()
+    }
+    pub fn into_account_map(self) -> HashMap<ClientId, ClientAccount> {
+        self.accounts
+    }
+    /// Combines two engines whose client IDs don't overlap, e.g. the results of processing
+    /// separate shards or regions. The merged engine keeps this engine's policy and validator,
+    /// and routes disputes by transaction if either engine did.
+    pub fn merge(mut self, other: Engine) -> Result<Engine> {
+        let mut overlapping: Vec<ClientId> = other
+            .accounts
+            .keys()
+            .filter(|client_id| self.accounts.contains_key(client_id))
+            .copied()
+            .collect();
+        if !overlapping.is_empty() {
+            overlapping.sort_unstable();
+            return Err(Error::msg(format!(
+                "Failed to merge engines: clients {:?} are in both",
+                overlapping
+            )));
+        }
+
+        self.accounts.extend(other.accounts);
+        match (&mut self.deposit_owners, other.deposit_owners) {
+            (Some(deposit_owners), Some(other_deposit_owners)) => {
+                deposit_owners.extend(other_deposit_owners);
+            }
+            (None, other_deposit_owners) => self.deposit_owners = other_deposit_owners,
+            (Some(_), None) => {}
+        }
+        Ok(self)
+    }
+    /// Checks that the held balances summed across every account equal the disputed
+    /// deposits, manual holds and pending withdrawals summed across every account. Unlike
+    /// checking each account alone, this also catches funds held on the wrong account.
+    pub fn reconcile_held_balances(&self) -> Result<()> {
+        let (held, expected) = self
+            .accounts
+            .values()
+            .try_fold(
+                (Decimal::ZERO, Decimal::ZERO),
+                |(held, expected), account| {
+                    Some((
+                        held.checked_add(account.held_balance)?,
+                        expected.checked_add(account.expected_held_balance())?,
+                    ))
+                },
+            )
+            .ok_or_else(|| Error::msg("Failed to reconcile held balances: total overflow"))?;
+
+        if held != expected {
+            return Err(Error::msg(format!(
+                "Failed to reconcile held balances: accounts hold {} in total but their disputes account for {}",
+                held, expected
+            )));
+        }
+        Ok(())
+    }
+    /// Encodes every account, including the deposits and transaction IDs it has seen,
+    /// so a long run can be checkpointed and later resumed with [`Engine::restore`].
+    pub fn snapshot(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self)
+            .map_err(|err| Error::msg(format!("Failed to snapshot engine: {}", err)))
+    }
+    /// Writes every account, including its deposits, as TOML for a person to inspect while
+    /// debugging. Unlike [`Engine::snapshot`], it isn't meant to be read back.
+    pub fn dump_toml(&self) -> Result<String> {
+        // TOML only has string keys and no null, so the engine goes through a JSON value,
+        // which stringifies client and transaction IDs, before the nulls are removed
+        let value = serde_json::to_value(self)
+            .map_err(|err| Error::msg(format!("Failed to dump engine as TOML: {}", err)))?;
+        toml::to_string(&without_nulls(value))
+            .map_err(|err| Error::msg(format!("Failed to dump engine as TOML: {}", err)))
+    }
+    /// Recreates an engine from the bytes produced by [`Engine::snapshot`].
+    pub fn restore(bytes: &[u8]) -> Result<Engine> {
+        serde_json::from_slice(bytes)
+            .map_err(|err| Error::msg(format!("Failed to restore engine snapshot: {}", err)))
+    }
+}
+
+/// Drops null fields, and turns maps used as sets, whose values are all null, into arrays
+/// of their keys.
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) if !map.is_empty() && map.values().all(Value::is_null) => {
+            Value::Array(map.into_iter().map(|(key, _)| Value::String(key)).collect())
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+/// Points a dispute, resolve or chargeback at the client owning the deposit it refers to.
+fn route_transaction(
+    transaction: &mut Transaction,
+    deposit_owners: &HashMap<TransactionId, ClientId>,
+) {
+    if !matches!(
+        transaction.action,
+        TransactionAction::Dispute(_) | TransactionAction::Resolve | TransactionAction::Chargeback
+    ) {
+        return;
+    }
+    let Some(&owner) = deposit_owners.get(&transaction.transaction_id) else {
+        return;
+    };
+    if owner != transaction.client_id {
+        log::warn!(
+            "Routing {} to client {}, which made the deposit",
+            transaction,
+            owner
+        );
+        transaction.client_id = owner;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Engine;
+    use crate::assert_err::assert_err;
+    use crate::domain::apply_outcome::{ApplyOutcome, IgnoredReason};
+    use crate::domain::transaction::{
+        Deposit, Dispute, Transaction, TransactionAction, Withdrawal,
+    };
+    use crate::domain::{
+        client_account::{ClientAccount, ClientId},
+        transaction::TransactionId,
+        transaction_validator::TransactionValidator,
+    };
+    use crate::test_fixture::{capture_logs, logged_messages};
+    use anyhow::{Error, Result};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    fn deposit(client_id: ClientId, transaction_id: TransactionId, amount: Decimal) -> Transaction {
+        Transaction {
+            client_id,
+            transaction_id,
+            action: TransactionAction::Deposit(Deposit { amount }),
+        }
+    }
+
+    #[test]
+    fn applies_batches_to_the_same_accounts() -> Result<()> {
+        let mut engine = Engine::default();
+
+        engine.apply(deposit(1, 1, dec!(10)))?;
+        engine.apply(deposit(2, 2, dec!(5)))?;
+
+        let total: Decimal = engine.accounts().map(|account| account.total_balance).sum();
+        assert_eq!(dec!(15), total);
+
+        engine.apply(Transaction {
+            client_id: 1,
+            transaction_id: 3,
+            action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(4) }),
+        })?;
+        engine.apply(deposit(3, 4, dec!(1)))?;
+
+        let mut accounts = engine.into_accounts();
+        accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(3, accounts.len());
+        assert_eq!(dec!(6), accounts[0].total_balance);
+        assert_eq!(dec!(5), accounts[1].total_balance);
+        assert_eq!(dec!(1), accounts[2].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_reconcile_corrupted_held_balance() -> Result<()> {
+        let mut engine = Engine::default();
+
+        engine.apply(deposit(1, 1, dec!(10)))?;
+        engine.apply(deposit(2, 2, dec!(5)))?;
+        engine.apply(Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        engine.reconcile_held_balances()?;
+
+        if let Some(account) = engine.accounts.get_mut(&2) {
+            account.held_balance += dec!(0.5);
+        }
+
+        let result = engine.reconcile_held_balances();
+        assert_err!(
+            result,
+            "Failed to reconcile held balances: accounts hold 10.5 in total but their disputes account for 10"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_from_a_snapshot_as_if_never_interrupted() -> Result<()> {
+        let transactions = || {
+            vec![
+                deposit(1, 1, dec!(10)),
+                deposit(2, 2, dec!(5)),
+                deposit(1, 3, dec!(2.5)),
+                Transaction {
+                    client_id: 1,
+                    transaction_id: 4,
+                    action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(1) }),
+                },
+                Transaction {
+                    client_id: 1,
+                    transaction_id: 3,
+                    action: TransactionAction::Dispute(Dispute {
+                        amount: None,
+                        target: None,
+                    }),
+                },
+                // Already applied before the snapshot, so must still be skipped after it
+                deposit(2, 2, dec!(5)),
+                Transaction {
+                    client_id: 2,
+                    transaction_id: 2,
+                    action: TransactionAction::Dispute(Dispute {
+                        amount: None,
+                        target: None,
+                    }),
+                },
+                Transaction {
+                    client_id: 2,
+                    transaction_id: 2,
+                    action: TransactionAction::Chargeback,
+                },
+            ]
+        };
+
+        let mut single_pass = Engine::default();
+        for transaction in transactions() {
+            single_pass.apply(transaction)?;
+        }
+
+        let mut transactions = transactions();
+        let second_half = transactions.split_off(transactions.len() / 2);
+        let mut interrupted = Engine::default();
+        for transaction in transactions {
+            interrupted.apply(transaction)?;
+        }
+        let mut resumed = Engine::restore(&interrupted.snapshot()?)?;
+        for transaction in second_half {
+            resumed.apply(transaction)?;
+        }
+
+        let rows = |engine: Engine| {
+            let mut accounts = engine.into_accounts();
+            accounts.sort_by_key(|account| account.client_id);
+            accounts
+                .iter()
+                .map(|account| account.summary_row(4))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rows(single_pass), rows(resumed));
+        Ok(())
+    }
+
+    #[test]
+    fn merges_engines_with_disjoint_clients() -> Result<()> {
+        let mut first = Engine::default();
+        first.apply(deposit(1, 1, dec!(10)))?;
+        first.apply(deposit(2, 2, dec!(5)))?;
+        let mut second = Engine::default();
+        second.apply(deposit(3, 3, dec!(1)))?;
+
+        let merged = first.merge(second)?;
+
+        let mut accounts = merged.into_accounts();
+        accounts.sort_by_key(|account| account.client_id);
+        assert_eq!(3, accounts.len());
+        assert_eq!(dec!(10), accounts[0].total_balance);
+        assert_eq!(dec!(5), accounts[1].total_balance);
+        assert_eq!(dec!(1), accounts[2].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn routes_disputes_after_merging_a_routed_engine_into_an_unrouted_one() -> Result<()> {
+        let mut first = Engine::default();
+        first.apply(deposit(1, 1, dec!(10)))?;
+        let mut second = Engine::default();
+        second.route_disputes_by_transaction();
+        second.apply(deposit(2, 2, dec!(5)))?;
+
+        let mut merged = first.merge(second)?;
+        merged.apply(Transaction {
+            client_id: 1,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        let mut accounts = merged.into_accounts();
+        accounts.sort_by_key(|account| account.client_id);
+        assert_eq!(dec!(0), accounts[0].held_balance);
+        assert_eq!(dec!(5), accounts[1].held_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_merge_engines_sharing_a_client() -> Result<()> {
+        let mut first = Engine::default();
+        first.apply(deposit(1, 1, dec!(10)))?;
+        first.apply(deposit(2, 2, dec!(5)))?;
+        let mut second = Engine::default();
+        second.apply(deposit(2, 3, dec!(1)))?;
+        second.apply(deposit(3, 4, dec!(1)))?;
+
+        let result = first.merge(second);
+        assert_err!(result, "Failed to merge engines: clients [2] are in both");
+        Ok(())
+    }
+
+    #[test]
+    fn calls_on_lock_once_per_chargeback_that_locks_an_account() -> Result<()> {
+        let locks = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::default();
+        let recorded = Arc::clone(&locks);
+        engine.set_on_lock(move |client_id, transaction_id| {
+            if let Ok(mut recorded) = recorded.lock() {
+                recorded.push((client_id, transaction_id));
+            }
+        });
+
+        engine.apply(deposit(1, 1, dec!(10)))?;
+        engine.apply(deposit(2, 2, dec!(5)))?;
+        engine.apply(deposit(2, 3, dec!(5)))?;
+        for (client_id, transaction_id) in [(1, 1), (2, 2), (2, 3)] {
+            engine.apply(Transaction {
+                client_id,
+                transaction_id,
+                action: TransactionAction::Dispute(Dispute {
+                    amount: None,
+                    target: None,
+                }),
+            })?;
+        }
+        for (client_id, transaction_id) in [(1, 1), (2, 2)] {
+            engine.apply(Transaction {
+                client_id,
+                transaction_id,
+                action: TransactionAction::Chargeback,
+            })?;
+        }
+        // Client 2 is already locked, so this fails rather than locking it again
+        let result = engine.apply(Transaction {
+            client_id: 2,
+            transaction_id: 3,
+            action: TransactionAction::Chargeback,
+        });
+        assert!(result.is_err());
+
+        assert_eq!(
+            Some(vec![(1, 1), (2, 2)]),
+            locks.lock().ok().map(|locks| locks.clone())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn does_not_call_on_lock_for_a_freeze() -> Result<()> {
+        let locks = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::default();
+        let recorded = Arc::clone(&locks);
+        engine.set_on_lock(move |client_id, transaction_id| {
+            if let Ok(mut recorded) = recorded.lock() {
+                recorded.push((client_id, transaction_id));
+            }
+        });
+
+        engine.apply(deposit(1, 1, dec!(10)))?;
+        engine.apply(Transaction {
+            client_id: 1,
+            transaction_id: 2,
+            action: TransactionAction::Freeze,
+        })?;
+
+        assert!(engine.last_account().is_some_and(|account| account.locked));
+        assert_eq!(Some(vec![]), locks.lock().ok().map(|locks| locks.clone()));
+        assert_eq!(None, engine.take_last_lock());
+        Ok(())
+    }
+
+    #[test]
+    fn warns_about_dispute_before_any_deposit() -> Result<()> {
+        capture_logs();
+        let mut engine = Engine::default();
+
+        let outcome = engine.apply(Transaction {
+            client_id: 7,
+            transaction_id: 1,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        assert_eq!(
+            ApplyOutcome::Ignored(IgnoredReason::BeforeAnyDeposit),
+            outcome
+        );
+        let balances: Vec<_> = engine
+            .accounts()
+            .map(|account| (account.available_balance, account.held_balance))
+            .collect();
+        assert_eq!(vec![(dec!(0), dec!(0))], balances);
+        assert!(logged_messages().contains(&"Dispute before any deposit for client 7".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn dumps_disputed_deposit_as_toml() -> Result<()> {
+        let mut engine = Engine::default();
+        engine.apply(deposit(1, 1, dec!(5)))?;
+        engine.apply(deposit(2, 2, dec!(7.5)))?;
+        engine.apply(Transaction {
+            client_id: 2,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+
+        let dump = engine.dump_toml()?;
+
+        assert!(dump.contains("[accounts.2.disputed_deposits.2]\namount = \"7.5\""));
+        Ok(())
+    }
+
+    /// Rejects deposits that would take an account's total balance over the limit.
+    #[derive(Debug)]
+    struct BalanceLimit(Decimal);
+
+    impl TransactionValidator for BalanceLimit {
+        fn validate(&self, account: &ClientAccount, transaction: &Transaction) -> Result<()> {
+            match &transaction.action {
+                TransactionAction::Deposit(deposit)
+                    if account.total_balance + deposit.amount > self.0 =>
+                {
+                    Err(Error::msg(format!("Balance over {} needs review", self.0)))
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn creates_no_account_for_a_client_whose_only_transaction_is_rejected() -> Result<()> {
+        let mut engine = Engine::default();
+        engine.set_validator(Box::new(BalanceLimit(dec!(100))));
+
+        engine.apply(deposit(1, 1, dec!(50)))?;
+        let result = engine.apply(deposit(2, 2, dec!(150)));
+
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 2 for client 2: Balance over 100 needs review"
+        );
+        assert!(engine.has_account(1));
+        assert!(!engine.has_account(2));
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_a_repeated_transaction_without_validating_it() -> Result<()> {
+        let mut engine = Engine::default();
+        engine.set_validator(Box::new(BalanceLimit(dec!(100))));
+
+        engine.apply(deposit(1, 1, dec!(80)))?;
+        // Applying it again would pass the limit, but as a repeat it's ignored
+        let outcome = engine.apply(deposit(1, 1, dec!(80)))?;
+
+        assert_eq!(
+            ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied),
+            outcome
+        );
+        Ok(())
+    }
+}
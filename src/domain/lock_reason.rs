@@ -0,0 +1,14 @@
+use super::transaction::TransactionId;
+use serde::{Deserialize, Serialize};
+
+/// Why an account was locked, to help investigate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "reason", content = "transaction_id")]
+pub enum LockReason {
+    /// The deposit with this transaction ID was charged back.
+    Chargeback(TransactionId),
+    /// An operator froze the account with the `freeze` of this transaction ID.
+    Frozen(TransactionId),
+    /// The account was already locked when processing started, e.g. by an earlier run.
+    Seeded,
+}
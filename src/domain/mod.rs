@@ -1,2 +1,9 @@
+pub mod account_policy;
+pub mod apply_outcome;
 pub mod client_account;
+pub mod deposit_status;
+pub mod engine;
+pub mod lock_reason;
+pub mod shared_engine;
 pub mod transaction;
+pub mod transaction_validator;
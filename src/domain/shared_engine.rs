@@ -0,0 +1,92 @@
+use super::{
+    apply_outcome::ApplyOutcome, client_account::ClientAccount, engine::Engine,
+    transaction::Transaction,
+};
+use anyhow::{Error, Result};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A handle to an engine that can be cloned and used from several threads at once, e.g. by
+/// a service handling concurrent requests. Each call locks the engine for its duration, so
+/// transactions are still applied one at a time.
+#[derive(Debug, Clone, Default)]
+pub struct SharedEngine {
+    engine: Arc<Mutex<Engine>>,
+}
+
+impl SharedEngine {
+    pub fn new(engine: Engine) -> SharedEngine {
+        SharedEngine {
+            engine: Arc::new(Mutex::new(engine)),
+        }
+    }
+    /// Applies the transaction to its client's account, creating the account if needed.
+    pub fn apply(&self, transaction: Transaction) -> Result<ApplyOutcome> {
+        self.lock()?.apply(transaction)
+    }
+    /// Copies every account as it is now, for reporting while transactions continue to be
+    /// applied. Unlike [`Engine::snapshot`], the copies aren't encoded.
+    pub fn snapshot(&self) -> Result<Vec<ClientAccount>> {
+        Ok(self.lock()?.accounts().cloned().collect())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Engine>> {
+        // A thread can only panic while holding the lock through a bug in the engine,
+        // which may have left an account half updated
+        self.engine
+            .lock()
+            .map_err(|_| Error::msg("Engine is unusable after a panic while it was locked"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedEngine;
+    use crate::domain::transaction::{Deposit, Transaction, TransactionAction, Withdrawal};
+    use anyhow::{Error, Result};
+    use rust_decimal_macros::dec;
+    use std::thread;
+
+    #[test]
+    fn applies_transactions_from_several_threads() -> Result<()> {
+        let engine = SharedEngine::default();
+
+        let handles: Vec<_> = (1..=4)
+            .map(|client_id| {
+                let engine = engine.clone();
+                thread::spawn(move || -> Result<()> {
+                    // Each client gets its own range of transaction IDs
+                    let first_id = u32::from(client_id) * 1000;
+                    for offset in 0..100 {
+                        engine.apply(Transaction {
+                            client_id,
+                            transaction_id: first_id + offset,
+                            action: TransactionAction::Deposit(Deposit { amount: dec!(1.5) }),
+                        })?;
+                    }
+                    engine.apply(Transaction {
+                        client_id,
+                        transaction_id: first_id + 100,
+                        action: TransactionAction::Withdrawal(Withdrawal { amount: dec!(50) }),
+                    })?;
+                    Ok(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle
+                .join()
+                .map_err(|_| Error::msg("Thread applying transactions panicked"))??;
+        }
+
+        let mut accounts = engine.snapshot()?;
+        accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(4, accounts.len());
+        for (account, client_id) in accounts.iter().zip(1..) {
+            assert_eq!(client_id, account.client_id);
+            assert_eq!(dec!(100), account.available_balance);
+            assert_eq!(dec!(100), account.total_balance);
+        }
+        Ok(())
+    }
+}
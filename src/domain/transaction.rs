@@ -1,5 +1,7 @@
 use super::client_account::ClientId;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub type TransactionId = u32;
 
@@ -10,25 +12,26 @@ pub struct Transaction {
     pub action: TransactionAction,
 }
 
-impl Transaction {
-    pub fn to_string(&self) -> String {
-        match self.action {
-            TransactionAction::Deposit(_) => {
-                format!("deposit with transaction ID {}", self.transaction_id)
-            }
-            TransactionAction::Withdrawal(_) => {
-                format!("withdrawal with transaction ID {}", self.transaction_id)
-            }
-            TransactionAction::Dispute => {
-                format!("dispute for transaction ID {}", self.transaction_id)
-            }
-            TransactionAction::Resolve => {
-                format!("resolve for transaction ID {}", self.transaction_id)
-            }
-            TransactionAction::Chargeback => {
-                format!("chargeback for transaction ID {}", self.transaction_id)
-            }
-        }
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self.action {
+            TransactionAction::Deposit(_) => "deposit with transaction ID",
+            TransactionAction::Withdrawal(_) => "withdrawal with transaction ID",
+            TransactionAction::Dispute(_) => "dispute for transaction ID",
+            TransactionAction::Resolve => "resolve for transaction ID",
+            TransactionAction::Chargeback => "chargeback for transaction ID",
+            TransactionAction::Reset => "reset with transaction ID",
+            TransactionAction::Hold(_) => "hold with transaction ID",
+            TransactionAction::Release(_) => "release with transaction ID",
+            TransactionAction::Settle => "settle for transaction ID",
+            TransactionAction::Freeze => "freeze with transaction ID",
+            TransactionAction::Unfreeze => "unfreeze with transaction ID",
+        };
+        write!(
+            f,
+            "{} {} for client {}",
+            description, self.transaction_id, self.client_id
+        )
     }
 }
 
@@ -36,17 +39,88 @@ impl Transaction {
 pub enum TransactionAction {
     Deposit(Deposit),
     Withdrawal(Withdrawal),
-    Dispute,
+    Dispute(Dispute),
     Resolve,
     Chargeback,
+    /// Returns the account to a zeroed, unlocked state. Only intended for test harnesses.
+    Reset,
+    Hold(Hold),
+    Release(Release),
+    /// Finalises a pending withdrawal, removing its held funds from the account.
+    Settle,
+    /// Locks the account administratively, leaving its balances untouched.
+    Freeze,
+    /// Unlocks an account locked by a freeze. A chargeback's lock is not lifted.
+    Unfreeze,
 }
 
-#[derive(Debug)]
+impl TransactionAction {
+    /// The type as written in a CSV row.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TransactionAction::Deposit(_) => "deposit",
+            TransactionAction::Withdrawal(_) => "withdrawal",
+            TransactionAction::Dispute(_) => "dispute",
+            TransactionAction::Resolve => "resolve",
+            TransactionAction::Chargeback => "chargeback",
+            TransactionAction::Reset => "reset",
+            TransactionAction::Hold(_) => "hold",
+            TransactionAction::Release(_) => "release",
+            TransactionAction::Settle => "settle",
+            TransactionAction::Freeze => "freeze",
+            TransactionAction::Unfreeze => "unfreeze",
+        }
+    }
+    /// Whether the action is ignored when its transaction ID has already been applied.
+    /// Disputes, resolves, chargebacks and settles instead use the ID to refer to an
+    /// earlier transaction.
+    pub fn is_deduplicated(&self) -> bool {
+        matches!(
+            self,
+            TransactionAction::Deposit(_)
+                | TransactionAction::Withdrawal(_)
+                | TransactionAction::Hold(_)
+                | TransactionAction::Release(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Deposit {
     pub amount: Decimal,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Withdrawal {
     pub amount: Decimal,
 }
+
+/// Holds the funds of an earlier deposit while it's investigated.
+#[derive(Debug)]
+pub struct Dispute {
+    /// The part of the deposit being disputed. The whole deposit is disputed when this is `None`.
+    pub amount: Option<Decimal>,
+    /// The kind of transaction disputed. When this is `None`, it's a deposit or a withdrawal
+    /// that hasn't settled.
+    pub target: Option<DisputeTarget>,
+}
+
+/// Whether a dispute refers to a deposit or a withdrawal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeTarget {
+    Deposit,
+    Withdrawal,
+}
+
+/// Manually reserves funds, e.g. for a pending authorization, moving them from available to held.
+#[derive(Debug)]
+pub struct Hold {
+    pub amount: Decimal,
+}
+
+/// Returns manually held funds to the available balance.
+#[derive(Debug)]
+pub struct Release {
+    pub amount: Decimal,
+}
@@ -0,0 +1,10 @@
+use super::{client_account::ClientAccount, transaction::Transaction};
+use anyhow::Result;
+use std::fmt;
+
+/// A business rule checked before a transaction is applied, e.g. that large withdrawals
+/// need review. Returning an error rejects the transaction, leaving the account unchanged.
+/// Validators must be `Send` so an engine can be shared between threads.
+pub trait TransactionValidator: fmt::Debug + Send {
+    fn validate(&self, account: &ClientAccount, transaction: &Transaction) -> Result<()>;
+}
@@ -0,0 +1,13 @@
+mod assert_err;
+pub mod cli;
+pub mod config;
+pub mod csv;
+pub mod domain;
+pub mod logger;
+pub mod options;
+pub mod output;
+pub mod processing;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(test)]
+mod test_fixture;
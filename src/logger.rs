@@ -0,0 +1,26 @@
+use anyhow::{Error, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes warnings and errors to stderr, leaving stdout for the account output.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &Record) {
+        eprintln!("{}: {}", record.level(), record.args());
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Installs the logger, writing messages at `max_level` and above. `LevelFilter::Off`
+/// silences it entirely.
+pub fn init(max_level: LevelFilter) -> Result<()> {
+    log::set_logger(&LOGGER)
+        .map_err(|err| Error::msg(format!("Failed to initialise logger: {}", err)))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
@@ -0,0 +1,817 @@
+use crate::{
+    config::EngineConfig,
+    csv::client_labels::ClientLabels,
+    domain::{
+        account_policy::DisputePolicy,
+        client_account::{ClientId, OUTPUT_PRECISION},
+        transaction::TransactionId,
+    },
+    output::{
+        output_file::OutputFile,
+        output_options::{OutputFormat, OutputOptions},
+        split_output::SplitOutput,
+    },
+    processing::processing_options::ProcessingOptions,
+};
+use anyhow::{Error, Result};
+use clap::{Arg, ArgAction, Command};
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+
+/// The largest scale a `Decimal` can represent.
+const MAX_SCALE: u32 = 28;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub csv_path: String,
+    pub print_stats: bool,
+    pub print_totals: bool,
+    /// Suppress everything written to stderr, leaving only the output and exit code.
+    pub quiet: bool,
+    pub pipelined: bool,
+    pub processing: ProcessingOptions,
+    pub output: OutputOptions,
+}
+
+impl Options {
+    pub fn from_args(args: &[String]) -> Result<Options> {
+        let matches = command().try_get_matches_from(args)?;
+        let matches = matches.subcommand_matches("process").unwrap_or(&matches);
+        let flag = |name: &str| matches.get_flag(name);
+        let value = |name: &str| matches.get_one::<String>(name).map(String::as_str);
+
+        // The config is loaded before the flags are applied so that they override it
+        let config = match value("config") {
+            Some(path) => EngineConfig::load(path)?,
+            None => EngineConfig::default(),
+        };
+        let mut output = config.output_options();
+        let mut processing = config.processing_options();
+        processing.parse.allow_zero_amounts |= flag("allow-zero");
+        processing.parse.sanitize_amounts |= flag("sanitize-amounts");
+        processing.parse.tolerant_whitespace_type |= flag("tolerant-whitespace-type");
+        processing.parse.allow_reset |= flag("allow-reset");
+        processing.parse.allow_admin |= flag("allow-admin");
+        processing.check_held_balances |= flag("check");
+        processing.reconcile_held_balances |= flag("reconcile");
+        processing.skip_unknown_types |= flag("skip-unknown");
+        processing.skip_repeated_headers |= flag("skip-repeated-headers");
+        processing.route_disputes_by_transaction |= flag("route-disputes");
+        processing.tolerant_bool_locked |= flag("tolerant-bool-locked");
+        processing.fail_on_lock |= flag("fail-on-lock");
+        processing.zstd_input |= flag("zstd");
+        if flag("string-client-ids") {
+            processing.parse.client_labels = Some(ClientLabels::default());
+        }
+
+        let policy = &mut processing.account_policy;
+        if flag("no-negative-available") {
+            policy.dispute_policy = DisputePolicy::RejectNegative;
+        }
+        policy.ignore_held_overflow |= flag("ignore-held-overflow");
+        policy.resolve_keeps_held |= flag("resolve-keeps-held");
+        policy.hold_withdrawals |= flag("hold-withdrawals");
+        policy.dispute_withdrawals |= flag("dispute-withdrawals");
+        policy.strict_dispute_flow |= flag("strict-dispute-flow");
+        policy.strict_transaction_ids |= flag("strict-transaction-ids");
+        if let Some(scale) = value("amount-scale") {
+            policy.amount_scale = Some(parse_amount_scale(scale)?);
+        }
+        if flag("warn-excess-precision") {
+            policy.warn_on_rounding = true;
+            policy.amount_scale.get_or_insert(OUTPUT_PRECISION);
+        }
+        if let Some(rounding) = value("amount-rounding") {
+            policy.amount_rounding = rounding.parse()?;
+        }
+        if let Some(fee) = value("withdrawal-fee") {
+            policy.withdrawal_fee = fee.parse()?;
+        }
+        if let Some(seeded_lock_policy) = value("seeded-lock-policy") {
+            policy.seeded_lock_policy = seeded_lock_policy.parse()?;
+        }
+        if let Some(window) = value("dedupe-window") {
+            policy.dedupe_window = Some(parse_dedupe_window(window)?);
+        }
+
+        if let Some(client_ids) = value("block-clients") {
+            processing.blocked_clients = parse_client_ids(client_ids)?;
+        }
+        if let Some(client_ids) = value("start-locked") {
+            processing.start_locked = parse_client_ids(client_ids)?;
+        }
+        if let Some(client_ids) = value("roster") {
+            processing.client_roster = parse_client_ids(client_ids)?;
+        }
+        if let Some(scale) = value("input-scale") {
+            processing.parse.input_scale = Some(parse_input_scale(scale)?);
+        }
+        if let Some(path) = value("applied-ids") {
+            processing.applied_ids_path = Some(path.to_string());
+        }
+        if let Some(path) = value("errors") {
+            processing.errors_path = Some(path.to_string());
+        }
+        if let Some(path) = value("ledger") {
+            processing.ledger_path = Some(path.to_string());
+        }
+        if let Some(transaction_id) = value("since-tx") {
+            processing.since_transaction_id = Some(parse_transaction_id(transaction_id)?);
+        }
+        if let Some(path) = value("opening-balances") {
+            processing.opening_balances_path = Some(path.to_string());
+        }
+        if let Some(max_clients) = value("max-clients") {
+            processing.max_clients = Some(parse_max_clients(max_clients)?);
+        }
+
+        // These name clients by numeric ID, which string identifiers don't have until read
+        let numbers_clients = processing.opening_balances_path.is_some()
+            || !processing.blocked_clients.is_empty()
+            || !processing.client_roster.is_empty()
+            || !processing.start_locked.is_empty();
+        if processing.parse.client_labels.is_some() && numbers_clients {
+            return Err(Error::msg(
+                "--string-client-ids can't be used with options naming clients by number",
+            ));
+        }
+
+        output.normalize_balances |= flag("normalize");
+        output.explicit_sign |= flag("explicit-sign");
+        output.balanced_rounding = flag("balanced-rounding");
+        output.include_gross_deposits = flag("gross-deposits");
+        output.locked_only = flag("locked-only");
+        output.descending |= flag("desc");
+        if let Some(precision) = value("precision") {
+            output.precision = Some(parse_precision(precision)?);
+        }
+        // A precision from the config file hasn't been checked yet
+        if let Some(precision) = output.precision {
+            check_precision(precision)?;
+        }
+        if let Some(threshold) = value("held-ratio-threshold") {
+            output.held_ratio_threshold = Some(parse_held_ratio_threshold(threshold)?);
+        }
+        if let Some(rounding) = value("rounding") {
+            output.rounding = Some(rounding.parse()?);
+        }
+        if let Some(sort_by) = value("sort-by") {
+            output.sort_by = Some(sort_by.parse()?);
+        }
+        // The default order is arbitrary, so there's nothing to reverse without a sort key
+        if output.descending && output.sort_by.is_none() {
+            return Err(Error::msg("--desc requires --sort-by"));
+        }
+        if let Some(format) = value("format") {
+            output.format = format.parse()?;
+        }
+        if let Some(path) = value("normalize-client-ids") {
+            // Labelled clients are written by label, so their numbers never appear
+            if processing.parse.client_labels.is_some() {
+                return Err(Error::msg(
+                    "--normalize-client-ids can't be used with --string-client-ids",
+                ));
+            }
+            output.client_id_mapping_path = Some(path.to_string());
+        }
+        if let Some(flush_every) = value("flush-every") {
+            output.flush_every = Some(parse_flush_every(flush_every)?);
+        }
+
+        let split_shards = value("split").map(parse_split_shards).transpose()?;
+        output.split = match (split_shards, value("output-prefix")) {
+            (Some(shards), Some(prefix)) => Some(SplitOutput {
+                shards,
+                prefix: prefix.to_string(),
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(Error::msg(
+                    "--split and --output-prefix must be used together",
+                ))
+            }
+        };
+        if output.split.is_some() && output.format != OutputFormat::Csv {
+            return Err(Error::msg("--split only supports CSV output"));
+        }
+        let output_mode = value("output-mode").map(str::parse).transpose()?;
+        output.file = match (value("output"), output_mode) {
+            (Some(path), mode) => Some(OutputFile {
+                path: path.to_string(),
+                mode: mode.unwrap_or_default(),
+            }),
+            (None, None) => None,
+            (None, Some(_)) => return Err(Error::msg("--output-mode requires --output")),
+        };
+        if output.file.is_some() && output.split.is_some() {
+            return Err(Error::msg("--output can't be used with --split"));
+        }
+        output.json_numbers = flag("json-numbers");
+        if output.json_numbers && output.format != OutputFormat::Json {
+            return Err(Error::msg("--json-numbers only applies to JSON output"));
+        }
+        // A leading + isn't valid in a JSON number
+        if output.json_numbers && output.explicit_sign {
+            return Err(Error::msg(
+                "--explicit-sign can't be used with --json-numbers",
+            ));
+        }
+
+        let pipelined = flag("pipelined");
+        if pipelined && processing.errors_path.is_some() {
+            return Err(Error::msg("--errors can't be used with --pipelined"));
+        }
+        // Rejected rows are written to the errors file rather than failing the run
+        if processing.fail_on_lock && processing.errors_path.is_some() {
+            return Err(Error::msg("--fail-on-lock can't be used with --errors"));
+        }
+
+        Ok(Options {
+            csv_path: value("csv_path")
+                .ok_or_else(|| {
+                    Error::msg("Missing CSV path argument. Example: cargo run -- transactions.csv")
+                })?
+                .to_string(),
+            print_stats: flag("stats"),
+            print_totals: flag("totals"),
+            quiet: flag("quiet"),
+            pipelined,
+            processing,
+            output,
+        })
+    }
+}
+
+/// The command line interface. Processing a file is the default, so `payments-engine
+/// tx.csv` is the same as `payments-engine process tx.csv`. A flag given twice takes
+/// its last value.
+pub fn command() -> Command {
+    Command::new("payments-engine")
+        .about("Applies a CSV of transactions and writes each client's resulting balances")
+        .args_override_self(true)
+        .args_conflicts_with_subcommands(true)
+        .disable_help_subcommand(true)
+        .args(process_args())
+        .subcommand(
+            Command::new("process")
+                .about("Process a CSV of transactions (the default)")
+                .args_override_self(true)
+                .args(process_args()),
+        )
+}
+
+fn process_args() -> Vec<Arg> {
+    vec![
+        Arg::new("csv_path")
+            .value_name("CSV")
+            .help("The CSV of transactions to process"),
+        option(
+            "config",
+            "PATH",
+            "Read settings from a JSON config file, which flags override",
+        ),
+        flag(
+            "stats",
+            "Print counts of each transaction type processed to stderr",
+        ),
+        flag(
+            "totals",
+            "Print the balances summed across all accounts to stderr",
+        ),
+        flag(
+            "quiet",
+            "Write nothing to stderr, leaving only the output and exit code",
+        ),
+        flag(
+            "pipelined",
+            "Parse rows on a separate thread to overlap with applying",
+        ),
+        flag(
+            "allow-zero",
+            "Skip zero-amount deposits and withdrawals instead of failing",
+        ),
+        flag(
+            "check",
+            "Verify each account's held balance against its disputes",
+        ),
+        flag(
+            "reconcile",
+            "Verify the held balances summed across all accounts",
+        ),
+        flag(
+            "sanitize-amounts",
+            "Accept amounts with a currency symbol and separators",
+        ),
+        flag(
+            "tolerant-whitespace-type",
+            "Ignore whitespace inside transaction types",
+        ),
+        option(
+            "errors",
+            "PATH",
+            "Write rejected rows to a CSV and carry on",
+        ),
+        flag(
+            "zstd",
+            "Decompress the input as zstd whatever its file name",
+        ),
+        option(
+            "ledger",
+            "PATH",
+            "Write each applied transaction with its account's balances to a CSV",
+        ),
+        flag(
+            "skip-unknown",
+            "Skip rows with an unrecognised type instead of failing",
+        ),
+        flag("skip-repeated-headers", "Skip rows repeating the header"),
+        flag(
+            "allow-reset",
+            "Accept reset transactions, for building fixtures",
+        ),
+        flag("allow-admin", "Accept freeze and unfreeze transactions"),
+        flag("normalize", "Write balances without trailing zeros"),
+        flag("explicit-sign", "Write positive balances with a leading +"),
+        option(
+            "amount-scale",
+            "SCALE",
+            "Round amounts to this many decimal places",
+        ),
+        option(
+            "amount-rounding",
+            "ROUNDING",
+            "How amounts are rounded: half-up, half-even or truncate",
+        ),
+        flag(
+            "warn-excess-precision",
+            "Warn about and round amounts beyond the amount scale",
+        ),
+        option(
+            "withdrawal-fee",
+            "FEE",
+            "Charge a flat fee, or a percentage like 2%, per withdrawal",
+        ),
+        option(
+            "precision",
+            "N",
+            "Write balances with this many decimal places, 4 by default",
+        ),
+        option(
+            "rounding",
+            "ROUNDING",
+            "How balances are written: half-up or half-even",
+        ),
+        flag(
+            "balanced-rounding",
+            "Write the held balance as total minus available",
+        ),
+        option("format", "FORMAT", "The output format: csv, json or fixed"),
+        flag(
+            "json-numbers",
+            "Write JSON balances as numbers rather than strings",
+        ),
+        option("output", "PATH", "Write the accounts to this file"),
+        option(
+            "output-mode",
+            "MODE",
+            "When the --output file exists: overwrite, append or fail",
+        ),
+        option("split", "N", "Split the output across N CSV files"),
+        option(
+            "output-prefix",
+            "PREFIX",
+            "The file name prefix for --split",
+        ),
+        option(
+            "sort-by",
+            "FIELD",
+            "Sort accounts by client, total, available or held",
+        ),
+        flag("desc", "Sort accounts in descending order"),
+        flag(
+            "gross-deposits",
+            "Add a column with each account's gross deposits",
+        ),
+        flag("locked-only", "Write only locked accounts"),
+        option(
+            "held-ratio-threshold",
+            "RATIO",
+            "Write only accounts holding more than this fraction of their total",
+        ),
+        option(
+            "normalize-client-ids",
+            "MAPPING_PATH",
+            "Renumber clients densely from 0, writing the original IDs to a CSV",
+        ),
+        option(
+            "flush-every",
+            "N",
+            "Flush the output after every N accounts",
+        ),
+        option(
+            "max-clients",
+            "N",
+            "Fail once more than N distinct clients are seen",
+        ),
+        option(
+            "block-clients",
+            "IDS",
+            "Skip every transaction for these clients",
+        ),
+        option(
+            "roster",
+            "IDS",
+            "Write a zeroed account for these clients if they're absent",
+        ),
+        flag(
+            "string-client-ids",
+            "Read clients as string identifiers and write them back out",
+        ),
+        option(
+            "input-scale",
+            "SCALE",
+            "Read amounts as whole numbers of minor units",
+        ),
+        flag(
+            "strict-dispute-flow",
+            "Fail on resolves and chargebacks of undisputed transactions",
+        ),
+        flag("strict-transaction-ids", "Fail on reused transaction IDs"),
+        option(
+            "dedupe-window",
+            "N",
+            "Remember only the last N transaction IDs of each account",
+        ),
+        flag(
+            "route-disputes",
+            "Apply disputes to the client that made the deposit",
+        ),
+        flag(
+            "resolve-keeps-held",
+            "Leave resolved funds held until released",
+        ),
+        flag("hold-withdrawals", "Hold withdrawn funds until settled"),
+        flag(
+            "dispute-withdrawals",
+            "Keep settled withdrawals so disputes can target them",
+        ),
+        flag(
+            "ignore-held-overflow",
+            "Ignore disputes that would overflow the held balance",
+        ),
+        option(
+            "applied-ids",
+            "PATH",
+            "Skip and record transaction IDs applied by earlier runs",
+        ),
+        option(
+            "since-tx",
+            "ID",
+            "Skip transactions with an ID at or below this",
+        ),
+        option(
+            "opening-balances",
+            "PATH",
+            "Start clients from the balances in this CSV",
+        ),
+        flag(
+            "tolerant-bool-locked",
+            "Accept yes/no and 1/0 for the opening balances' locked column",
+        ),
+        option(
+            "start-locked",
+            "IDS",
+            "Start these clients' accounts locked",
+        ),
+        option(
+            "seeded-lock-policy",
+            "POLICY",
+            "Reject or skip transactions for accounts that start locked",
+        ),
+        flag(
+            "fail-on-lock",
+            "Fail as soon as a transaction locks an account",
+        ),
+        flag(
+            "no-negative-available",
+            "Reject disputes that would leave the available balance negative",
+        ),
+    ]
+}
+
+fn flag(name: &'static str, help: &'static str) -> Arg {
+    Arg::new(name)
+        .long(name)
+        .action(ArgAction::SetTrue)
+        .help(help)
+}
+
+fn option(name: &'static str, value_name: &'static str, help: &'static str) -> Arg {
+    // Values may start with a hyphen, e.g. a negative amount to be rejected with a clear error
+    Arg::new(name)
+        .long(name)
+        .value_name(value_name)
+        .allow_hyphen_values(true)
+        .help(help)
+}
+
+fn parse_input_scale(value: &str) -> Result<u32> {
+    let scale: u32 = value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid input scale {}: {}", value, err)))?;
+
+    if scale > MAX_SCALE {
+        return Err(Error::msg(format!(
+            "Input scale {} exceeds the maximum of {}",
+            scale, MAX_SCALE
+        )));
+    }
+
+    Ok(scale)
+}
+
+fn parse_amount_scale(value: &str) -> Result<u32> {
+    let scale: u32 = value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid amount scale {}: {}", value, err)))?;
+
+    if scale > MAX_SCALE {
+        return Err(Error::msg(format!(
+            "Amount scale {} exceeds the maximum of {}",
+            scale, MAX_SCALE
+        )));
+    }
+
+    Ok(scale)
+}
+
+fn parse_precision(value: &str) -> Result<u32> {
+    value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid precision {}: {}", value, err)))
+}
+
+fn check_precision(precision: u32) -> Result<()> {
+    if precision > MAX_SCALE {
+        return Err(Error::msg(format!(
+            "Precision {} exceeds the maximum of {}",
+            precision, MAX_SCALE
+        )));
+    }
+    Ok(())
+}
+
+fn parse_split_shards(value: &str) -> Result<u16> {
+    let shards: u16 = value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid split count {}: {}", value, err)))?;
+
+    if shards == 0 {
+        return Err(Error::msg("Split count must be at least 1"));
+    }
+
+    Ok(shards)
+}
+
+fn parse_max_clients(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid maximum clients {}: {}", value, err)))
+}
+
+fn parse_dedupe_window(value: &str) -> Result<usize> {
+    let window: usize = value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid dedupe window {}: {}", value, err)))?;
+    if window == 0 {
+        return Err(Error::msg("Dedupe window must be at least 1"));
+    }
+    Ok(window)
+}
+
+fn parse_flush_every(value: &str) -> Result<usize> {
+    let flush_every: usize = value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid flush interval {}: {}", value, err)))?;
+    if flush_every == 0 {
+        return Err(Error::msg("Flush interval must be at least 1"));
+    }
+    Ok(flush_every)
+}
+
+fn parse_held_ratio_threshold(value: &str) -> Result<Decimal> {
+    let threshold: Decimal = value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid held ratio threshold {}: {}", value, err)))?;
+    if threshold.is_sign_negative() {
+        return Err(Error::msg("Held ratio threshold must not be negative"));
+    }
+    Ok(threshold)
+}
+
+fn parse_transaction_id(value: &str) -> Result<TransactionId> {
+    value
+        .parse()
+        .map_err(|err| Error::msg(format!("Invalid transaction ID {}: {}", value, err)))
+}
+
+fn parse_client_ids(value: &str) -> Result<HashSet<ClientId>> {
+    value
+        .split(',')
+        .map(|client_id| {
+            client_id
+                .trim()
+                .parse()
+                .map_err(|err| Error::msg(format!("Invalid client ID {}: {}", client_id, err)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::{
+        assert_err::assert_err,
+        domain::account_policy::WithdrawalFee,
+        output::output_options::{OutputFormat, Rounding},
+    };
+    use anyhow::Result;
+    use clap::error::ErrorKind;
+    use rust_decimal_macros::dec;
+    use std::collections::HashSet;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_csv_path_and_flags_in_any_order() -> Result<()> {
+        let options = Options::from_args(&args(&[
+            "payments-engine",
+            "--stats",
+            "tx.csv",
+            "--allow-zero",
+        ]))?;
+
+        assert_eq!("tx.csv", options.csv_path);
+        assert!(options.print_stats);
+        assert!(options.processing.parse.allow_zero_amounts);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_missing_csv_path() -> Result<()> {
+        assert_err!(
+            Options::from_args(&args(&["payments-engine", "--stats"])),
+            "Missing CSV path argument. Example: cargo run -- transactions.csv"
+        );
+        Ok(())
+    }
+
+    fn usage_error_kind(result: Result<Options>) -> Option<ErrorKind> {
+        result
+            .err()
+            .and_then(|err| err.downcast_ref::<clap::Error>().map(clap::Error::kind))
+    }
+
+    #[test]
+    fn fails_to_read_unknown_option() -> Result<()> {
+        let result = Options::from_args(&args(&["payments-engine", "--bogus", "tx.csv"]));
+        assert_eq!(Some(ErrorKind::UnknownArgument), usage_error_kind(result));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_csv_path_after_process_subcommand() -> Result<()> {
+        let options =
+            Options::from_args(&args(&["payments-engine", "process", "--stats", "tx.csv"]))?;
+
+        assert_eq!("tx.csv", options.csv_path);
+        assert!(options.print_stats);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_help_as_a_request_to_display_it() -> Result<()> {
+        let result = Options::from_args(&args(&["payments-engine", "--help"]));
+        assert_eq!(Some(ErrorKind::DisplayHelp), usage_error_kind(result));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_last_value_of_repeated_option() -> Result<()> {
+        let options = Options::from_args(&args(&[
+            "payments-engine",
+            "tx.csv",
+            "--format",
+            "json",
+            "--format",
+            "fixed",
+        ]))?;
+
+        assert_eq!(OutputFormat::Fixed, options.output.format);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_option_values() -> Result<()> {
+        let options = Options::from_args(&args(&[
+            "payments-engine",
+            "tx.csv",
+            "--rounding",
+            "half-even",
+        ]))?;
+
+        assert_eq!(Some(Rounding::HalfEven), options.output.rounding);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_missing_option_value() -> Result<()> {
+        let result = Options::from_args(&args(&["payments-engine", "tx.csv", "--rounding"]));
+        assert_eq!(Some(ErrorKind::InvalidValue), usage_error_kind(result));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_blocked_client_list() -> Result<()> {
+        let options = Options::from_args(&args(&[
+            "payments-engine",
+            "tx.csv",
+            "--block-clients",
+            "3,7,9",
+        ]))?;
+
+        assert_eq!(HashSet::from([3, 7, 9]), options.processing.blocked_clients);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_invalid_blocked_client() -> Result<()> {
+        assert_err!(
+            Options::from_args(&args(&[
+                "payments-engine",
+                "tx.csv",
+                "--block-clients",
+                "3,x"
+            ])),
+            "Invalid client ID x: invalid digit found in string"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_input_scale_beyond_decimal_precision() -> Result<()> {
+        assert_err!(
+            Options::from_args(&args(&["payments-engine", "tx.csv", "--input-scale", "29"])),
+            "Input scale 29 exceeds the maximum of 28"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_precision_beyond_decimal_precision() -> Result<()> {
+        assert_err!(
+            Options::from_args(&args(&["payments-engine", "tx.csv", "--precision", "29"])),
+            "Precision 29 exceeds the maximum of 28"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_flat_and_percentage_withdrawal_fees() -> Result<()> {
+        let read_fee = |fee: &str| {
+            Options::from_args(&args(&[
+                "payments-engine",
+                "tx.csv",
+                "--withdrawal-fee",
+                fee,
+            ]))
+            .map(|options| options.processing.account_policy.withdrawal_fee)
+        };
+
+        assert_eq!(WithdrawalFee::Flat(dec!(1.50)), read_fee("1.50")?);
+        assert_eq!(WithdrawalFee::Percentage(dec!(2)), read_fee("2%")?);
+        let result = read_fee("-1");
+        assert_err!(result, "Invalid withdrawal fee -1: must not be negative");
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_split_without_output_prefix() -> Result<()> {
+        assert_err!(
+            Options::from_args(&args(&["payments-engine", "tx.csv", "--split", "2"])),
+            "--split and --output-prefix must be used together"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_read_desc_without_sort_by() -> Result<()> {
+        assert_err!(
+            Options::from_args(&args(&["payments-engine", "tx.csv", "--desc"])),
+            "--desc requires --sort-by"
+        );
+        Ok(())
+    }
+}
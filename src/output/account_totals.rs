@@ -0,0 +1,87 @@
+use crate::domain::client_account::ClientAccount;
+use anyhow::{Error, Result};
+use rust_decimal::Decimal;
+use std::fmt;
+
+/// Balances summed across every account, e.g. for a treasury summary.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AccountTotals {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+}
+
+impl AccountTotals {
+    /// Sums the balances of `client_accounts`. Each account's balances fit in a `Decimal`
+    /// but their sum may not, so this fails rather than overflowing.
+    pub fn of<'a>(
+        client_accounts: impl IntoIterator<Item = &'a ClientAccount>,
+    ) -> Result<AccountTotals> {
+        client_accounts
+            .into_iter()
+            .try_fold(AccountTotals::default(), |totals, account| {
+                Some(AccountTotals {
+                    available: totals.available.checked_add(account.available_balance)?,
+                    held: totals.held.checked_add(account.held_balance)?,
+                    total: totals.total.checked_add(account.total_balance)?,
+                })
+            })
+            .ok_or(Error::msg("Aggregate total overflow"))
+    }
+}
+
+impl fmt::Display for AccountTotals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total available: {}", self.available)?;
+        writeln!(f, "total held: {}", self.held)?;
+        writeln!(f, "total: {}", self.total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccountTotals;
+    use crate::{assert_err::assert_err, domain::client_account::ClientAccount};
+    use anyhow::Result;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn account(client_id: u16, available_balance: Decimal) -> ClientAccount {
+        let mut account = ClientAccount::new(client_id);
+        account.available_balance = available_balance;
+        account.total_balance = available_balance;
+        account
+    }
+
+    #[test]
+    fn sums_balances_across_accounts() -> Result<()> {
+        let mut disputed = account(2, dec!(1));
+        disputed.held_balance = dec!(2.5);
+        disputed.total_balance = dec!(3.5);
+
+        let totals = AccountTotals::of(&[account(1, dec!(10)), disputed])?;
+
+        assert_eq!(
+            AccountTotals {
+                available: dec!(11),
+                held: dec!(2.5),
+                total: dec!(13.5),
+            },
+            totals
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_sum_balances_that_overflow() -> Result<()> {
+        assert_err!(
+            AccountTotals::of(&[
+                account(1, Decimal::MAX),
+                account(2, Decimal::MAX),
+                account(3, Decimal::MAX),
+            ]),
+            "Aggregate total overflow"
+        );
+        Ok(())
+    }
+}
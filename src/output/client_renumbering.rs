@@ -0,0 +1,84 @@
+use super::{csv_output::filter_accounts, output_options::OutputOptions};
+use crate::domain::client_account::{ClientAccount, ClientId};
+use anyhow::{Error, Result};
+use csv::Writer;
+
+/// Renumbers the clients of the accounts to be written densely from 0, in ascending order
+/// of their original IDs, for downstream tools that handle sparse IDs badly. The mapping
+/// is written to a CSV at `mapping_path` with `client,original_client` columns. Accounts
+/// the options filter out are dropped first, so they don't leave gaps.
+pub fn renumber_clients(
+    client_accounts: &mut Vec<ClientAccount>,
+    mapping_path: &str,
+    options: &OutputOptions,
+) -> Result<()> {
+    filter_accounts(client_accounts, options);
+    client_accounts.sort_by_key(|account| account.client_id);
+
+    let mut mapping = Writer::from_path(mapping_path).map_err(|err| {
+        Error::msg(format!(
+            "Failed to create client ID mapping file at path {}: {}",
+            mapping_path, err
+        ))
+    })?;
+    mapping.write_record(["client", "original_client"])?;
+
+    // There are never more accounts than client IDs, so every new ID fits
+    for (client_id, account) in (0..=ClientId::MAX).zip(client_accounts.iter_mut()) {
+        mapping.write_record([client_id.to_string(), account.client_id.to_string()])?;
+        account.client_id = client_id;
+    }
+
+    mapping.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::renumber_clients;
+    use crate::{
+        domain::client_account::ClientAccount, output::output_options::OutputOptions,
+        test_fixture::write_fixture,
+    };
+    use anyhow::Result;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::fs;
+
+    #[test]
+    fn renumbers_clients_densely_in_ascending_order() -> Result<()> {
+        let mapping_path = write_fixture("client-id-mapping", "")?;
+        let mut client_accounts: Vec<ClientAccount> = [60000, 1, 5000]
+            .into_iter()
+            .map(|client_id| {
+                let mut account = ClientAccount::new(client_id);
+                account.total_balance = Decimal::from(client_id);
+                account
+            })
+            .collect();
+
+        renumber_clients(
+            &mut client_accounts,
+            &mapping_path,
+            &OutputOptions::default(),
+        )?;
+
+        let renumbered: Vec<_> = client_accounts
+            .iter()
+            .map(|account| (account.client_id, account.total_balance))
+            .collect();
+        assert_eq!(
+            vec![(0, dec!(1)), (1, dec!(5000)), (2, dec!(60000))],
+            renumbered
+        );
+        assert_eq!(
+            "client,original_client\n\
+             0,1\n\
+             1,5000\n\
+             2,60000\n",
+            fs::read_to_string(mapping_path)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,552 @@
+use super::output_options::{OutputOptions, SortKey};
+use crate::domain::client_account::ClientAccount;
+use anyhow::Result;
+use csv::{QuoteStyle, Writer, WriterBuilder};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::io::Write;
+
+pub fn write_accounts<W: Write>(
+    output: W,
+    client_accounts: Vec<ClientAccount>,
+    options: &OutputOptions,
+) -> Result<()> {
+    write_csv(output, client_accounts, options, true)
+}
+
+/// Writes the accounts like [`write_accounts`] but without the header, e.g. when adding
+/// them to a file that already has one.
+pub fn write_accounts_without_header<W: Write>(
+    output: W,
+    client_accounts: Vec<ClientAccount>,
+    options: &OutputOptions,
+) -> Result<()> {
+    write_csv(output, client_accounts, options, false)
+}
+
+fn write_csv<W: Write>(
+    output: W,
+    mut client_accounts: Vec<ClientAccount>,
+    options: &OutputOptions,
+    include_header: bool,
+) -> Result<()> {
+    filter_accounts(&mut client_accounts, options);
+    sort_accounts(&mut client_accounts, options);
+
+    let mut writer = csv_writer(output);
+
+    if include_header {
+        let mut header = vec!["client", "available", "held", "total", "locked"];
+        if options.include_gross_deposits {
+            header.push("gross_deposits");
+        }
+        writer.write_record(header)?;
+    }
+
+    for (written, account) in (1..).zip(client_accounts) {
+        let (available_balance, held_balance, total_balance) =
+            displayed_balances(&account, options);
+        let account = ClientAccount {
+            available_balance,
+            held_balance,
+            total_balance,
+            ..account
+        };
+        let mut record = account
+            .summary_row_with(|balance| format_balance(balance, options))
+            .to_vec();
+        if options.include_gross_deposits {
+            record.push(format_balance(account.gross_deposits, options));
+        }
+        writer.write_record(record)?;
+        if flush_due(written, options) {
+            writer.flush()?;
+        }
+    }
+
+    // Taking the output back flushes it, where dropping the writer would flush it again
+    writer.into_inner().map_err(|err| err.into_error())?;
+
+    Ok(())
+}
+
+/// Whether the output should be flushed after writing the `written`th account.
+pub(crate) fn flush_due(written: usize, options: &OutputOptions) -> bool {
+    options
+        .flush_every
+        .is_some_and(|flush_every| written.is_multiple_of(flush_every))
+}
+
+pub(crate) fn filter_accounts(client_accounts: &mut Vec<ClientAccount>, options: &OutputOptions) {
+    if options.locked_only {
+        client_accounts.retain(|account| account.locked);
+    }
+    if let Some(threshold) = options.held_ratio_threshold {
+        client_accounts.retain(|account| exceeds_held_ratio(account, threshold));
+    }
+}
+
+/// Whether the account's held balance is more than `threshold` of its total. An account
+/// with nothing in total exceeds any threshold if it holds anything, since its held funds
+/// are backed by a negative available balance.
+fn exceeds_held_ratio(account: &ClientAccount, threshold: Decimal) -> bool {
+    if account.total_balance <= Decimal::ZERO {
+        return account.held_balance > Decimal::ZERO;
+    }
+    // Division only overflows when the ratio is far beyond any sensible threshold
+    account
+        .held_balance
+        .checked_div(account.total_balance)
+        .is_none_or(|ratio| ratio > threshold)
+}
+
+pub(crate) fn sort_accounts(client_accounts: &mut [ClientAccount], options: &OutputOptions) {
+    let Some(sort_by) = options.sort_by else {
+        return;
+    };
+
+    // Ties are broken by client ID so the output is deterministic
+    client_accounts.sort_by(|a, b| {
+        let ordering = match sort_by {
+            SortKey::Client => a.client_id.cmp(&b.client_id),
+            SortKey::Total => a.total_balance.cmp(&b.total_balance),
+            SortKey::Available => a.available_balance.cmp(&b.available_balance),
+            SortKey::Held => a.held_balance.cmp(&b.held_balance),
+        }
+        .then(a.client_id.cmp(&b.client_id));
+
+        if options.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Builds the writer for account output. Fields are quoted only when they contain a
+/// delimiter, quote or line break, with embedded quotes doubled, as RFC 4180 requires.
+/// These match the `csv` defaults but are set explicitly so the output format can't
+/// change with the crate.
+fn csv_writer<W: Write>(output: W) -> Writer<W> {
+    WriterBuilder::new()
+        .delimiter(b',')
+        .quote(b'"')
+        .quote_style(QuoteStyle::Necessary)
+        .double_quote(true)
+        .from_writer(output)
+}
+
+/// The available, held and total balances to write. With balanced rounding, the total and
+/// available are rounded and the held balance is derived from them, so the written
+/// columns always add up even when rounding each would not.
+pub(crate) fn displayed_balances(
+    account: &ClientAccount,
+    options: &OutputOptions,
+) -> (Decimal, Decimal, Decimal) {
+    if !options.balanced_rounding {
+        return (
+            account.available_balance,
+            account.held_balance,
+            account.total_balance,
+        );
+    }
+
+    let strategy = options
+        .rounding
+        .map_or(RoundingStrategy::ToZero, |rounding| rounding.strategy());
+    let total = account
+        .total_balance
+        .round_dp_with_strategy(options.precision(), strategy);
+    let available = account
+        .available_balance
+        .round_dp_with_strategy(options.precision(), strategy);
+    (available, total - available, total)
+}
+
+pub(crate) fn format_balance(balance: Decimal, options: &OutputOptions) -> String {
+    let balance = match options.rounding {
+        Some(rounding) => balance.round_dp_with_strategy(options.precision(), rounding.strategy()),
+        None => balance,
+    };
+
+    let formatted = if options.normalize_balances {
+        balance.normalize().to_string()
+    } else {
+        format!("{:.*}", options.precision() as usize, balance)
+    };
+
+    // Checking the written digits rather than the balance leaves a balance that rounds to
+    // zero unsigned
+    let is_positive =
+        !formatted.starts_with('-') && formatted.bytes().any(|byte| matches!(byte, b'1'..=b'9'));
+    if options.explicit_sign && is_positive {
+        format!("+{}", formatted)
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_writer, format_balance, write_accounts};
+    use crate::{
+        domain::client_account::{ClientAccount, ClientId},
+        output::output_options::{OutputOptions, Rounding, SortKey},
+    };
+    use anyhow::Result;
+    use csv::{ReaderBuilder, StringRecord};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::io::{self, Write};
+
+    /// Discards what's written, counting how many times it's flushed.
+    #[derive(Default)]
+    struct FlushCounter {
+        flushes: usize,
+    }
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn account(client_id: ClientId, available_balance: Decimal) -> ClientAccount {
+        let mut account = ClientAccount::new(client_id);
+        account.available_balance = available_balance;
+        account.total_balance = available_balance;
+        account
+    }
+
+    #[test]
+    fn writes_balances_at_four_decimal_places() -> Result<()> {
+        let mut output = Vec::new();
+
+        write_accounts(
+            &mut output,
+            vec![account(1, dec!(12.55))],
+            &OutputOptions::default(),
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,12.5500,0.0000,12.5500,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn flushes_after_every_n_accounts_and_at_the_end() -> Result<()> {
+        let accounts = || {
+            (1..=5)
+                .map(|client_id| account(client_id, dec!(1)))
+                .collect()
+        };
+
+        for (flush_every, expected_flushes) in [(None, 1), (Some(2), 3), (Some(1), 6)] {
+            let mut output = FlushCounter::default();
+            let options = OutputOptions {
+                flush_every,
+                ..Default::default()
+            };
+            write_accounts(&mut output, accounts(), &options)?;
+            assert_eq!(expected_flushes, output.flushes, "{:?}", flush_every);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writes_balanced_columns_with_balanced_rounding() -> Result<()> {
+        let account = || {
+            let mut account = ClientAccount::new(1);
+            account.available_balance = dec!(0.00005);
+            account.held_balance = dec!(0.00005);
+            account.total_balance = dec!(0.0001);
+            account
+        };
+        let options = OutputOptions {
+            rounding: Some(Rounding::HalfUp),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        write_accounts(&mut output, vec![account()], &options)?;
+        // Rounded independently, 0.0001 + 0.0001 doesn't add up to 0.0001
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,0.0001,0.0001,0.0001,false\n",
+            String::from_utf8(output)?
+        );
+
+        let mut output = Vec::new();
+        write_accounts(
+            &mut output,
+            vec![account()],
+            &OutputOptions {
+                balanced_rounding: true,
+                ..options
+            },
+        )?;
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,0.0001,0.0000,0.0001,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_normalized_balances_identically_regardless_of_scale() -> Result<()> {
+        let mut output = Vec::new();
+
+        write_accounts(
+            &mut output,
+            vec![account(1, dec!(12.5500)), account(2, dec!(12.55))],
+            &OutputOptions {
+                normalize_balances: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,12.55,0,12.55,false\n\
+             2,12.55,0,12.55,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_accounts_by_client_ascending() -> Result<()> {
+        let mut output = Vec::new();
+
+        write_accounts(
+            &mut output,
+            vec![
+                account(3, dec!(1)),
+                account(1, dec!(2)),
+                account(2, dec!(3)),
+            ],
+            &OutputOptions {
+                normalize_balances: true,
+                sort_by: Some(SortKey::Client),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,2,0,2,false\n\
+             2,3,0,3,false\n\
+             3,1,0,1,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_accounts_by_total_descending() -> Result<()> {
+        let mut output = Vec::new();
+
+        write_accounts(
+            &mut output,
+            vec![
+                account(1, dec!(2)),
+                account(2, dec!(10)),
+                account(3, dec!(-1)),
+                account(4, dec!(2)),
+            ],
+            &OutputOptions {
+                normalize_balances: true,
+                sort_by: Some(SortKey::Total),
+                descending: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             2,10,0,10,false\n\
+             4,2,0,2,false\n\
+             1,2,0,2,false\n\
+             3,-1,0,-1,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_only_locked_accounts_when_filtering() -> Result<()> {
+        let mut output = Vec::new();
+        let mut locked = account(2, dec!(0));
+        locked.locked = true;
+
+        write_accounts(
+            &mut output,
+            vec![account(1, dec!(5)), locked, account(3, dec!(1))],
+            &OutputOptions {
+                normalize_balances: true,
+                locked_only: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             2,0,0,0,true\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_only_accounts_above_the_held_ratio_threshold() -> Result<()> {
+        let mut output = Vec::new();
+        let with_held = |client_id, available, held| {
+            let mut account = account(client_id, available);
+            account.held_balance = held;
+            account.total_balance = available + held;
+            account
+        };
+
+        write_accounts(
+            &mut output,
+            vec![
+                // Exactly at the threshold, so not flagged
+                with_held(1, dec!(5), dec!(5)),
+                with_held(2, dec!(4), dec!(6)),
+                // Nothing in total but something held, so flagged despite the zero total
+                with_held(3, dec!(-2), dec!(2)),
+                with_held(4, dec!(0), dec!(0)),
+            ],
+            &OutputOptions {
+                normalize_balances: true,
+                held_ratio_threshold: Some(dec!(0.5)),
+                sort_by: Some(SortKey::Client),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             2,4,6,10,false\n\
+             3,-2,2,0,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_positive_balances_with_an_explicit_sign() -> Result<()> {
+        let mut output = Vec::new();
+
+        write_accounts(
+            &mut output,
+            vec![
+                account(1, dec!(12.5555)),
+                account(2, dec!(0)),
+                account(3, dec!(-12.5555)),
+            ],
+            &OutputOptions {
+                explicit_sign: true,
+                sort_by: Some(SortKey::Client),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,+12.5555,0.0000,+12.5555,false\n\
+             2,0.0000,0.0000,0.0000,false\n\
+             3,-12.5555,0.0000,-12.5555,false\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_gross_deposits_column_when_included() -> Result<()> {
+        let mut output = Vec::new();
+        let mut account = account(1, dec!(6));
+        account.gross_deposits = dec!(10);
+
+        write_accounts(
+            &mut output,
+            vec![account],
+            &OutputOptions {
+                include_gross_deposits: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            "client,available,held,total,locked,gross_deposits\n\
+             1,6.0000,0.0000,6.0000,false,10.0000\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rounds_midpoint_balances_half_up() {
+        let options = OutputOptions {
+            rounding: Some(Rounding::HalfUp),
+            ..Default::default()
+        };
+
+        assert_eq!("0.0001", format_balance(dec!(0.00005), &options));
+        assert_eq!("0.0002", format_balance(dec!(0.00015), &options));
+    }
+
+    #[test]
+    fn rounds_midpoint_balances_half_even() {
+        let options = OutputOptions {
+            rounding: Some(Rounding::HalfEven),
+            ..Default::default()
+        };
+
+        assert_eq!("0.0000", format_balance(dec!(0.00005), &options));
+        assert_eq!("0.0002", format_balance(dec!(0.00015), &options));
+    }
+
+    #[test]
+    fn quotes_fields_containing_delimiters_quotes_and_line_breaks() -> Result<()> {
+        let mut output = Vec::new();
+
+        let mut writer = csv_writer(&mut output);
+        writer.write_record(["1", "a,b", "say \"hi\"", "two\nlines", "plain"])?;
+        writer.flush()?;
+        drop(writer);
+
+        assert_eq!(
+            "1,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",plain\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_back_a_field_containing_a_comma() -> Result<()> {
+        let mut output = Vec::new();
+        let record = StringRecord::from(vec!["1", "12.5500", "Acme, Inc.", "false"]);
+
+        let mut writer = csv_writer(&mut output);
+        writer.write_record(&record)?;
+        writer.flush()?;
+        drop(writer);
+
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(output.as_slice());
+        let mut read_back = StringRecord::new();
+        assert!(reader.read_record(&mut read_back)?);
+
+        assert_eq!(record, read_back);
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use super::{
+    csv_output::{displayed_balances, filter_accounts, flush_due, format_balance, sort_accounts},
+    output_options::OutputOptions,
+};
+use crate::domain::client_account::ClientAccount;
+use anyhow::{Error, Result};
+use std::io::Write;
+
+/// The width of the client column, which fits any client ID but not every client label.
+const CLIENT_WIDTH: usize = 5;
+/// The width of each balance column.
+const AMOUNT_WIDTH: usize = 20;
+/// The width of the locked column, which fits `false`.
+const LOCKED_WIDTH: usize = 6;
+
+/// Writes each account as a line of fixed-width fields with no delimiters or header, for
+/// systems that read columns by position. The client and balances are right-aligned and
+/// the locked flag is left-aligned, all padded with spaces. A balance too wide for its
+/// column is an error rather than being truncated.
+pub fn write_accounts_fixed_width<W: Write>(
+    mut output: W,
+    mut client_accounts: Vec<ClientAccount>,
+    options: &OutputOptions,
+) -> Result<()> {
+    filter_accounts(&mut client_accounts, options);
+    sort_accounts(&mut client_accounts, options);
+
+    for (written, account) in (1..).zip(client_accounts) {
+        let (available, held, total) = displayed_balances(&account, options);
+        let amount_field = |balance| amount_field(&account, format_balance(balance, options));
+
+        let client = account.client_label();
+        if client.len() > CLIENT_WIDTH {
+            return Err(Error::msg(format!(
+                "Client {} is wider than the fixed-width column of {} characters",
+                client, CLIENT_WIDTH
+            )));
+        }
+        let mut line = format!("{:>CLIENT_WIDTH$}", client);
+        line.push_str(&amount_field(available)?);
+        line.push_str(&amount_field(held)?);
+        line.push_str(&amount_field(total)?);
+        line.push_str(&format!("{:<LOCKED_WIDTH$}", account.locked));
+        if options.include_gross_deposits {
+            line.push_str(&amount_field(account.gross_deposits)?);
+        }
+        writeln!(output, "{}", line)?;
+        if flush_due(written, options) {
+            output.flush()?;
+        }
+    }
+
+    output.flush()?;
+
+    Ok(())
+}
+
+fn amount_field(account: &ClientAccount, amount: String) -> Result<String> {
+    if amount.len() > AMOUNT_WIDTH {
+        return Err(Error::msg(format!(
+            "Balance {} for client {} is wider than the fixed-width column of {} characters",
+            amount,
+            account.client_label(),
+            AMOUNT_WIDTH
+        )));
+    }
+    Ok(format!("{:>AMOUNT_WIDTH$}", amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_accounts_fixed_width;
+    use crate::{
+        assert_err::assert_err, domain::client_account::ClientAccount,
+        output::output_options::OutputOptions,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn writes_fields_at_fixed_positions() -> Result<()> {
+        let mut account = ClientAccount::new(42);
+        account.available_balance = dec!(1.5);
+        account.held_balance = dec!(2);
+        account.total_balance = dec!(3.5);
+        account.locked = true;
+
+        let mut output = Vec::new();
+        write_accounts_fixed_width(&mut output, vec![account], &OutputOptions::default())?;
+        let output = String::from_utf8(output)?;
+
+        assert_eq!(
+            "   42              1.5000              2.0000              3.5000true  \n",
+            output
+        );
+        let line = output.trim_end_matches('\n');
+        assert_eq!(71, line.len());
+        assert_eq!("   42", &line[0..5]);
+        assert_eq!("1.5000", line[5..25].trim_start());
+        assert_eq!("2.0000", line[25..45].trim_start());
+        assert_eq!("3.5000", line[45..65].trim_start());
+        assert_eq!("true  ", &line[65..71]);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_write_balance_wider_than_its_column() -> Result<()> {
+        let mut account = ClientAccount::new(1);
+        account.available_balance = dec!(12345678901234567);
+        account.total_balance = dec!(12345678901234567);
+
+        let result =
+            write_accounts_fixed_width(Vec::new(), vec![account], &OutputOptions::default());
+        assert_err!(
+            result,
+            "Balance 12345678901234567.0000 for client 1 is wider than the fixed-width column of 20 characters"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use super::{
+    csv_output::{displayed_balances, filter_accounts, flush_due, format_balance, sort_accounts},
+    output_options::OutputOptions,
+};
+use crate::domain::{
+    client_account::{ClientAccount, ClientId},
+    lock_reason::LockReason,
+    transaction::TransactionId,
+};
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::value::RawValue;
+use std::io::Write;
+
+/// An account as written in JSON. Balances are formatted as in the CSV output and written
+/// as strings by default, so no precision is lost to a consumer parsing them as floats.
+#[derive(Debug, Serialize)]
+struct JsonAccount {
+    client: JsonClient,
+    available: Box<RawValue>,
+    held: Box<RawValue>,
+    total: Box<RawValue>,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lock_reason: Option<LockReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gross_deposits: Option<Box<RawValue>>,
+    disputed_transaction_ids: Vec<TransactionId>,
+}
+
+/// A client written as its numeric ID, or as its label for clients identified by strings.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum JsonClient {
+    Id(ClientId),
+    Label(String),
+}
+
+/// Writes the accounts as a JSON array, including the IDs of each account's
+/// currently disputed deposits.
+pub fn write_accounts_json<W: Write>(
+    mut output: W,
+    mut client_accounts: Vec<ClientAccount>,
+    options: &OutputOptions,
+) -> Result<()> {
+    filter_accounts(&mut client_accounts, options);
+    sort_accounts(&mut client_accounts, options);
+
+    let json_accounts = client_accounts
+        .iter()
+        .map(|account| -> Result<JsonAccount> {
+            let mut disputed_transaction_ids: Vec<TransactionId> =
+                account.disputed_deposits.keys().copied().collect();
+            disputed_transaction_ids.sort_unstable();
+
+            let (available, held, total) = displayed_balances(account, options);
+
+            Ok(JsonAccount {
+                client: match &account.label {
+                    Some(label) => JsonClient::Label(label.clone()),
+                    None => JsonClient::Id(account.client_id),
+                },
+                available: json_balance(available, options)?,
+                held: json_balance(held, options)?,
+                total: json_balance(total, options)?,
+                locked: account.locked,
+                lock_reason: account.lock_reason,
+                gross_deposits: options
+                    .include_gross_deposits
+                    .then(|| json_balance(account.gross_deposits, options))
+                    .transpose()?,
+                disputed_transaction_ids,
+            })
+        });
+
+    // Written an account at a time, rather than as one array, so it can be flushed as it goes
+    write!(output, "[")?;
+    for (written, json_account) in (1..).zip(json_accounts) {
+        if written > 1 {
+            write!(output, ",")?;
+        }
+        serde_json::to_writer(&mut output, &json_account?)?;
+        if flush_due(written, options) {
+            output.flush()?;
+        }
+    }
+    writeln!(output, "]")?;
+    output.flush()?;
+
+    Ok(())
+}
+
+/// The balance formatted as in the CSV output, written as a JSON number with exactly those
+/// digits when the options ask for numbers, or else as a string.
+fn json_balance(balance: Decimal, options: &OutputOptions) -> Result<Box<RawValue>> {
+    let formatted = format_balance(balance, options);
+    let json = if options.json_numbers {
+        formatted
+    } else {
+        serde_json::to_string(&formatted)?
+    };
+    Ok(RawValue::from_string(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_accounts_json;
+    use crate::{
+        domain::{
+            client_account::ClientAccount,
+            transaction::{Deposit, Dispute, Transaction, TransactionAction},
+        },
+        output::output_options::OutputOptions,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn writes_disputed_transaction_ids() -> Result<()> {
+        let mut account = ClientAccount::new(1);
+        for transaction_id in [1, 2] {
+            account.apply_transaction(Transaction {
+                client_id: 1,
+                transaction_id,
+                action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+            })?;
+        }
+        account.apply_transaction(Transaction {
+            client_id: 1,
+            transaction_id: 2,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+        let mut output = Vec::new();
+
+        write_accounts_json(&mut output, vec![account], &OutputOptions::default())?;
+
+        assert_eq!(
+            "[{\"client\":1,\"available\":\"5.0000\",\"held\":\"5.0000\",\"total\":\"10.0000\",\
+             \"locked\":false,\"disputed_transaction_ids\":[2]}]\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_the_lock_reason() -> Result<()> {
+        let mut account = ClientAccount::new(1);
+        account.apply_transaction(Transaction {
+            client_id: 1,
+            transaction_id: 3,
+            action: TransactionAction::Deposit(Deposit { amount: dec!(5) }),
+        })?;
+        account.apply_transaction(Transaction {
+            client_id: 1,
+            transaction_id: 3,
+            action: TransactionAction::Dispute(Dispute {
+                amount: None,
+                target: None,
+            }),
+        })?;
+        account.apply_transaction(Transaction {
+            client_id: 1,
+            transaction_id: 3,
+            action: TransactionAction::Chargeback,
+        })?;
+        let mut output = Vec::new();
+
+        write_accounts_json(&mut output, vec![account], &OutputOptions::default())?;
+
+        assert_eq!(
+            "[{\"client\":1,\"available\":\"0.0000\",\"held\":\"0.0000\",\"total\":\"0.0000\",\
+             \"locked\":true,\"lock_reason\":{\"reason\":\"chargeback\",\"transaction_id\":3},\
+             \"disputed_transaction_ids\":[]}]\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_balances_as_numbers_when_asked() -> Result<()> {
+        let mut account = ClientAccount::new(1);
+        account.apply_transaction(Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            action: TransactionAction::Deposit(Deposit {
+                amount: dec!(12.5555),
+            }),
+        })?;
+        let mut output = Vec::new();
+
+        write_accounts_json(
+            &mut output,
+            vec![account],
+            &OutputOptions {
+                json_numbers: true,
+                ..Default::default()
+            },
+        )?;
+
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("\"available\":12.5555,"));
+        assert!(!output.contains("\"12.5555\""));
+        Ok(())
+    }
+}
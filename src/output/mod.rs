@@ -0,0 +1,8 @@
+pub mod account_totals;
+pub mod client_renumbering;
+pub mod csv_output;
+pub mod fixed_width_output;
+pub mod json_output;
+pub mod output_file;
+pub mod output_options;
+pub mod split_output;
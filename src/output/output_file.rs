@@ -0,0 +1,77 @@
+use anyhow::{Error, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    str::FromStr,
+};
+
+/// A file to write the accounts to rather than stdout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFile {
+    pub path: String,
+    /// What to do when the file already exists.
+    pub mode: OutputMode,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Replace the file's contents.
+    #[default]
+    Overwrite,
+    /// Add to the end of the file, leaving out the header if the file already has content.
+    Append,
+    /// Fail rather than touch an existing file, so a retried run can't write twice.
+    Fail,
+}
+
+impl FromStr for OutputMode {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<OutputMode> {
+        match value {
+            "overwrite" => Ok(OutputMode::Overwrite),
+            "append" => Ok(OutputMode::Append),
+            "fail" => Ok(OutputMode::Fail),
+            _ => Err(Error::msg(format!(
+                "Unknown output mode {}. Expected overwrite, append or fail",
+                value
+            ))),
+        }
+    }
+}
+
+impl OutputFile {
+    /// Opens the file for writing as its mode says. Also returns whether the file already
+    /// has content being added to, in which case the header shouldn't be written again.
+    pub fn open(&self) -> Result<(File, bool)> {
+        let mut open_options = OpenOptions::new();
+        match self.mode {
+            OutputMode::Overwrite => open_options.write(true).create(true).truncate(true),
+            OutputMode::Append => open_options.append(true).create(true),
+            OutputMode::Fail => open_options.write(true).create_new(true),
+        };
+
+        let file = open_options.open(&self.path).map_err(|err| {
+            if err.kind() == ErrorKind::AlreadyExists {
+                return Error::msg(format!("Output file at path {} already exists", self.path));
+            }
+            Error::msg(format!(
+                "Failed to create output file at path {}: {}",
+                self.path, err
+            ))
+        })?;
+
+        let has_content = self.mode == OutputMode::Append
+            && file
+                .metadata()
+                .map_err(|err| {
+                    Error::msg(format!(
+                        "Failed to read output file at path {}: {}",
+                        self.path, err
+                    ))
+                })?
+                .len()
+                > 0;
+        Ok((file, has_content))
+    }
+}
@@ -0,0 +1,140 @@
+use super::{output_file::OutputFile, split_output::SplitOutput};
+use crate::domain::client_account::OUTPUT_PRECISION;
+use anyhow::{Error, Result};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OutputOptions {
+    /// Write balances without trailing zeros rather than at a fixed number of decimal
+    /// places, so equal amounts stored at different scales are written identically.
+    pub normalize_balances: bool,
+    /// How many decimal places balances are written with. Without this, four.
+    pub precision: Option<u32>,
+    /// Write positive balances with a leading `+`, so they align with negative ones. Zero
+    /// is written without a sign.
+    pub explicit_sign: bool,
+    /// How balances are rounded to the precision when written. Without this the excess
+    /// precision is truncated.
+    pub rounding: Option<Rounding>,
+    /// Round the total and available balances and write the held balance as their
+    /// difference, so the written columns always add up.
+    pub balanced_rounding: bool,
+    /// Which field accounts are ordered by when written. Without this the order is arbitrary.
+    pub sort_by: Option<SortKey>,
+    /// Add a `gross_deposits` column with the sum of each account's applied deposits.
+    pub include_gross_deposits: bool,
+    /// Write only locked accounts, e.g. to triage charged back clients.
+    pub locked_only: bool,
+    /// Write only accounts holding more than this fraction of their total balance, e.g.
+    /// `0.5` for accounts with over half their funds held, for risk review.
+    pub held_ratio_threshold: Option<Decimal>,
+    /// Order accounts from largest to smallest rather than smallest to largest.
+    pub descending: bool,
+    pub format: OutputFormat,
+    /// Write JSON balances as numbers rather than strings, for consumers that expect them.
+    /// Parsing them as floating point can lose precision.
+    pub json_numbers: bool,
+    /// Write the accounts across several CSV files rather than to stdout.
+    pub split: Option<SplitOutput>,
+    /// Write the accounts to a file rather than stdout.
+    pub file: Option<OutputFile>,
+    /// Renumber the written clients densely from 0 in ascending order of their IDs, writing
+    /// the original IDs to a CSV at this path.
+    pub client_id_mapping_path: Option<String>,
+    /// Flush the output after every this many accounts, so a slow consumer receives them
+    /// steadily. Without this the output is only flushed once all accounts are written.
+    pub flush_every: Option<usize>,
+}
+
+impl OutputOptions {
+    /// How many decimal places balances are written with.
+    pub fn precision(&self) -> u32 {
+        self.precision.unwrap_or(OUTPUT_PRECISION)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// A JSON array of accounts, which can also list each account's disputed transactions.
+    Json,
+    /// Space-padded columns of fixed widths with no delimiters, for legacy systems.
+    Fixed,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<OutputFormat> {
+        match value {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "fixed" => Ok(OutputFormat::Fixed),
+            _ => Err(Error::msg(format!(
+                "Unknown output format {}. Expected csv, json or fixed",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    HalfUp,
+    HalfEven,
+}
+
+impl Rounding {
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Rounding> {
+        match value {
+            "half-up" => Ok(Rounding::HalfUp),
+            "half-even" => Ok(Rounding::HalfEven),
+            _ => Err(Error::msg(format!(
+                "Unknown rounding {}. Expected half-up or half-even",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortKey {
+    Client,
+    Total,
+    Available,
+    Held,
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<SortKey> {
+        match value {
+            "client" => Ok(SortKey::Client),
+            "total" => Ok(SortKey::Total),
+            "available" => Ok(SortKey::Available),
+            "held" => Ok(SortKey::Held),
+            _ => Err(Error::msg(format!(
+                "Unknown sort key {}. Expected client, total, available or held",
+                value
+            ))),
+        }
+    }
+}
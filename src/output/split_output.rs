@@ -0,0 +1,79 @@
+use super::{csv_output::write_accounts, output_options::OutputOptions};
+use crate::domain::client_account::ClientAccount;
+use anyhow::{Error, Result};
+use std::{fs::File, io::BufWriter};
+
+/// Where and how finely to split the output, for sharded downstream ingestion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitOutput {
+    /// How many files to write. Each client is written to file `client % shards`.
+    pub shards: u16,
+    /// Files are named `{prefix}_{shard}.csv`.
+    pub prefix: String,
+}
+
+impl SplitOutput {
+    pub fn shard_path(&self, shard: u16) -> String {
+        format!("{}_{}.csv", self.prefix, shard)
+    }
+}
+
+/// Writes the accounts across `split.shards` CSV files, each with its own header and
+/// written as [`write_accounts`] would. A shard without accounts gets just a header.
+pub fn write_split_accounts(
+    client_accounts: Vec<ClientAccount>,
+    split: &SplitOutput,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut shards: Vec<Vec<ClientAccount>> = (0..split.shards).map(|_| Vec::new()).collect();
+    for account in client_accounts {
+        shards[(account.client_id % split.shards) as usize].push(account);
+    }
+
+    for (shard, accounts) in (0..split.shards).zip(shards) {
+        let path = split.shard_path(shard);
+        let file = File::create(&path).map_err(|err| {
+            Error::msg(format!(
+                "Failed to create output file at path {}: {}",
+                path, err
+            ))
+        })?;
+        write_accounts(BufWriter::new(file), accounts, options)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_split_accounts, SplitOutput};
+    use crate::{domain::client_account::ClientAccount, output::output_options::OutputOptions};
+    use anyhow::Result;
+    use std::{env, fs, process};
+
+    #[test]
+    fn writes_each_client_to_its_shard() -> Result<()> {
+        let prefix = env::temp_dir()
+            .join(format!("payments-engine-{}-split", process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let split = SplitOutput { shards: 2, prefix };
+        let accounts = [4, 1, 2, 3].map(ClientAccount::new).into();
+
+        write_split_accounts(accounts, &split, &OutputOptions::default())?;
+
+        let client_ids = |shard| -> Result<Vec<String>> {
+            let contents = fs::read_to_string(split.shard_path(shard))?;
+            let mut lines = contents.lines();
+            assert_eq!(Some("client,available,held,total,locked"), lines.next());
+            let mut client_ids: Vec<String> = lines
+                .map(|line| line.split(',').next().unwrap_or_default().to_string())
+                .collect();
+            client_ids.sort();
+            Ok(client_ids)
+        };
+        assert_eq!(vec!["2", "4"], client_ids(0)?);
+        assert_eq!(vec!["1", "3"], client_ids(1)?);
+        Ok(())
+    }
+}
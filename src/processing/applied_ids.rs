@@ -0,0 +1,102 @@
+use crate::domain::{client_account::ClientAccount, transaction::TransactionId};
+use anyhow::{Error, Result};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Write},
+};
+
+/// Loads the transaction IDs applied by earlier runs from the sidecar file at `path`,
+/// one per line. A missing file is treated as a first run with no IDs applied.
+pub fn load_applied_ids(path: &str) -> Result<HashSet<TransactionId>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => {
+            return Err(Error::msg(format!(
+                "Failed to read applied transaction IDs from {}: {}",
+                path, err
+            )))
+        }
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim().parse().map_err(|err| {
+                Error::msg(format!(
+                    "Invalid applied transaction ID {} in {}: {}",
+                    line, path, err
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Saves `previously_applied` together with every ID applied to `client_accounts` to the
+/// sidecar file at `path`, sorted so the file is stable between runs.
+pub fn save_applied_ids<'a>(
+    path: &str,
+    previously_applied: &HashSet<TransactionId>,
+    client_accounts: impl Iterator<Item = &'a ClientAccount>,
+) -> Result<()> {
+    let mut applied_ids: Vec<TransactionId> = previously_applied.iter().copied().collect();
+    for account in client_accounts {
+        applied_ids.extend(account.applied_transaction_ids.keys());
+    }
+    applied_ids.sort_unstable();
+    applied_ids.dedup();
+
+    let file = File::create(path).map_err(|err| {
+        Error::msg(format!(
+            "Failed to write applied transaction IDs to {}: {}",
+            path, err
+        ))
+    })?;
+    let mut writer = BufWriter::new(file);
+    for transaction_id in applied_ids {
+        writeln!(writer, "{}", transaction_id)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load_applied_ids;
+    use crate::test_fixture::write_fixture;
+    use anyhow::Result;
+    use std::collections::HashSet;
+
+    #[test]
+    fn loads_no_ids_from_a_missing_file() -> Result<()> {
+        assert!(load_applied_ids("/nonexistent/payments-engine-applied-ids")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn loads_ids_one_per_line() -> Result<()> {
+        let path = write_fixture("applied-ids", "1\n3\n\n7\n")?;
+
+        assert_eq!(HashSet::from([1, 3, 7]), load_applied_ids(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_to_load_an_invalid_id() -> Result<()> {
+        let path = write_fixture("applied-ids-invalid", "1\nx\n")?;
+
+        let error = load_applied_ids(&path).unwrap_err();
+
+        assert_eq!(
+            format!(
+                "Invalid applied transaction ID x in {}: invalid digit found in string",
+                path
+            ),
+            error.to_string()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,1976 @@
+use super::{
+    applied_ids::{load_applied_ids, save_applied_ids},
+    ledger::Ledger,
+    processing_failed::ProcessingFailed,
+    processing_metrics::ProcessingMetrics,
+    processing_options::ProcessingOptions,
+    processing_stats::ProcessingStats,
+    rejected_rows::RejectedRows,
+};
+use crate::{
+    csv::{
+        csv_opening_balance::read_opening_balances,
+        csv_reader::{csv_reader, is_header, transaction_records, transaction_records_at},
+        csv_transaction::{CsvTransaction, TRANSACTION_ID_COLUMN},
+    },
+    domain::{
+        apply_outcome::{ApplyOutcome, IgnoredReason},
+        client_account::{ClientAccount, ClientId},
+        engine::Engine,
+        transaction::{Transaction, TransactionId},
+        transaction_validator::TransactionValidator,
+    },
+};
+use anyhow::{Error, Result};
+use csv::{ByteRecord, StringRecord};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many parsed transactions may queue up ahead of the applier when pipelining.
+const PIPELINE_CAPACITY: usize = 1024;
+
+/// A transaction read from a CSV row, with the row's own ID if it has one.
+struct TransactionRow {
+    transaction: Transaction,
+    row_id: Option<TransactionId>,
+}
+
+/// Processes every transaction in the CSV at `csv_path`, or in each CSV in order of file
+/// name if it's a directory. Counts are recorded into `stats` as rows are read, so they
+/// remain available if processing fails part way.
+pub fn process_csv(
+    csv_path: &str,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Vec<ClientAccount>> {
+    Ok(process_csv_map(csv_path, options, stats)?
+        .into_values()
+        .collect())
+}
+
+/// Processes the CSV at `csv_path` like [`process_csv`], returning the accounts keyed by
+/// client ID so a client can be looked up directly.
+pub fn process_csv_map(
+    csv_path: &str,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<HashMap<ClientId, ClientAccount>> {
+    let records = transaction_records_at(csv_path, options.zstd_input)?;
+    Ok(apply_records(records, create_engine(options)?, options, None, stats)?.into_account_map())
+}
+
+/// Processes the CSV at `csv_path` like [`process_csv`], rejecting any transaction that
+/// `validator` fails before it's applied.
+pub fn process_csv_with_validator(
+    csv_path: &str,
+    options: &ProcessingOptions,
+    validator: Box<dyn TransactionValidator>,
+    stats: &mut ProcessingStats,
+) -> Result<Vec<ClientAccount>> {
+    let mut engine = create_engine(options)?;
+    engine.set_validator(validator);
+    Ok(apply_records(
+        transaction_records_at(csv_path, options.zstd_input)?,
+        engine,
+        options,
+        None,
+        stats,
+    )?
+    .into_accounts())
+}
+
+/// Processes every transaction in the CSV read from `source`, like [`process_csv`] but
+/// without requiring a file.
+pub fn process_reader<R: Read>(
+    source: R,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Vec<ClientAccount>> {
+    let records = transaction_records(csv_reader(source)?)?;
+    Ok(apply_records(records, create_engine(options)?, options, None, stats)?.into_accounts())
+}
+
+/// Processes records already split into fields, like [`process_reader`], whatever their
+/// source. Each record has the type, client, transaction ID and amount of a CSV row.
+pub fn process_records<I: IntoIterator<Item = csv::Result<StringRecord>>>(
+    records: I,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Vec<ClientAccount>> {
+    let records = records
+        .into_iter()
+        .map(|record| record.map(StringRecord::into_byte_record));
+    Ok(apply_records(records, create_engine(options)?, options, None, stats)?.into_accounts())
+}
+
+/// Applies each record to `engine`. With `metrics`, the time spent reading, parsing and
+/// applying each row is added to them.
+fn apply_records<I: IntoIterator<Item = csv::Result<ByteRecord>>>(
+    records: I,
+    mut engine: Engine,
+    options: &ProcessingOptions,
+    mut metrics: Option<&mut ProcessingMetrics>,
+    stats: &mut ProcessingStats,
+) -> Result<Engine> {
+    let previously_applied = load_previously_applied(options)?;
+    let mut ledger = create_ledger(options)?;
+    let mut rejected_rows = match &options.errors_path {
+        Some(path) => Some(RejectedRows::create(path)?),
+        None => None,
+    };
+
+    let mut records = records.into_iter();
+    let mut rows_processed = 0;
+    loop {
+        let started = start_timer(&metrics);
+        let Some(csv_record) = records.next() else {
+            break;
+        };
+        if stop_requested(options) {
+            break;
+        }
+        record_time(&mut metrics, started, ProcessingMetrics::record_read);
+
+        // Failing to read the file itself, e.g. on an over-long line, ends the records, so it
+        // fails the run rather than silently dropping every row after it
+        let rejected_rows = rejected_rows
+            .as_mut()
+            .filter(|_| !is_read_failure(&csv_record));
+        let line = record_line(&csv_record);
+        let transaction_id = if rejected_rows.is_some() {
+            record_transaction_id(&csv_record)
+        } else {
+            String::new()
+        };
+
+        // The errors file gives the line in its own column, so the error needn't repeat it
+        let started = start_timer(&metrics);
+        let row = match rejected_rows {
+            Some(_) => read_record(csv_record, options, stats),
+            None => read_transaction(csv_record, options, stats),
+        };
+        record_time(&mut metrics, started, ProcessingMetrics::record_parse);
+
+        let started = start_timer(&metrics);
+        let applied = row.and_then(|row| {
+            apply_row(
+                row,
+                &mut engine,
+                &previously_applied,
+                &mut ledger,
+                options,
+                stats,
+            )
+        });
+        record_time(&mut metrics, started, ProcessingMetrics::record_apply);
+        match (applied, rejected_rows) {
+            (Ok(()), _) => {}
+            (Err(err), Some(rejected_rows)) => rejected_rows.write(line, &transaction_id, &err)?,
+            (Err(err), None) => return Err(ProcessingFailed::wrap(rows_processed, err)),
+        }
+        rows_processed += 1;
+    }
+
+    if let Some(rejected_rows) = &mut rejected_rows {
+        rejected_rows.flush()?;
+    }
+    flush_ledger(&mut ledger)?;
+
+    finish(engine, &previously_applied, options)
+}
+
+/// Processes the CSV at `csv_path` like [`process_csv`], also measuring how long is spent
+/// reading, parsing and applying. Timing every row has a small cost, so it's kept out of
+/// [`process_csv`].
+pub fn process_csv_with_metrics(
+    csv_path: &str,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<(Vec<ClientAccount>, ProcessingMetrics)> {
+    apply_records_with_metrics(
+        transaction_records_at(csv_path, options.zstd_input)?,
+        options,
+        stats,
+    )
+}
+
+/// Processes the CSV read from `source` like [`process_csv_with_metrics`].
+pub fn process_reader_with_metrics<R: Read>(
+    source: R,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<(Vec<ClientAccount>, ProcessingMetrics)> {
+    apply_records_with_metrics(transaction_records(csv_reader(source)?)?, options, stats)
+}
+
+fn apply_records_with_metrics<I: IntoIterator<Item = csv::Result<ByteRecord>>>(
+    records: I,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<(Vec<ClientAccount>, ProcessingMetrics)> {
+    let mut metrics = ProcessingMetrics::default();
+    let engine = apply_records(
+        records,
+        create_engine(options)?,
+        options,
+        Some(&mut metrics),
+        stats,
+    )?;
+    Ok((engine.into_accounts(), metrics))
+}
+
+/// Processes the CSV at `csv_path` like [`process_csv`], but parses rows on a separate
+/// thread so parsing overlaps with applying. A single thread still applies every
+/// transaction, in file order, so per-client ordering is preserved.
+pub fn process_csv_pipelined(
+    csv_path: &str,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Vec<ClientAccount>> {
+    let records = transaction_records_at(csv_path, options.zstd_input)?;
+    let previously_applied = load_previously_applied(options)?;
+
+    let mut engine = create_engine(options)?;
+    let mut ledger = create_ledger(options)?;
+    // Skipped rows are sent too, so the applier can count every row processed
+    let (sender, receiver) =
+        mpsc::sync_channel::<Result<Option<TransactionRow>>>(PIPELINE_CAPACITY);
+
+    let (read_stats, applied) = thread::scope(|scope| {
+        let reader_thread = scope.spawn(move || {
+            let mut read_stats = ProcessingStats::default();
+
+            for csv_record in records {
+                if stop_requested(options) {
+                    break;
+                }
+                let row = read_transaction(csv_record, options, &mut read_stats);
+                let failed = row.is_err();
+
+                // A send only fails once the applier has stopped receiving
+                if sender.send(row).is_err() || failed {
+                    break;
+                }
+            }
+
+            read_stats
+        });
+
+        let mut applied = Ok(());
+        for (rows_processed, row) in receiver.into_iter().enumerate() {
+            applied = row
+                .and_then(|row| {
+                    apply_row(
+                        row,
+                        &mut engine,
+                        &previously_applied,
+                        &mut ledger,
+                        options,
+                        stats,
+                    )
+                })
+                .map_err(|err| ProcessingFailed::wrap(rows_processed, err));
+            if applied.is_err() {
+                break;
+            }
+        }
+
+        let read_stats = reader_thread.join().expect("CSV reader thread panicked");
+        (read_stats, applied)
+    });
+
+    stats.merge(read_stats);
+    applied?;
+    flush_ledger(&mut ledger)?;
+
+    Ok(finish(engine, &previously_applied, options)?.into_accounts())
+}
+
+/// Reads a single CSV record into a transaction, returning `None` if the row is skipped.
+/// Errors are prefixed with the line number of the record to help locate bad rows.
+fn read_transaction(
+    csv_record: csv::Result<ByteRecord>,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Option<TransactionRow>> {
+    let line = csv_record
+        .as_ref()
+        .ok()
+        .and_then(|record| record.position())
+        .map(|position| position.line());
+
+    read_record(csv_record, options, stats).map_err(|err| match line {
+        Some(line) => Error::msg(format!("line {}: {}", line, err)),
+        None => err,
+    })
+}
+
+/// Reads a single CSV record like [`read_transaction`], without the line number prefix.
+fn read_record(
+    csv_record: csv::Result<ByteRecord>,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Option<TransactionRow>> {
+    let mut record = csv_record
+        .map_err(|err| Error::msg(format!("Failed to parse CSV line: {}", err)))
+        .inspect_err(|_| stats.record_rejection())?;
+
+    if options.skip_repeated_headers && is_header(&record) {
+        return Ok(None);
+    }
+
+    parse_transaction(&mut record, options, stats)
+}
+
+/// Whether the record failed because the file itself couldn't be read, rather than because
+/// of what the row holds.
+fn is_read_failure(csv_record: &csv::Result<ByteRecord>) -> bool {
+    csv_record.as_ref().is_err_and(csv::Error::is_io_error)
+}
+
+fn record_transaction_id(csv_record: &csv::Result<ByteRecord>) -> String {
+    csv_record
+        .as_ref()
+        .ok()
+        .and_then(|record| record.get(TRANSACTION_ID_COLUMN))
+        .map(|transaction_id| String::from_utf8_lossy(transaction_id).trim().to_string())
+        .unwrap_or_default()
+}
+
+fn record_line(csv_record: &csv::Result<ByteRecord>) -> Option<u64> {
+    match csv_record {
+        Ok(record) => record.position(),
+        Err(err) => err.position(),
+    }
+    .map(|position| position.line())
+}
+
+fn parse_transaction(
+    record: &mut ByteRecord,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Option<TransactionRow>> {
+    let csv_transaction = CsvTransaction::from_byte_record(record, &options.parse)
+        .inspect_err(|_| stats.record_rejection())?;
+
+    if options.skip_unknown_types && !csv_transaction.has_known_type(&options.parse) {
+        log::warn!(
+            "Skipping transaction with ID {}: Unknown type {}",
+            csv_transaction.transaction_id,
+            csv_transaction.transaction_type
+        );
+        stats.record_rejection();
+        return Ok(None);
+    }
+
+    let row_id = csv_transaction.row_id;
+    csv_transaction
+        .to_transaction(&options.parse)
+        .map(|transaction| {
+            transaction.map(|transaction| TransactionRow {
+                transaction,
+                row_id,
+            })
+        })
+        .inspect_err(|_| stats.record_rejection())
+}
+
+/// Starts timing a stage of processing a row, if it's being measured.
+fn start_timer(metrics: &Option<&mut ProcessingMetrics>) -> Option<Instant> {
+    metrics.as_ref().map(|_| Instant::now())
+}
+
+fn record_time(
+    metrics: &mut Option<&mut ProcessingMetrics>,
+    started: Option<Instant>,
+    record: fn(&mut ProcessingMetrics, Duration),
+) {
+    if let (Some(metrics), Some(started)) = (metrics, started) {
+        record(metrics, started.elapsed());
+    }
+}
+
+fn stop_requested(options: &ProcessingOptions) -> bool {
+    let requested = options.stop.is_stop_requested();
+    if requested {
+        log::warn!("Stopping early: the output only includes rows read so far");
+    }
+    requested
+}
+
+fn create_engine(options: &ProcessingOptions) -> Result<Engine> {
+    let mut engine = Engine::with_accounts(load_opening_balances(options)?, options.account_policy);
+    if options.route_disputes_by_transaction {
+        engine.route_disputes_by_transaction();
+    }
+    Ok(engine)
+}
+
+fn load_opening_balances(options: &ProcessingOptions) -> Result<HashMap<ClientId, ClientAccount>> {
+    let mut accounts = match &options.opening_balances_path {
+        Some(path) => {
+            read_opening_balances(path, options.account_policy, options.tolerant_bool_locked)?
+        }
+        None => HashMap::new(),
+    };
+
+    for &client_id in &options.start_locked {
+        if !options.blocked_clients.contains(&client_id) {
+            accounts
+                .entry(client_id)
+                .or_insert_with(|| ClientAccount::with_policy(client_id, options.account_policy))
+                .seed_locked();
+        }
+    }
+    Ok(accounts)
+}
+
+fn load_previously_applied(options: &ProcessingOptions) -> Result<HashSet<TransactionId>> {
+    match &options.applied_ids_path {
+        Some(path) => load_applied_ids(path),
+        None => Ok(HashSet::new()),
+    }
+}
+
+/// Applies the transaction read from a row, if the row had one rather than being skipped.
+fn apply_row(
+    row: Option<TransactionRow>,
+    engine: &mut Engine,
+    previously_applied: &HashSet<TransactionId>,
+    ledger: &mut Option<Ledger>,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<()> {
+    match row {
+        Some(row) => apply_transaction(engine, row, previously_applied, ledger, options, stats),
+        None => Ok(()),
+    }
+}
+
+fn apply_transaction(
+    engine: &mut Engine,
+    row: TransactionRow,
+    previously_applied: &HashSet<TransactionId>,
+    ledger: &mut Option<Ledger>,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<()> {
+    let TransactionRow {
+        transaction,
+        row_id,
+    } = row;
+    if options.blocked_clients.contains(&transaction.client_id) {
+        stats.record_blocked();
+        return Ok(());
+    }
+
+    // Checked before the account is created so a client whose transactions were all
+    // applied by an earlier run doesn't appear in the output.
+    if transaction.action.is_deduplicated()
+        && previously_applied.contains(&transaction.transaction_id)
+    {
+        stats.record_skipped();
+        return Ok(());
+    }
+
+    if transaction.action.is_deduplicated()
+        && options
+            .since_transaction_id
+            .is_some_and(|since| transaction.transaction_id <= since)
+    {
+        stats.record_skipped();
+        return Ok(());
+    }
+
+    if let Some(max_clients) = options.max_clients {
+        if !engine.has_account(transaction.client_id) && engine.account_count() >= max_clients {
+            stats.record_rejection();
+            return Err(Error::msg(format!(
+                "Too many distinct clients: the maximum is {}",
+                max_clients
+            )));
+        }
+    }
+
+    stats.record_transaction(&transaction);
+    let transaction_id = transaction.transaction_id;
+    let transaction_type = transaction.action.type_name();
+
+    let outcome = engine
+        .apply_row(transaction, row_id)
+        .inspect_err(|_| stats.record_rejection())?;
+    match outcome {
+        ApplyOutcome::Ignored(IgnoredReason::AlreadyApplied | IgnoredReason::SeededLocked) => {
+            stats.record_skipped()
+        }
+        ApplyOutcome::Ignored(IgnoredReason::BeforeAnyDeposit) => stats.record_before_any_deposit(),
+        _ => {}
+    }
+
+    // Written before checking for a lock, so a run stopped by --fail-on-lock still records
+    // the chargeback that stopped it
+    if let (Some(ledger), ApplyOutcome::Applied) = (ledger, outcome) {
+        if let Some(account) = engine.last_account() {
+            let client = match &options.parse.client_labels {
+                Some(client_labels) => client_labels
+                    .label(account.client_id)?
+                    .unwrap_or_else(|| account.client_label()),
+                None => account.client_label(),
+            };
+            ledger.write(&client, transaction_id, transaction_type, account)?;
+        }
+    }
+
+    if options.fail_on_lock {
+        if let Some((client_id, transaction_id)) = engine.take_last_lock() {
+            return Err(Error::msg(format!(
+                "Stopping because the account for client {} was locked by transaction ID {}",
+                client_id, transaction_id
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn create_ledger(options: &ProcessingOptions) -> Result<Option<Ledger>> {
+    options
+        .ledger_path
+        .as_deref()
+        .map(Ledger::create)
+        .transpose()
+}
+
+fn flush_ledger(ledger: &mut Option<Ledger>) -> Result<()> {
+    match ledger {
+        Some(ledger) => ledger.flush(),
+        None => Ok(()),
+    }
+}
+
+fn finish(
+    mut engine: Engine,
+    previously_applied: &HashSet<TransactionId>,
+    options: &ProcessingOptions,
+) -> Result<Engine> {
+    for &client_id in &options.client_roster {
+        if !options.blocked_clients.contains(&client_id) {
+            engine.open_account(client_id);
+        }
+    }
+
+    if let Some(client_labels) = &options.parse.client_labels {
+        for account in engine.accounts_mut() {
+            account.label = client_labels.label(account.client_id)?;
+        }
+    }
+
+    if options.check_held_balances {
+        if let Some(account) = engine
+            .accounts()
+            .find(|account| !account.held_matches_disputes())
+        {
+            return Err(Error::msg(format!(
+                "Held balance for client {} does not match its disputed deposits",
+                account.client_id
+            )));
+        }
+    }
+
+    if options.reconcile_held_balances {
+        engine.reconcile_held_balances()?;
+    }
+
+    if let Some(path) = &options.applied_ids_path {
+        save_applied_ids(path, previously_applied, engine.accounts())?;
+    }
+
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs::File, io::BufWriter};
+
+    use anyhow::{Error, Result};
+    use csv::{StringRecord, Writer};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use stopwatch::Stopwatch;
+
+    use super::{
+        process_csv, process_csv_map, process_csv_pipelined, process_csv_with_metrics,
+        process_csv_with_validator, process_reader, process_records,
+    };
+    use crate::{
+        assert_err::assert_err,
+        csv::{csv_fixture::generate_fixture, parse_options::ParseOptions},
+        domain::{
+            account_policy::{AccountPolicy, SeededLockPolicy},
+            client_account::ClientAccount,
+            lock_reason::LockReason,
+            transaction::{Transaction, TransactionAction},
+            transaction_validator::TransactionValidator,
+        },
+        processing::{
+            processing_failed::ProcessingFailed, processing_options::ProcessingOptions,
+            processing_stats::ProcessingStats,
+        },
+        test_fixture::{write_fixture, write_fixture_dir, write_zstd_fixture},
+    };
+
+    #[test]
+    fn records_stats_for_each_transaction_type() -> Result<()> {
+        let csv_path = write_fixture(
+            "stats",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.0\n\
+             dispute,1,1,\n\
+             resolve,1,1,\n\
+             dispute,2,2,\n\
+             chargeback,2,2,\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        process_csv(&csv_path, &ProcessingOptions::default(), &mut stats)?;
+
+        assert_eq!(
+            ProcessingStats {
+                deposits: 2,
+                withdrawals: 1,
+                disputes: 2,
+                resolves: 1,
+                chargebacks: 1,
+                resets: 0,
+                holds: 0,
+                releases: 0,
+                settles: 0,
+                freezes: 0,
+                unfreezes: 0,
+                rejected: 0,
+                blocked: 0,
+                skipped: 0,
+                before_any_deposit: 0,
+            },
+            stats
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn processes_csv_from_a_buffer() -> Result<()> {
+        let csv = b"type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    withdrawal,1,2,2.5\n\
+                    deposit,2,3,4.0\n";
+
+        let mut client_accounts = process_reader(
+            &csv[..],
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(2, client_accounts.len());
+        assert_eq!(dec!(7.5), client_accounts[0].total_balance);
+        assert_eq!(dec!(4), client_accounts[1].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn measures_time_spent_processing() -> Result<()> {
+        let csv_path = write_fixture(
+            "metrics",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.0\n\
+             dispute,1,1,\n",
+        )?;
+
+        let (client_accounts, metrics) = process_csv_with_metrics(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+
+        assert_eq!(2, client_accounts.len());
+        assert_eq!(4, metrics.total_rows);
+        // A single stage can take less time than a coarse clock measures, but not all of them
+        assert!(metrics.read_ms + metrics.parse_ms + metrics.apply_ms > 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn measures_time_spent_processing_a_directory_with_an_errors_file() -> Result<()> {
+        let csv_dir = write_fixture_dir(
+            "metrics-dir",
+            &[
+                (
+                    "1-deposits.csv",
+                    "type,client,tx,amount
+deposit,1,1,10.0
+",
+                ),
+                (
+                    "2-withdrawals.csv",
+                    "type,client,tx,amount
+withdrawal,1,2,abc
+withdrawal,1,3,2.5
+",
+                ),
+            ],
+        )?;
+        let errors_path = write_fixture("metrics-errors-output", "")?;
+        let options = ProcessingOptions {
+            errors_path: Some(errors_path.clone()),
+            ..Default::default()
+        };
+
+        let (client_accounts, metrics) =
+            process_csv_with_metrics(&csv_dir, &options, &mut ProcessingStats::default())?;
+
+        assert_eq!(dec!(7.5), client_accounts[0].total_balance);
+        assert_eq!(3, metrics.total_rows);
+        assert!(metrics.read_ms + metrics.parse_ms + metrics.apply_ms > 0.0);
+        assert_eq!(
+            "line,transaction_id,error\n\
+             2,2,Invalid amount 'abc' for transaction 2\n",
+            std::fs::read_to_string(errors_path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn records_stats_up_to_a_rejected_transaction() -> Result<()> {
+        let csv_path = write_fixture(
+            "stats-rejected",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n\
+             deposit,1,3,10.0\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let result = process_csv(&csv_path, &ProcessingOptions::default(), &mut stats);
+
+        assert_err!(
+            result,
+            "Failed to apply withdrawal with transaction ID 2 for client 1: Insufficient available balance for withdrawal"
+        );
+        assert_eq!(1, stats.deposits);
+        assert_eq!(1, stats.withdrawals);
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_zero_amount_rows_when_allowed() -> Result<()> {
+        let csv_path = write_fixture(
+            "allow-zero",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,0.0\n\
+             withdrawal,1,3,0\n",
+        )?;
+        let options = ProcessingOptions {
+            parse: ParseOptions {
+                allow_zero_amounts: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let client_accounts = process_csv(&csv_path, &options, &mut stats)?;
+
+        assert_eq!(dec!(10), client_accounts[0].total_balance);
+        assert_eq!(1, stats.deposits);
+        assert_eq!(0, stats.withdrawals);
+        assert_eq!(0, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn records_stats_for_skipped_duplicate_transactions() -> Result<()> {
+        let csv_path = write_fixture(
+            "stats-skipped",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,2.0\n\
+             withdrawal,1,2,2.0\n\
+             deposit,2,1,5.0\n\
+             deposit,1,1,10.0\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let mut client_accounts =
+            process_csv(&csv_path, &ProcessingOptions::default(), &mut stats)?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(dec!(8), client_accounts[0].total_balance);
+        assert_eq!(dec!(5), client_accounts[1].total_balance);
+        assert_eq!(4, stats.deposits);
+        assert_eq!(2, stats.withdrawals);
+        assert_eq!(3, stats.skipped);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_zero_amount_rows_by_default() -> Result<()> {
+        let csv_path = write_fixture(
+            "reject-zero",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,0.0\n",
+        )?;
+
+        assert_err!(
+            process_csv(
+                &csv_path,
+                &ProcessingOptions::default(),
+                &mut ProcessingStats::default()
+            ),
+            "line 3: Failed to read transaction with ID 2: Amount is negative or zero"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn passes_held_balance_check_after_disputes() -> Result<()> {
+        let csv_path = write_fixture(
+            "check",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,5.0\n\
+             dispute,1,1,\n\
+             dispute,1,2,\n\
+             resolve,1,2,\n",
+        )?;
+        let options = ProcessingOptions {
+            check_held_balances: true,
+            ..Default::default()
+        };
+
+        let client_accounts = process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+
+        assert_eq!(dec!(10), client_accounts[0].held_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_unknown_transaction_types_when_enabled() -> Result<()> {
+        let csv_path = write_fixture(
+            "skip-unknown",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             refund,1,2,5.0\n\
+             withdrawal,1,3,2.0\n",
+        )?;
+        let options = ProcessingOptions {
+            skip_unknown_types: true,
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let client_accounts = process_csv(&csv_path, &options, &mut stats)?;
+
+        assert_eq!(dec!(8), client_accounts[0].total_balance);
+        assert_eq!(1, stats.deposits);
+        assert_eq!(1, stats.withdrawals);
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_unknown_transaction_types_by_default() -> Result<()> {
+        let csv_path = write_fixture(
+            "reject-unknown",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             refund,1,2,5.0\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let result = process_csv(&csv_path, &ProcessingOptions::default(), &mut stats);
+
+        assert_err!(
+            result,
+            "line 3: Failed to read transaction with ID 2: Unknown type refund"
+        );
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_the_line_number_of_a_malformed_row() -> Result<()> {
+        let csv_path = write_fixture(
+            "malformed-line",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,5.0\n\
+             deposit,1,three,5.0\n\
+             deposit,1,4,5.0\n",
+        )?;
+
+        let error = process_csv(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )
+        .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("line 4: Failed to deserialize CSV transaction: "));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_a_row_with_too_many_fields() -> Result<()> {
+        let csv_path = write_fixture(
+            "too-many-fields",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,5.0,extra\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let error = process_csv(&csv_path, &ProcessingOptions::default(), &mut stats).unwrap_err();
+
+        assert!(error.to_string().starts_with("Failed to parse CSV line: "));
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_transactions_applied_by_an_earlier_run() -> Result<()> {
+        let first_day = write_fixture(
+            "applied-ids-first-day",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n",
+        )?;
+        let second_day = write_fixture(
+            "applied-ids-second-day",
+            "type,client,tx,amount\n\
+             deposit,2,2,5.0\n\
+             deposit,1,3,4.0\n",
+        )?;
+        let both_days = write_fixture(
+            "applied-ids-both-days",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             deposit,1,3,4.0\n",
+        )?;
+        let applied_ids_path = write_fixture("applied-ids-sidecar", "")?;
+        let options = ProcessingOptions {
+            applied_ids_path: Some(applied_ids_path),
+            ..Default::default()
+        };
+
+        let mut first_day_accounts =
+            process_csv(&first_day, &options, &mut ProcessingStats::default())?;
+        first_day_accounts.sort_by_key(|account| account.client_id);
+        let mut stats = ProcessingStats::default();
+        let mut second_day_accounts = process_csv(&second_day, &options, &mut stats)?;
+        second_day_accounts.sort_by_key(|account| account.client_id);
+        let mut once_accounts = process_csv(
+            &both_days,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+        once_accounts.sort_by_key(|account| account.client_id);
+
+        // Only the deposit to client 1 is new on the second day
+        assert_eq!(1, stats.skipped);
+        assert_eq!(1, second_day_accounts.len());
+        assert_eq!(
+            once_accounts[0].total_balance,
+            first_day_accounts[0].total_balance + second_day_accounts[0].total_balance
+        );
+        assert_eq!(
+            once_accounts[1].total_balance,
+            first_day_accounts[1].total_balance
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skips_transactions_up_to_the_since_transaction_id() -> Result<()> {
+        let csv_path = write_fixture(
+            "since-tx",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,5.0\n\
+             deposit,1,3,2.0\n\
+             withdrawal,1,4,1.0\n",
+        )?;
+        let options = ProcessingOptions {
+            since_transaction_id: Some(2),
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let client_accounts = process_csv(&csv_path, &options, &mut stats)?;
+
+        assert_eq!(dec!(1), client_accounts[0].total_balance);
+        assert_eq!(2, stats.skipped);
+        assert_eq!(1, stats.deposits);
+        assert_eq!(1, stats.withdrawals);
+        Ok(())
+    }
+
+    #[test]
+    fn builds_on_opening_balances() -> Result<()> {
+        let opening_balances_path = write_fixture(
+            "opening-balances-seed",
+            "client,available,held,total,locked\n\
+             1,10.0,0,10.0,false\n\
+             2,3.0,0,3.0,false\n",
+        )?;
+        let csv_path = write_fixture(
+            "opening-balances-transactions",
+            "type,client,tx,amount\n\
+             withdrawal,1,1,4.0\n\
+             deposit,3,2,1.0\n",
+        )?;
+        let options = ProcessingOptions {
+            opening_balances_path: Some(opening_balances_path),
+            ..Default::default()
+        };
+
+        let mut client_accounts =
+            process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(3, client_accounts.len());
+        assert_eq!(dec!(6), client_accounts[0].total_balance);
+        assert_eq!(dec!(3), client_accounts[1].total_balance);
+        assert_eq!(dec!(1), client_accounts[2].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_deposit_to_account_seeded_locked() -> Result<()> {
+        let opening_balances_path = write_fixture(
+            "opening-balances-locked",
+            "client,available,held,total,locked\n\
+             1,10.0,0,10.0,true\n",
+        )?;
+        let csv_path = write_fixture(
+            "opening-balances-locked-transactions",
+            "type,client,tx,amount\n\
+             deposit,1,1,5.0\n",
+        )?;
+        let options = ProcessingOptions {
+            opening_balances_path: Some(opening_balances_path),
+            ..Default::default()
+        };
+
+        let result = process_csv(&csv_path, &options, &mut ProcessingStats::default());
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 1 for client 1: Account is locked"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn starts_listed_clients_locked() -> Result<()> {
+        let csv_path = write_fixture(
+            "start-locked-transactions",
+            "type,client,tx,amount\n\
+             deposit,2,1,5.0\n",
+        )?;
+        let options = ProcessingOptions {
+            start_locked: HashSet::from([1]),
+            ..Default::default()
+        };
+
+        let mut client_accounts =
+            process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(2, client_accounts.len());
+        assert!(client_accounts[0].locked);
+        assert_eq!(Some(LockReason::Seeded), client_accounts[0].lock_reason);
+        assert!(!client_accounts[1].locked);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_the_seeded_lock_policy_to_accounts_that_start_locked() -> Result<()> {
+        let csv_path = write_fixture(
+            "seeded-lock-policy",
+            "type,client,tx,amount\n\
+             deposit,2,1,5.0\n\
+             deposit,1,2,5.0\n\
+             withdrawal,2,3,1.0\n",
+        )?;
+        let options = |seeded_lock_policy| ProcessingOptions {
+            start_locked: HashSet::from([1]),
+            account_policy: AccountPolicy {
+                seeded_lock_policy,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let result = process_csv(
+            &csv_path,
+            &options(SeededLockPolicy::Reject),
+            &mut ProcessingStats::default(),
+        );
+        assert_err!(
+            result,
+            "Failed to apply deposit with transaction ID 2 for client 1: Account is locked"
+        );
+
+        let mut stats = ProcessingStats::default();
+        let mut client_accounts =
+            process_csv(&csv_path, &options(SeededLockPolicy::Skip), &mut stats)?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!((1, 0), (stats.skipped, stats.rejected));
+        assert_eq!(dec!(0), client_accounts[0].total_balance);
+        assert_eq!(Some(LockReason::Seeded), client_accounts[0].lock_reason);
+        assert_eq!(dec!(4), client_accounts[1].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_replayed_dispute_rows_by_row_id() -> Result<()> {
+        let csv_path = write_fixture(
+            "row-ids",
+            "type,client,tx,amount,id\n\
+             deposit,1,1,10.0,\n\
+             dispute,1,1,,100\n\
+             resolve,1,1,,101\n\
+             dispute,1,1,,100\n",
+        )?;
+        let mut stats = ProcessingStats::default();
+
+        let client_accounts = process_csv(&csv_path, &ProcessingOptions::default(), &mut stats)?;
+
+        assert_eq!(dec!(10), client_accounts[0].available_balance);
+        assert_eq!(dec!(0), client_accounts[0].held_balance);
+        assert_eq!(1, stats.skipped);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_rows_processed_before_a_failing_row() -> Result<()> {
+        let csv_path = write_fixture(
+            "rows-processed",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,5.0\n\
+             dispute,1,1,\n\
+             withdrawal,1,3,1.0\n\
+             withdrawal,1,4,100.0\n\
+             deposit,1,5,1.0\n",
+        )?;
+
+        for process in [process_csv, process_csv_pipelined] {
+            let err = process(
+                &csv_path,
+                &ProcessingOptions::default(),
+                &mut ProcessingStats::default(),
+            )
+            .err()
+            .ok_or_else(|| Error::msg("Expected the fifth row to fail"))?;
+
+            assert_eq!(
+                Some(4),
+                err.downcast_ref::<ProcessingFailed>()
+                    .map(|failed| failed.rows_processed)
+            );
+            assert_eq!(
+                "Failed to apply withdrawal with transaction ID 4 for client 1: Insufficient available balance for withdrawal",
+                err.to_string()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_the_first_lock_with_fail_on_lock() -> Result<()> {
+        let csv_path = write_fixture(
+            "fail-on-lock",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             dispute,1,1,\n\
+             chargeback,1,1,\n\
+             dispute,2,2,\n\
+             chargeback,2,2,\n",
+        )?;
+        let options = ProcessingOptions {
+            fail_on_lock: true,
+            ..Default::default()
+        };
+
+        for process in [process_csv, process_csv_pipelined] {
+            let mut stats = ProcessingStats::default();
+            let err = process(&csv_path, &options, &mut stats)
+                .err()
+                .ok_or_else(|| Error::msg("Expected the chargeback to fail the run"))?;
+
+            assert_eq!(
+                Some(3),
+                err.downcast_ref::<ProcessingFailed>()
+                    .map(|failed| failed.rows_processed)
+            );
+            assert_eq!(
+                "Stopping because the account for client 1 was locked by transaction ID 1",
+                err.to_string()
+            );
+            assert_eq!((1, 0), (stats.chargebacks, stats.rejected));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn continues_past_a_freeze_with_fail_on_lock() -> Result<()> {
+        let csv_path = write_fixture(
+            "fail-on-lock-freeze",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             freeze,1,2,\n\
+             deposit,2,3,5.0\n",
+        )?;
+        let mut options = ProcessingOptions {
+            fail_on_lock: true,
+            ..Default::default()
+        };
+        options.parse.allow_admin = true;
+
+        for process in [process_csv, process_csv_pipelined] {
+            let mut client_accounts =
+                process(&csv_path, &options, &mut ProcessingStats::default())?;
+            client_accounts.sort_by_key(|account| account.client_id);
+
+            assert_eq!(2, client_accounts.len());
+            assert!(client_accounts[0].locked);
+            assert_eq!(dec!(5), client_accounts[1].total_balance);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn processes_a_directory_of_csvs_in_file_name_order() -> Result<()> {
+        // Each file relies on the one before it, so any other order fails or differs
+        let csv_dir = write_fixture_dir(
+            "csv-dir",
+            &[
+                (
+                    "2-withdrawals.csv",
+                    "type,client,tx,amount\nwithdrawal,1,2,4.0\n",
+                ),
+                (
+                    "1-deposits.csv",
+                    "type,client,tx,amount\ndeposit,1,1,10.0\n",
+                ),
+                ("3-disputes.csv", "type,client,tx,amount\ndispute,1,1,\n"),
+                ("notes.txt", "not a CSV"),
+            ],
+        )?;
+
+        let client_accounts = process_csv(
+            &csv_dir,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+
+        assert_eq!(1, client_accounts.len());
+        assert_eq!(dec!(-4), client_accounts[0].available_balance);
+        assert_eq!(dec!(10), client_accounts[0].held_balance);
+        assert_eq!(dec!(6), client_accounts[0].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn routes_disputes_by_their_target() -> Result<()> {
+        let csv_path = write_fixture(
+            "dispute-targets",
+            "type,client,tx,amount,target\n\
+             deposit,1,5,10.0,\n\
+             deposit,2,1,20.0,\n\
+             withdrawal,2,5,8.0,\n\
+             dispute,1,5,,deposit\n\
+             dispute,2,5,,withdrawal\n",
+        )?;
+        let options = ProcessingOptions {
+            account_policy: AccountPolicy {
+                dispute_withdrawals: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut client_accounts =
+            process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        // Disputing the deposit holds funds the client had
+        assert_eq!(dec!(0), client_accounts[0].available_balance);
+        assert_eq!(dec!(10), client_accounts[0].held_balance);
+        assert_eq!(dec!(10), client_accounts[0].total_balance);
+        // Disputing the withdrawal holds the funds it took out
+        assert_eq!(dec!(12), client_accounts[1].available_balance);
+        assert_eq!(dec!(8), client_accounts[1].held_balance);
+        assert_eq!(dec!(20), client_accounts[1].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn processes_crlf_files_identically_to_lf_files() -> Result<()> {
+        let rows = [
+            "type,client,tx,amount",
+            "deposit,1,1,10.0",
+            "deposit,2,2,5.0",
+            "withdrawal,1,3,2.5",
+            "dispute,2,2,",
+            "chargeback,2,2,",
+        ];
+        let lf_path = write_fixture("line-endings-lf", &(rows.join("\n") + "\n"))?;
+        let crlf_path = write_fixture("line-endings-crlf", &(rows.join("\r\n") + "\r\n"))?;
+
+        let mut results = Vec::new();
+        for csv_path in [lf_path, crlf_path] {
+            let mut client_accounts = process_csv(
+                &csv_path,
+                &ProcessingOptions::default(),
+                &mut ProcessingStats::default(),
+            )?;
+            client_accounts.sort_by_key(|account| account.client_id);
+            results.push(
+                client_accounts
+                    .iter()
+                    .map(|account| account.summary_row(4))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        assert_eq!(results[0], results[1]);
+        assert_eq!(2, results[1].len());
+        Ok(())
+    }
+
+    #[test]
+    fn processes_zstd_files_identically_to_plaintext_files() -> Result<()> {
+        let transactions = "type,client,tx,amount\n\
+                            deposit,1,1,10.0\n\
+                            deposit,2,2,5.0\n\
+                            withdrawal,1,3,2.5\n\
+                            dispute,2,2,\n\
+                            chargeback,2,2,\n";
+        let plaintext_path = write_fixture("zstd-plaintext", transactions)?;
+        let zstd_path = write_zstd_fixture("zstd", transactions)?;
+        // Without a .zst extension the file is only decompressed when asked to
+        let unnamed_zstd_path = write_fixture("zstd-unnamed", "")?;
+        std::fs::copy(&zstd_path, &unnamed_zstd_path)?;
+
+        let balances = |csv_path: &str, zstd_input| -> Result<Vec<[String; 5]>> {
+            let options = ProcessingOptions {
+                zstd_input,
+                ..Default::default()
+            };
+            let client_accounts = sorted(process_csv(
+                csv_path,
+                &options,
+                &mut ProcessingStats::default(),
+            )?);
+            Ok(client_accounts
+                .iter()
+                .map(|account| account.summary_row(4))
+                .collect())
+        };
+
+        let plaintext = balances(&plaintext_path, false)?;
+        assert_eq!(2, plaintext.len());
+        assert_eq!(plaintext, balances(&zstd_path, false)?);
+        assert_eq!(plaintext, balances(&unnamed_zstd_path, true)?);
+        Ok(())
+    }
+
+    #[test]
+    fn processes_up_to_the_maximum_number_of_clients() -> Result<()> {
+        let csv_path = write_fixture(
+            "max-clients-within",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             deposit,1,3,1.0\n",
+        )?;
+        let options = ProcessingOptions {
+            max_clients: Some(2),
+            ..Default::default()
+        };
+
+        let client_accounts = process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+
+        assert_eq!(2, client_accounts.len());
+        Ok(())
+    }
+
+    #[test]
+    fn fails_when_exceeding_the_maximum_number_of_clients() -> Result<()> {
+        let csv_path = write_fixture(
+            "max-clients-exceeded",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             deposit,3,3,1.0\n",
+        )?;
+        let options = ProcessingOptions {
+            max_clients: Some(2),
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let result = process_csv(&csv_path, &options, &mut stats);
+
+        assert_err!(result, "Too many distinct clients: the maximum is 2");
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_repeated_header_rows_when_enabled() -> Result<()> {
+        let csv_path = write_fixture(
+            "repeated-headers",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             type,client,tx,amount\n\
+             deposit,1,2,5.0\n",
+        )?;
+        let options = ProcessingOptions {
+            skip_repeated_headers: true,
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let client_accounts = process_csv(&csv_path, &options, &mut stats)?;
+
+        assert_eq!(dec!(15), client_accounts[0].total_balance);
+        assert_eq!(2, stats.deposits);
+        assert_eq!(0, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_repeated_header_rows_by_default() -> Result<()> {
+        let csv_path = write_fixture(
+            "repeated-headers-default",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             type,client,tx,amount\n",
+        )?;
+
+        assert_err!(
+            process_csv(
+                &csv_path,
+                &ProcessingOptions::default(),
+                &mut ProcessingStats::default(),
+            ),
+            "line 3: Invalid amount 'amount' for transaction tx"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn processes_an_empty_file_into_no_accounts() -> Result<()> {
+        let csv_path = write_fixture("empty-input", "")?;
+
+        let client_accounts = process_csv(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+
+        assert!(client_accounts.is_empty());
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct WithdrawalLimit(Decimal);
+
+    impl TransactionValidator for WithdrawalLimit {
+        fn validate(&self, _account: &ClientAccount, transaction: &Transaction) -> Result<()> {
+            match &transaction.action {
+                TransactionAction::Withdrawal(withdrawal) if withdrawal.amount > self.0 => Err(
+                    Error::msg(format!("Withdrawal over {} needs review", self.0)),
+                ),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_transactions_failing_the_validator() -> Result<()> {
+        let csv_path = write_fixture(
+            "validator",
+            "type,client,tx,amount\n\
+             deposit,1,1,500.0\n\
+             withdrawal,1,2,50.0\n\
+             withdrawal,1,3,200.0\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let result = process_csv_with_validator(
+            &csv_path,
+            &ProcessingOptions::default(),
+            Box::new(WithdrawalLimit(dec!(100))),
+            &mut stats,
+        );
+
+        assert_err!(
+            result,
+            "Failed to apply withdrawal with transaction ID 3 for client 1: Withdrawal over 100 needs review"
+        );
+        assert_eq!(2, stats.withdrawals);
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    fn stops_early_with_the_accounts_built_so_far() -> Result<()> {
+        let options = ProcessingOptions::default();
+        let stop = options.stop.clone();
+        let records = [
+            ["deposit", "1", "1", "10.0"],
+            ["deposit", "2", "2", "5.0"],
+            ["deposit", "1", "3", "1.0"],
+            ["deposit", "3", "4", "1.0"],
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, fields)| {
+            // Simulates Ctrl-C arriving once two rows have been read
+            if index == 2 {
+                stop.request_stop();
+            }
+            Ok(StringRecord::from(fields.to_vec()))
+        });
+
+        let mut stats = ProcessingStats::default();
+        let mut client_accounts = process_records(records, &options, &mut stats)?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(2, client_accounts.len());
+        assert_eq!(dec!(10), client_accounts[0].total_balance);
+        assert_eq!(dec!(5), client_accounts[1].total_balance);
+        assert_eq!(2, stats.deposits);
+        Ok(())
+    }
+
+    #[test]
+    fn processes_columns_in_a_different_order() -> Result<()> {
+        let csv_path = write_fixture(
+            "reordered-processing",
+            "client,type,amount,tx\n\
+             1,deposit,10.0,1\n\
+             1,withdrawal,2.5,2\n\
+             1,dispute,,1\n",
+        )?;
+
+        let client_accounts = process_csv(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+
+        assert_eq!(dec!(-2.5), client_accounts[0].available_balance);
+        assert_eq!(dec!(10), client_accounts[0].held_balance);
+        assert_eq!(dec!(7.5), client_accounts[0].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn looks_up_clients_by_id_in_the_account_map() -> Result<()> {
+        let csv_path = write_fixture(
+            "account-map",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.0\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let accounts = process_csv_map(&csv_path, &ProcessingOptions::default(), &mut stats)?;
+
+        assert_eq!(2, accounts.len());
+        assert_eq!(dec!(8), accounts[&1].total_balance);
+        assert_eq!(dec!(5), accounts[&2].total_balance);
+        assert!(!accounts.contains_key(&3));
+        Ok(())
+    }
+
+    #[test]
+    fn writes_rejected_rows_to_the_errors_file_and_carries_on() -> Result<()> {
+        let csv_path = write_fixture(
+            "errors-file",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,abc\n\
+             withdrawal,1,3,50.0\n\
+             withdrawal,1,4,2.5\n",
+        )?;
+        let errors_path = write_fixture("errors-file-output", "")?;
+        let options = ProcessingOptions {
+            errors_path: Some(errors_path.clone()),
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let client_accounts = process_csv(&csv_path, &options, &mut stats)?;
+
+        assert_eq!(dec!(7.5), client_accounts[0].total_balance);
+        assert_eq!(2, stats.rejected);
+        assert_eq!(
+            "line,transaction_id,error\n\
+             3,2,Invalid amount 'abc' for transaction 2\n\
+             4,3,Failed to apply withdrawal with transaction ID 3 for client 1: Insufficient available balance for withdrawal\n",
+            std::fs::read_to_string(errors_path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_the_balances_after_each_applied_transaction_to_the_ledger() -> Result<()> {
+        let csv_path = write_fixture(
+            "ledger",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.5\n\
+             dispute,1,1,\n\
+             dispute,1,9,\n\
+             resolve,1,1,\n\
+             deposit,1,1,10.0\n\
+             chargeback,2,2,\n",
+        )?;
+        let ledger_path = write_fixture("ledger-output", "")?;
+        let options = ProcessingOptions {
+            ledger_path: Some(ledger_path.clone()),
+            ..Default::default()
+        };
+
+        for process in [process_csv, process_csv_pipelined] {
+            process(&csv_path, &options, &mut ProcessingStats::default())?;
+
+            // The dispute of an unknown deposit, the replayed deposit and the chargeback of
+            // an undisputed deposit are ignored, so have no row
+            assert_eq!(
+                "client,tx,type,available,held,total\n\
+                 1,1,deposit,10.0000,0.0000,10.0000\n\
+                 2,2,deposit,5.0000,0.0000,5.0000\n\
+                 1,3,withdrawal,7.5000,0.0000,7.5000\n\
+                 1,1,dispute,-2.5000,10.0000,7.5000\n\
+                 1,1,resolve,7.5000,0.0000,7.5000\n",
+                std::fs::read_to_string(&ledger_path)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writes_the_locking_chargeback_to_the_ledger_with_fail_on_lock() -> Result<()> {
+        let csv_path = write_fixture(
+            "ledger-fail-on-lock",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             dispute,1,1,\n\
+             chargeback,1,1,\n\
+             deposit,1,2,5.0\n",
+        )?;
+        let ledger_path = write_fixture("ledger-fail-on-lock-output", "")?;
+        let options = ProcessingOptions {
+            ledger_path: Some(ledger_path.clone()),
+            fail_on_lock: true,
+            ..Default::default()
+        };
+
+        for process in [process_csv, process_csv_pipelined] {
+            let result = process(&csv_path, &options, &mut ProcessingStats::default());
+
+            assert!(result.is_err());
+            assert_eq!(
+                "client,tx,type,available,held,total\n\
+                 1,1,deposit,10.0000,0.0000,10.0000\n\
+                 1,1,dispute,0.0000,10.0000,10.0000\n\
+                 1,1,chargeback,0.0000,0.0000,0.0000\n",
+                std::fs::read_to_string(&ledger_path)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_an_unreadable_line_even_with_an_errors_file() -> Result<()> {
+        // The over-long line can't be read, so the rows after it can't be either
+        let csv_path = write_fixture(
+            "errors-file-long-line",
+            &format!(
+                "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 deposit,1,2,{}\n\
+                 deposit,1,3,5.0\n",
+                "1".repeat(1024 * 1024)
+            ),
+        )?;
+        let errors_path = write_fixture("errors-file-long-line-output", "")?;
+        let options = ProcessingOptions {
+            errors_path: Some(errors_path),
+            ..Default::default()
+        };
+
+        let result = process_csv(&csv_path, &options, &mut ProcessingStats::default());
+        assert_err!(
+            result,
+            "Failed to parse CSV line: Line exceeds the maximum of 65536 bytes"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skips_transactions_for_blocked_clients() -> Result<()> {
+        let csv_path = write_fixture(
+            "blocked-clients",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.0\n\
+             dispute,2,1,\n\
+             withdrawal,2,4,100.0\n",
+        )?;
+        let options = ProcessingOptions {
+            blocked_clients: HashSet::from([2, 3]),
+            ..Default::default()
+        };
+
+        let mut stats = ProcessingStats::default();
+        let client_accounts = process_csv(&csv_path, &options, &mut stats)?;
+
+        assert_eq!(1, client_accounts.len());
+        assert_eq!(1, client_accounts[0].client_id);
+        assert_eq!(dec!(8), client_accounts[0].available_balance);
+        assert_eq!(dec!(0), client_accounts[0].held_balance);
+        assert_eq!(dec!(8), client_accounts[0].total_balance);
+        assert_eq!(3, stats.blocked);
+        assert_eq!(1, stats.deposits);
+        Ok(())
+    }
+
+    #[test]
+    fn outputs_zeroed_accounts_for_roster_clients_without_transactions() -> Result<()> {
+        let csv_path = write_fixture(
+            "client-roster",
+            "type,client,tx,amount\n\
+             deposit,2,1,10.0\n",
+        )?;
+        let options = ProcessingOptions {
+            client_roster: HashSet::from([1, 2, 3]),
+            ..Default::default()
+        };
+
+        let mut client_accounts =
+            process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(3, client_accounts.len());
+        for account in [&client_accounts[0], &client_accounts[2]] {
+            assert_eq!(dec!(0), account.available_balance);
+            assert_eq!(dec!(0), account.held_balance);
+            assert_eq!(dec!(0), account.total_balance);
+            assert!(!account.locked);
+        }
+        assert_eq!(dec!(10), client_accounts[1].total_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_dispute_naming_the_wrong_client_when_routing_by_transaction() -> Result<()> {
+        let csv_path = write_fixture(
+            "routed-dispute",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             dispute,2,1,\n",
+        )?;
+        let options = ProcessingOptions {
+            route_disputes_by_transaction: true,
+            ..Default::default()
+        };
+
+        let mut client_accounts =
+            process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(dec!(0), client_accounts[0].available_balance);
+        assert_eq!(dec!(10), client_accounts[0].held_balance);
+        assert_eq!(dec!(5), client_accounts[1].available_balance);
+        assert_eq!(dec!(0), client_accounts[1].held_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_amounts_in_minor_units_with_input_scale() -> Result<()> {
+        let csv_path = write_fixture(
+            "input-scale",
+            "type,client,tx,amount\n\
+             deposit,1,1,125555\n\
+             withdrawal,1,2,5555\n",
+        )?;
+        let options = ProcessingOptions {
+            parse: ParseOptions {
+                input_scale: Some(4),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let client_accounts = process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+
+        assert_eq!(dec!(12), client_accounts[0].available_balance);
+        assert_eq!(dec!(12), client_accounts[0].total_balance);
+        Ok(())
+    }
+
+    fn sorted(mut client_accounts: Vec<ClientAccount>) -> Vec<ClientAccount> {
+        client_accounts.sort_by_key(|account| account.client_id);
+        client_accounts
+    }
+
+    #[test]
+    fn pipelined_processing_matches_serial_processing() -> Result<()> {
+        let csv_path = write_fixture(
+            "pipelined",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.5\n\
+             dispute,1,1,\n\
+             resolve,1,1,\n\
+             deposit,3,4,7.1234\n\
+             dispute,2,2,\n\
+             chargeback,2,2,\n\
+             dispute,3,4,\n",
+        )?;
+
+        let mut serial_stats = ProcessingStats::default();
+        let serial = sorted(process_csv(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut serial_stats,
+        )?);
+        let mut pipelined_stats = ProcessingStats::default();
+        let pipelined = sorted(process_csv_pipelined(
+            &csv_path,
+            &ProcessingOptions::default(),
+            &mut pipelined_stats,
+        )?);
+
+        assert_eq!(serial.len(), pipelined.len());
+        for (serial, pipelined) in serial.iter().zip(pipelined.iter()) {
+            assert_eq!(serial.client_id, pipelined.client_id);
+            assert_eq!(serial.available_balance, pipelined.available_balance);
+            assert_eq!(serial.held_balance, pipelined.held_balance);
+            assert_eq!(serial.total_balance, pipelined.total_balance);
+            assert_eq!(serial.locked, pipelined.locked);
+        }
+        assert_eq!(serial_stats, pipelined_stats);
+        Ok(())
+    }
+
+    #[test]
+    fn pipelined_processing_stops_at_the_first_failure() -> Result<()> {
+        let csv_path = write_fixture(
+            "pipelined-failure",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             refund,1,2,5.0\n\
+             deposit,1,3,10.0\n",
+        )?;
+
+        let mut stats = ProcessingStats::default();
+        let result = process_csv_pipelined(&csv_path, &ProcessingOptions::default(), &mut stats);
+
+        assert_err!(
+            result,
+            "line 3: Failed to read transaction with ID 2: Unknown type refund"
+        );
+        assert_eq!(1, stats.deposits);
+        assert_eq!(1, stats.rejected);
+        Ok(())
+    }
+
+    #[test]
+    #[ignore] // Comment this to test performance of a large file
+    fn test_large_file() -> Result<()> {
+        let csv_path = "/media/chris/x/large-file.csv";
+        let num_events = 1_000_000_000;
+        let expected = generate_fixture(BufWriter::new(File::create(csv_path)?), num_events, 0)?;
+
+        let stopwatch = Stopwatch::start_new();
+        let mut stats = ProcessingStats::default();
+        let mut client_accounts = process_csv(csv_path, &ProcessingOptions::default(), &mut stats)?;
+        assert_eq!(0, stats.rejected);
+        client_accounts.sort_by_key(|account| account.client_id);
+        assert_eq!(expected.len(), client_accounts.len());
+        for (expected, account) in expected.iter().zip(&client_accounts) {
+            assert_eq!(expected.client_id, account.client_id);
+            assert_eq!(expected.available, account.available_balance);
+            assert_eq!(expected.held, account.held_balance);
+            assert_eq!(expected.available + expected.held, account.total_balance);
+            assert_eq!(expected.locked, account.locked);
+        }
+        println!(
+            "Processed {} events in {} ms",
+            num_events,
+            stopwatch.elapsed_ms()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore] // Comment this to compare serial and pipelined performance
+    fn compare_pipelined_performance() -> Result<()> {
+        let csv_path = std::env::temp_dir().join("payments-engine-pipelined-perf.csv");
+        let csv_path = csv_path.to_string_lossy();
+        let mut writer = Writer::from_writer(BufWriter::new(File::create(csv_path.as_ref())?));
+        let num_deposits = 2_000_000;
+
+        writer.write_record(["type", "client", "tx", "amount"])?;
+        for i in 0..num_deposits {
+            let client = (i % 1000).to_string();
+            writer.write_record(["deposit", &client, &i.to_string(), "123.4500"])?;
+        }
+
+        writer.flush()?;
+
+        let options = ProcessingOptions::default();
+
+        let stopwatch = Stopwatch::start_new();
+        process_csv(&csv_path, &options, &mut ProcessingStats::default())?;
+        println!("Serial processing took {} ms", stopwatch.elapsed_ms());
+
+        let stopwatch = Stopwatch::start_new();
+        process_csv_pipelined(&csv_path, &options, &mut ProcessingStats::default())?;
+        println!("Pipelined processing took {} ms", stopwatch.elapsed_ms());
+
+        Ok(())
+    }
+}
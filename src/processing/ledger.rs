@@ -0,0 +1,50 @@
+use crate::domain::{
+    client_account::{ClientAccount, OUTPUT_PRECISION},
+    transaction::TransactionId,
+};
+use anyhow::{Error, Result};
+use csv::Writer;
+use std::fs::File;
+
+/// A CSV with a row for each applied transaction, giving its account's balances straight
+/// after it was applied, so auditors can follow how each balance was reached.
+pub struct Ledger {
+    writer: Writer<File>,
+}
+
+impl Ledger {
+    pub fn create(path: &str) -> Result<Ledger> {
+        let mut writer = Writer::from_path(path).map_err(|err| {
+            Error::msg(format!(
+                "Failed to create ledger file at path {}: {}",
+                path, err
+            ))
+        })?;
+        writer.write_record(["client", "tx", "type", "available", "held", "total"])?;
+        Ok(Ledger { writer })
+    }
+    /// Writes the transaction with the balances of `account`, which it was applied to.
+    /// The client is given separately so a string client identifier can be written.
+    pub fn write(
+        &mut self,
+        client: &str,
+        transaction_id: TransactionId,
+        transaction_type: &str,
+        account: &ClientAccount,
+    ) -> Result<()> {
+        let [_, available, held, total, _] = account.summary_row(OUTPUT_PRECISION);
+        self.writer.write_record([
+            client,
+            &transaction_id.to_string(),
+            transaction_type,
+            &available,
+            &held,
+            &total,
+        ])?;
+        Ok(())
+    }
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
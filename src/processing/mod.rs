@@ -0,0 +1,9 @@
+pub mod applied_ids;
+pub mod csv_processor;
+pub mod ledger;
+pub mod processing_failed;
+pub mod processing_metrics;
+pub mod processing_options;
+pub mod processing_stats;
+pub mod rejected_rows;
+pub mod stop_flag;
@@ -0,0 +1,34 @@
+use anyhow::Error;
+use std::fmt;
+
+/// The error a run fails with when a row can't be processed, carrying how many rows were
+/// processed before it so the failure can be placed relative to progress. It reads as the
+/// underlying error, so callers wanting the count downcast to it.
+#[derive(Debug)]
+pub struct ProcessingFailed {
+    pub rows_processed: usize,
+    pub source: Error,
+}
+
+impl ProcessingFailed {
+    /// Wraps `source` as the error for a failure after `rows_processed` rows.
+    pub fn wrap(rows_processed: usize, source: Error) -> Error {
+        Error::new(ProcessingFailed {
+            rows_processed,
+            source,
+        })
+    }
+}
+
+impl fmt::Display for ProcessingFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.source, f)
+    }
+}
+
+/// Skips straight to the underlying error's cause, since this error already reads as it.
+impl std::error::Error for ProcessingFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.source()
+    }
+}
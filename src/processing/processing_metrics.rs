@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+/// Where time went while processing an input, for profiling.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ProcessingMetrics {
+    /// Time spent reading and splitting CSV rows from the input.
+    pub read_ms: f64,
+    /// Time spent converting rows into transactions.
+    pub parse_ms: f64,
+    /// Time spent applying transactions to accounts.
+    pub apply_ms: f64,
+    /// How many rows were read, not counting the header.
+    pub total_rows: u64,
+}
+
+impl ProcessingMetrics {
+    pub fn record_read(&mut self, elapsed: Duration) {
+        self.read_ms += as_ms(elapsed);
+        self.total_rows += 1;
+    }
+    pub fn record_parse(&mut self, elapsed: Duration) {
+        self.parse_ms += as_ms(elapsed);
+    }
+    pub fn record_apply(&mut self, elapsed: Duration) {
+        self.apply_ms += as_ms(elapsed);
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
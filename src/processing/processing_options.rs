@@ -0,0 +1,59 @@
+use super::stop_flag::StopFlag;
+use crate::{
+    csv::parse_options::ParseOptions,
+    domain::{account_policy::AccountPolicy, client_account::ClientId, transaction::TransactionId},
+};
+use std::collections::HashSet;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProcessingOptions {
+    pub parse: ParseOptions,
+    /// Verify each account's held balance against its disputed deposits after processing.
+    pub check_held_balances: bool,
+    /// Verify the held balances summed across every account against the disputed deposits
+    /// summed across every account after processing.
+    pub reconcile_held_balances: bool,
+    /// Skip rows with an unrecognised type, counting them as rejected, instead of failing.
+    pub skip_unknown_types: bool,
+    /// Skip rows repeating the header, as found in files concatenated from several exports.
+    pub skip_repeated_headers: bool,
+    /// Clients whose transactions are skipped entirely, so they never appear in the output.
+    pub blocked_clients: HashSet<ClientId>,
+    /// Clients to include in the output even without transactions, as zeroed accounts.
+    pub client_roster: HashSet<ClientId>,
+    /// Clients whose accounts start locked, as when continuing from a state in which they
+    /// were already locked, so all their transactions are rejected.
+    pub start_locked: HashSet<ClientId>,
+    /// Apply disputes, resolves and chargebacks to the client that made the referenced
+    /// deposit, even when their row names a different client.
+    pub route_disputes_by_transaction: bool,
+    /// Fail the run as soon as a transaction locks an account, e.g. so a chargeback halts
+    /// processing for manual review. No accounts are returned.
+    pub fail_on_lock: bool,
+    /// The policy every client account is created with.
+    pub account_policy: AccountPolicy,
+    /// A sidecar file of transaction IDs applied by earlier runs. Those transactions are
+    /// skipped, and the file is updated with this run's IDs once processing succeeds.
+    pub applied_ids_path: Option<String>,
+    /// Skip deposits, withdrawals, holds and releases with an ID at or below this, to resume
+    /// reprocessing a file from a known checkpoint.
+    pub since_transaction_id: Option<TransactionId>,
+    /// A CSV of balances to start each client from, e.g. the previous day's output,
+    /// instead of zero.
+    pub opening_balances_path: Option<String>,
+    /// Accept `yes`/`no` and `1`/`0` as well as `true`/`false`, in any case, for the
+    /// opening balances' `locked` column, as written by some sources.
+    pub tolerant_bool_locked: bool,
+    /// The most distinct clients an input may refer to, to bound memory use on hostile input.
+    pub max_clients: Option<usize>,
+    /// Write rejected rows to a CSV at this path and carry on, rather than failing the run.
+    /// Only applies to serial processing.
+    pub errors_path: Option<String>,
+    /// Decompress the input as zstd even if its name doesn't end in `.zst`.
+    pub zstd_input: bool,
+    /// Write a CSV at this path with each applied transaction and its account's balances
+    /// straight after it, for auditing.
+    pub ledger_path: Option<String>,
+    /// Set to stop early, keeping the accounts built from the rows read so far.
+    pub stop: StopFlag,
+}
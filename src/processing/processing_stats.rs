@@ -0,0 +1,91 @@
+use crate::domain::transaction::{Transaction, TransactionAction};
+use std::fmt;
+
+/// Counts of the transactions seen while processing an input, for monitoring.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProcessingStats {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+    pub resets: u64,
+    pub holds: u64,
+    pub releases: u64,
+    pub settles: u64,
+    pub freezes: u64,
+    pub unfreezes: u64,
+    pub rejected: u64,
+    pub blocked: u64,
+    pub skipped: u64,
+    /// Disputes, resolves and chargebacks ignored because they came before their client's
+    /// first deposit.
+    pub before_any_deposit: u64,
+}
+
+impl ProcessingStats {
+    pub fn record_transaction(&mut self, transaction: &Transaction) {
+        match transaction.action {
+            TransactionAction::Deposit(_) => self.deposits += 1,
+            TransactionAction::Withdrawal(_) => self.withdrawals += 1,
+            TransactionAction::Dispute(_) => self.disputes += 1,
+            TransactionAction::Resolve => self.resolves += 1,
+            TransactionAction::Chargeback => self.chargebacks += 1,
+            TransactionAction::Reset => self.resets += 1,
+            TransactionAction::Hold(_) => self.holds += 1,
+            TransactionAction::Release(_) => self.releases += 1,
+            TransactionAction::Settle => self.settles += 1,
+            TransactionAction::Freeze => self.freezes += 1,
+            TransactionAction::Unfreeze => self.unfreezes += 1,
+        }
+    }
+    pub fn record_rejection(&mut self) {
+        self.rejected += 1;
+    }
+    pub fn record_blocked(&mut self) {
+        self.blocked += 1;
+    }
+    pub fn record_skipped(&mut self) {
+        self.skipped += 1;
+    }
+    pub fn record_before_any_deposit(&mut self) {
+        self.before_any_deposit += 1;
+    }
+    pub fn merge(&mut self, other: ProcessingStats) {
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.disputes += other.disputes;
+        self.resolves += other.resolves;
+        self.chargebacks += other.chargebacks;
+        self.resets += other.resets;
+        self.holds += other.holds;
+        self.releases += other.releases;
+        self.settles += other.settles;
+        self.freezes += other.freezes;
+        self.unfreezes += other.unfreezes;
+        self.rejected += other.rejected;
+        self.blocked += other.blocked;
+        self.skipped += other.skipped;
+        self.before_any_deposit += other.before_any_deposit;
+    }
+}
+
+impl fmt::Display for ProcessingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "deposits: {}", self.deposits)?;
+        writeln!(f, "withdrawals: {}", self.withdrawals)?;
+        writeln!(f, "disputes: {}", self.disputes)?;
+        writeln!(f, "resolves: {}", self.resolves)?;
+        writeln!(f, "chargebacks: {}", self.chargebacks)?;
+        writeln!(f, "resets: {}", self.resets)?;
+        writeln!(f, "holds: {}", self.holds)?;
+        writeln!(f, "releases: {}", self.releases)?;
+        writeln!(f, "settles: {}", self.settles)?;
+        writeln!(f, "freezes: {}", self.freezes)?;
+        writeln!(f, "unfreezes: {}", self.unfreezes)?;
+        writeln!(f, "rejected: {}", self.rejected)?;
+        writeln!(f, "blocked: {}", self.blocked)?;
+        writeln!(f, "skipped: {}", self.skipped)?;
+        writeln!(f, "before any deposit: {}", self.before_any_deposit)
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::{Error, Result};
+use csv::Writer;
+use std::fs::File;
+
+/// A CSV of the rows rejected during processing, with the line, transaction ID and error
+/// of each, so bad data can be corrected separately from the output.
+pub struct RejectedRows {
+    writer: Writer<File>,
+}
+
+impl RejectedRows {
+    pub fn create(path: &str) -> Result<RejectedRows> {
+        let mut writer = Writer::from_path(path).map_err(|err| {
+            Error::msg(format!(
+                "Failed to create errors file at path {}: {}",
+                path, err
+            ))
+        })?;
+        writer.write_record(["line", "transaction_id", "error"])?;
+        Ok(RejectedRows { writer })
+    }
+    pub fn write(&mut self, line: Option<u64>, transaction_id: &str, error: &Error) -> Result<()> {
+        let line = line.map(|line| line.to_string()).unwrap_or_default();
+        self.writer
+            .write_record([line.as_str(), transaction_id, &error.to_string()])?;
+        Ok(())
+    }
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Asks a run to stop early, e.g. from a Ctrl-C handler. Processing checks it between
+/// rows and, once set, finishes with the accounts built so far. Clones share the flag.
+#[derive(Debug, Default, Clone)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    pub fn request_stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_stop_requested(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Flags are equal when they're clones of each other, so options holding one can
+/// still be compared.
+impl PartialEq for StopFlag {
+    fn eq(&self, other: &StopFlag) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for StopFlag {}
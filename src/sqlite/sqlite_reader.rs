@@ -0,0 +1,138 @@
+use crate::{
+    domain::client_account::ClientAccount,
+    processing::{
+        csv_processor::process_records, processing_options::ProcessingOptions,
+        processing_stats::ProcessingStats,
+    },
+};
+use anyhow::{Error, Result};
+use csv::StringRecord;
+use rusqlite::{types::ValueRef, Connection, Row};
+use std::{io, iter};
+
+/// Runs `query` and processes each row as it's read, like a CSV row passed to
+/// [`process_records`], so the table is never held in memory. The query should select the
+/// type, client, transaction ID and amount, in that order, e.g.
+/// `SELECT type, client, tx, amount FROM transactions ORDER BY tx`.
+pub fn process_sqlite(
+    connection: &Connection,
+    query: &str,
+    options: &ProcessingOptions,
+    stats: &mut ProcessingStats,
+) -> Result<Vec<ClientAccount>> {
+    let mut statement = connection
+        .prepare(query)
+        .map_err(|err| Error::msg(format!("Failed to prepare SQLite query: {}", err)))?;
+    if statement.column_count() != 4 {
+        return Err(Error::msg(format!(
+            "SQLite query must select 4 columns but selects {}",
+            statement.column_count()
+        )));
+    }
+
+    let mut rows = statement
+        .query([])
+        .map_err(|err| Error::msg(format!("Failed to run SQLite query: {}", err)))?;
+
+    // A row that can't be read becomes a failed record, as an unreadable CSV line would
+    let records = iter::from_fn(|| match rows.next() {
+        Ok(Some(row)) => Some(row_to_record(row).map_err(read_failure)),
+        Ok(None) => None,
+        Err(err) => Some(Err(read_failure(Error::msg(format!(
+            "Failed to read SQLite row: {}",
+            err
+        ))))),
+    });
+
+    process_records(records, options, stats)
+}
+
+/// Renders the row as a record of the same four fields as a CSV row.
+fn row_to_record(row: &Row) -> Result<StringRecord> {
+    let mut record = StringRecord::new();
+    for column in 0..4 {
+        let value = row
+            .get_ref(column)
+            .map_err(|err| Error::msg(format!("Failed to read SQLite column: {}", err)))?;
+        record.push_field(&value_to_field(value)?);
+    }
+    Ok(record)
+}
+
+fn read_failure(err: Error) -> csv::Error {
+    csv::Error::from(io::Error::other(err.to_string()))
+}
+
+/// Renders a column as the text it would have in a CSV, with `NULL` as an empty field.
+fn value_to_field(value: ValueRef) -> Result<String> {
+    match value {
+        ValueRef::Null => Ok(String::new()),
+        ValueRef::Integer(integer) => Ok(integer.to_string()),
+        ValueRef::Real(real) => Ok(real.to_string()),
+        ValueRef::Text(text) => String::from_utf8(text.to_vec())
+            .map_err(|err| Error::msg(format!("Invalid text in SQLite column: {}", err))),
+        ValueRef::Blob(_) => Err(Error::msg("Unexpected blob in SQLite column")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::process_sqlite;
+    use crate::processing::{
+        processing_options::ProcessingOptions, processing_stats::ProcessingStats,
+    };
+    use anyhow::Result;
+    use rusqlite::Connection;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn processes_transactions_from_a_sqlite_table() -> Result<()> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(
+            "CREATE TABLE transactions (type TEXT, client INTEGER, tx INTEGER, amount TEXT);
+             INSERT INTO transactions VALUES
+                 ('deposit', 1, 1, '10.0'),
+                 ('deposit', 2, 2, '5.5'),
+                 ('withdrawal', 1, 3, '2.5'),
+                 ('dispute', 2, 2, NULL);",
+        )?;
+
+        let mut client_accounts = process_sqlite(
+            &connection,
+            "SELECT type, client, tx, amount FROM transactions ORDER BY tx",
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        )?;
+        client_accounts.sort_by_key(|account| account.client_id);
+
+        assert_eq!(dec!(7.5), client_accounts[0].available_balance);
+        assert_eq!(dec!(7.5), client_accounts[0].total_balance);
+        assert_eq!(dec!(0), client_accounts[1].available_balance);
+        assert_eq!(dec!(5.5), client_accounts[1].held_balance);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_a_row_that_cant_be_read_as_a_csv_row() -> Result<()> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(
+            "CREATE TABLE transactions (type TEXT, client INTEGER, tx INTEGER, amount BLOB);
+             INSERT INTO transactions VALUES
+                 ('deposit', 1, 1, '10.0'),
+                 ('deposit', 1, 2, x'00');",
+        )?;
+
+        let result = process_sqlite(
+            &connection,
+            "SELECT type, client, tx, amount FROM transactions ORDER BY tx",
+            &ProcessingOptions::default(),
+            &mut ProcessingStats::default(),
+        );
+
+        let err = result
+            .err()
+            .ok_or_else(|| anyhow::Error::msg("Expected the blob to fail the run"))?;
+        assert!(err.to_string().contains("Unexpected blob in SQLite column"));
+        Ok(())
+    }
+}
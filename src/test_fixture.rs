@@ -0,0 +1,68 @@
+use anyhow::Result;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::{env, fs, process, sync::Mutex};
+
+/// Writes `contents` to a uniquely named file in the temp directory and returns its path.
+pub fn write_fixture(name: &str, contents: &str) -> Result<String> {
+    let path = env::temp_dir().join(format!("payments-engine-{}-{}.csv", process::id(), name));
+    fs::write(&path, contents)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Writes `contents` compressed with zstd to a uniquely named `.csv.zst` file in the temp
+/// directory and returns its path.
+pub fn write_zstd_fixture(name: &str, contents: &str) -> Result<String> {
+    let path = env::temp_dir().join(format!(
+        "payments-engine-{}-{}.csv.zst",
+        process::id(),
+        name
+    ));
+    fs::write(&path, zstd::encode_all(contents.as_bytes(), 0)?)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Creates a uniquely named directory in the temp directory holding each of `files`,
+/// given as a file name and its contents, and returns its path.
+pub fn write_fixture_dir(name: &str, files: &[(&str, &str)]) -> Result<String> {
+    let path = env::temp_dir().join(format!("payments-engine-{}-{}", process::id(), name));
+    fs::create_dir_all(&path)?;
+    for (file_name, contents) in files {
+        fs::write(path.join(file_name), contents)?;
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+static LOGGED_MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Records every log message so tests can check what was logged.
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &Record) {
+        if let Ok(mut messages) = LOGGED_MESSAGES.lock() {
+            messages.push(record.args().to_string());
+        }
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+
+/// Starts recording log messages. Tests run in parallel and share the logger, so check for
+/// a specific message with [`logged_messages`] rather than counting them.
+pub fn capture_logs() {
+    // Fails harmlessly when an earlier test has already installed the logger
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(LevelFilter::Warn);
+}
+
+/// Every message logged since [`capture_logs`] was first called, by any test.
+pub fn logged_messages() -> Vec<String> {
+    LOGGED_MESSAGES
+        .lock()
+        .map(|messages| messages.clone())
+        .unwrap_or_default()
+}
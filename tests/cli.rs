@@ -0,0 +1,26 @@
+use std::{env, fs, process::Command};
+
+#[test]
+fn writes_nothing_to_stderr_when_quiet() {
+    let csv_path = env::temp_dir().join(format!(
+        "payments-engine-cli-{}-quiet.csv",
+        std::process::id()
+    ));
+    fs::write(
+        &csv_path,
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         bogus,1,2,1.0\n\
+         withdrawal,1,3,100.0\n",
+    )
+    .expect("fixture should be written");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_payments-engine"))
+        .args(["--quiet", "--stats", "--skip-unknown"])
+        .arg(&csv_path)
+        .output()
+        .expect("binary should run");
+
+    assert!(!output.status.success());
+    assert_eq!("", String::from_utf8_lossy(&output.stderr));
+}